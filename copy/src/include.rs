@@ -11,6 +11,7 @@ use librad::{
         include::{self, Include},
         local::url::LocalUrl,
         storage::Storage,
+        tracking,
     },
     git_ext,
     identities::relations,
@@ -25,6 +26,9 @@ pub enum Error {
     #[error(transparent)]
     Include(#[from] include::Error),
 
+    #[error(transparent)]
+    Tracking(#[from] tracking::Error),
+
     #[error(transparent)]
     Ref(#[from] git_ext::name::Error),
 
@@ -35,12 +39,15 @@ pub enum Error {
 /// Update the include file for the given `project`.
 ///
 /// It looks at the tracked peers of the `project` and creates an entry for each
-/// one in an include file. The file can be located by using
+/// one in an include file. Remotes are named after the nickname of the peer
+/// (see [`tracking::set_nickname`]) if one is set, and the name of the person
+/// otherwise. The file can be located by using
 /// [`Paths::git_includes_dir`], and the name of the file will be the `Urn`.
 pub fn update(storage: &Storage, paths: &Paths, project: &Project) -> Result<PathBuf, Error> {
     let urn = project.urn();
     let url = LocalUrl::from(urn.clone());
     let tracked = identities::relations::tracked(storage, &urn)?;
+    let mut metadata = tracking::tracked(storage, &urn)?;
     let include = Include::from_tracked_persons(
         paths.git_includes_dir().to_path_buf(),
         url,
@@ -48,7 +55,11 @@ pub fn update(storage: &Storage, paths: &Paths, project: &Project) -> Result<Pat
             .into_iter()
             .filter_map(|peer| {
                 relations::Peer::replicated_remote(peer).map(|(p, u)| {
-                    git_ext::RefLike::try_from(u.subject().name.to_string()).map(|r| (r, p))
+                    let handle = metadata
+                        .remove(&p)
+                        .and_then(|meta| meta.nickname)
+                        .unwrap_or_else(|| u.subject().name.to_string());
+                    git_ext::RefLike::try_from(handle).map(|r| (r, p))
                 })
             })
             .collect::<Result<Vec<_>, _>>()?,
@@ -175,6 +175,20 @@ impl From<&RefLike> for RefLike {
     }
 }
 
+/// The hex representation of an [`crate::Oid`] is always a valid, single
+/// component ref name.
+impl From<&crate::Oid> for RefLike {
+    fn from(oid: &crate::Oid) -> Self {
+        Self(oid.to_string())
+    }
+}
+
+impl From<crate::Oid> for RefLike {
+    fn from(oid: crate::Oid) -> Self {
+        Self::from(&oid)
+    }
+}
+
 impl From<RefLike> for String {
     fn from(RefLike(path): RefLike) -> Self {
         path
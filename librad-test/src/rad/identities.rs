@@ -62,7 +62,7 @@ impl TestProject {
         let remote_peer = from.local_peer_id();
         let remote_addrs = from.listen_addrs()?;
         let urn = self.project.urn();
-        let cfg = to.protocol_config().replication;
+        let cfg = to.protocol_config().replication.clone();
         let res = to
             .using_storage(move |storage| {
                 let fetcher = fetcher::PeerToPeer::new(urn, remote_peer, remote_addrs)
@@ -3,7 +3,10 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
+pub mod blocklist;
+pub mod bundle;
 pub mod fetch;
+pub mod hooks;
 pub mod identities;
 pub mod include;
 pub mod local;
@@ -0,0 +1,142 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Peers we refuse to interact with.
+//!
+//! Blocked peers are:
+//!
+//! * refused as the remote peer of [`super::replication::replicate`], and their
+//!   refs are not replicated from other peers
+//! * rejected by [`super::tracking::track`]
+//! * ignored by the gossip protocol, and refused when connecting to us
+//!
+//! The blocklist is stored in the storage's git config as
+//! `blocklist.<peer>.reason`.
+
+use std::{collections::BTreeMap, convert::TryFrom};
+
+use git_ext::is_not_found_err;
+use thiserror::Error;
+
+use super::storage::{self, ReadOnly, Storage};
+use crate::peer::PeerId;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("can't block oneself")]
+    SelfReferential,
+
+    #[error("invalid blocklist entry `{0}`")]
+    Entry(String),
+
+    #[error(transparent)]
+    Config(#[from] storage::config::Error),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+/// Block `peer`, recording `reason`.
+///
+/// Blocking does not untrack `peer`, nor prune its refs.
+///
+/// `true` is returned if `peer` wasn't blocked before. Otherwise, `false` is
+/// returned and the recorded reason is replaced.
+#[tracing::instrument(skip(storage), err)]
+pub fn block(storage: &Storage, peer: PeerId, reason: &str) -> Result<bool, Error> {
+    if storage.peer_id() == &peer {
+        return Err(Error::SelfReferential);
+    }
+    let was_blocked = is_blocked(storage, peer)?;
+
+    let mut config = storage::Config::try_from(storage)?;
+    config.as_raw_mut().set_str(&config_key(peer), reason)?;
+
+    Ok(!was_blocked)
+}
+
+/// Remove `peer` from the blocklist.
+///
+/// `true` is returned if `peer` was blocked before. Otherwise, `false` is
+/// returned.
+#[tracing::instrument(skip(storage), err)]
+pub fn unblock(storage: &Storage, peer: PeerId) -> Result<bool, Error> {
+    let mut config = storage::Config::try_from(storage)?;
+    match config.as_raw_mut().remove(&config_key(peer)) {
+        Ok(()) => Ok(true),
+        Err(e) if is_not_found_err(&e) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Determine if `peer` is blocked.
+#[tracing::instrument(level = "trace", skip(storage), err)]
+pub fn is_blocked(storage: &ReadOnly, peer: PeerId) -> Result<bool, Error> {
+    let config = storage.as_raw().config()?;
+    match config.get_string(&config_key(peer)) {
+        Ok(_) => Ok(true),
+        Err(e) if is_not_found_err(&e) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// All blocked peers, along with the reason they were blocked for.
+pub fn blocked(storage: &ReadOnly) -> Result<BTreeMap<PeerId, String>, Error> {
+    let config = storage.as_raw().config()?;
+    let mut blocked = BTreeMap::new();
+    for entry in &config.entries(Some(r"^blocklist\..+\.reason$"))? {
+        let entry = entry?;
+        let name = entry.name().unwrap_or_default();
+        let peer = name
+            .strip_prefix("blocklist.")
+            .and_then(|name| name.strip_suffix(".reason"))
+            .and_then(|peer| peer.parse().ok())
+            .ok_or_else(|| Error::Entry(name.to_owned()))?;
+        blocked.insert(peer, entry.value().unwrap_or_default().to_owned());
+    }
+
+    Ok(blocked)
+}
+
+fn config_key(peer: PeerId) -> String {
+    format!("blocklist.{}.reason", peer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{keys::SecretKey, paths::Paths};
+
+    #[test]
+    fn block_unblock() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(&tmp).unwrap();
+            let storage = Storage::open(&paths, SecretKey::new()).unwrap();
+            let remote_peer = PeerId::from(SecretKey::new());
+
+            assert!(matches!(
+                block(&storage, *storage.peer_id(), "me"),
+                Err(Error::SelfReferential)
+            ));
+            assert!(!is_blocked(&storage, remote_peer).unwrap());
+            assert!(block(&storage, remote_peer, "spam").unwrap());
+            assert!(!block(&storage, remote_peer, "more spam").unwrap());
+            assert!(is_blocked(&storage, remote_peer).unwrap());
+            assert_eq!(
+                blocked(&storage).unwrap(),
+                Some((remote_peer, "more spam".to_owned()))
+                    .into_iter()
+                    .collect()
+            );
+
+            assert!(unblock(&storage, remote_peer).unwrap());
+            assert!(!unblock(&storage, remote_peer).unwrap());
+            assert!(blocked(&storage).unwrap().is_empty());
+        }
+    }
+}
@@ -0,0 +1,308 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Offline replication of a [`Urn`] via `git bundle` files.
+//!
+//! A bundle contains the namespace of the exported [`Urn`] as seen by the
+//! exporting peer -- that is, the identity history, the `rad/signed_refs` of
+//! the exporter and of every peer tracked by it, as well as all heads, tags
+//! and notes. The namespaces of the identity's delegates are included, too.
+//!
+//! Importing a bundle goes through [`replication::replicate`], so the same
+//! identity verification and signed refs checks apply as for replicating over
+//! the network.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+};
+
+use git_ext as ext;
+use thiserror::Error;
+
+use super::{
+    fetch::{self, FetchResult, Fetchspecs, RemoteHeads},
+    identities::local::LocalIdentity,
+    replication::{self, ReplicateResult},
+    storage::{self, glob, staging::Staging, transaction, Storage},
+    Urn,
+};
+use crate::{identities::git::Revision, peer::PeerId};
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("{0} not found in storage")]
+    NoSuchUrn(Urn),
+
+    #[error("`git {cmd}` exited unsuccessfully: {status}")]
+    Child {
+        cmd: &'static str,
+        status: ExitStatus,
+    },
+
+    #[error("malformed bundle header line: {0}")]
+    Header(String),
+
+    #[error(transparent)]
+    Replication(#[from] replication::Error),
+
+    #[error(transparent)]
+    Store(#[from] storage::Error),
+
+    #[error(transparent)]
+    Transaction(#[from] transaction::Error),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Write the namespace of `urn`, and the namespaces of its delegates, to a
+/// bundle file at `path`.
+///
+/// The refs contained in the bundle are returned.
+#[tracing::instrument(skip(storage), err)]
+pub fn export<P>(
+    storage: &Storage,
+    urn: &Urn,
+    path: P,
+) -> Result<BTreeMap<ext::RefLike, ext::Oid>, Error>
+where
+    P: AsRef<Path> + std::fmt::Debug,
+{
+    if !storage.has_urn(urn)? {
+        return Err(Error::NoSuchUrn(urn.clone()));
+    }
+
+    let mut urns = delegates(storage, urn)?;
+    urns.insert(urn.clone().with_path(None));
+
+    let mut refs = BTreeMap::new();
+    for urn in urns {
+        let namespace = glob::RefspecMatcher::from(
+            reflike!("refs/namespaces")
+                .join(&urn)
+                .with_pattern_suffix(refspec_pattern!("*")),
+        );
+        for reference in storage.references_glob(namespace)? {
+            let reference = reference?;
+            // Symbolic refs (`rad/ids/*`) are recreated by the receiving end
+            let name = match reference.name().map(ext::RefLike::try_from) {
+                Some(Ok(name)) => name,
+                _ => continue,
+            };
+            if let Some(target) = reference.target() {
+                refs.insert(name, target.into());
+            }
+        }
+    }
+
+    // `git` runs in the storage directory
+    let path = std::env::current_dir()?.join(path);
+    // Namespaces can have arbitrarily many refs, so pass them via stdin rather
+    // than risk exceeding the maximum size of the argument list
+    let mut child = git()
+        .current_dir(storage.path())
+        .args(&["bundle", "create", "--quiet"])
+        .arg(&path)
+        .arg("--stdin")
+        .stdin(Stdio::piped())
+        .spawn()?;
+    let written = {
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        refs.keys().try_for_each(|name| writeln!(stdin, "{}", name))
+    };
+    // Reap the child even if writing failed, its exit status is the more
+    // useful error
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(Error::Child {
+            cmd: "bundle create",
+            status,
+        });
+    }
+    written?;
+
+    Ok(refs)
+}
+
+/// Replicate `urn` from the bundle file at `path`.
+///
+/// `remote_peer` is the peer which exported the bundle: the refs at the top
+/// level of the namespace are considered to be owned by it, just as if we were
+/// fetching from it over the network.
+#[tracing::instrument(skip(storage, whoami), err)]
+pub fn import<P>(
+    storage: &Storage,
+    urn: Urn,
+    remote_peer: PeerId,
+    path: P,
+    config: replication::Config,
+    whoami: Option<LocalIdentity>,
+) -> Result<ReplicateResult, Error>
+where
+    P: AsRef<Path> + std::fmt::Debug,
+{
+    let fetcher = Fetcher::new(storage, urn, remote_peer, path.as_ref())?;
+    Ok(replication::replicate(storage, fetcher, config, whoami)?)
+}
+
+/// A [`fetch::Fetcher`] which fetches from a bundle file instead of a remote
+/// peer.
+///
+/// Note that the [`fetch::Limit`]s are not enforced, as the data is already
+/// available locally.
+pub struct Fetcher<'a> {
+    storage: &'a Storage,
+    bundle: PathBuf,
+    urn: Urn,
+    remote_peer: PeerId,
+    remote_heads: RemoteHeads,
+}
+
+impl<'a> Fetcher<'a> {
+    pub fn new(
+        storage: &'a Storage,
+        urn: Urn,
+        remote_peer: PeerId,
+        bundle: &Path,
+    ) -> Result<Self, Error> {
+        // `git` runs in the storage directory
+        let bundle = std::env::current_dir()?.join(bundle);
+        let remote_heads = list_heads(storage, &bundle)?;
+        Ok(Self {
+            storage,
+            bundle,
+            urn,
+            remote_peer,
+            remote_heads,
+        })
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    fn fetch(&mut self, fetchspecs: Fetchspecs<PeerId, Revision>) -> Result<FetchResult, Error> {
+        let staging = Staging::new();
+        let refspecs =
+            staging.refspecs(fetchspecs.refspecs(&self.urn, self.remote_peer, &self.remote_heads));
+        tracing::trace!("{:?}", refspecs);
+
+        let status = git()
+            .current_dir(self.storage.path())
+            .args(&["fetch", "--quiet", "--no-tags", "--no-prune"])
+            .arg(&self.bundle)
+            .args(&refspecs)
+            .status();
+        match status {
+            Ok(status) if status.success() => Ok(FetchResult {
+                updated_tips: staging.apply(self.storage)?,
+            }),
+            status => {
+                if let Err(e) = staging.discard(self.storage) {
+                    tracing::warn!(err = %e, "failed to discard staged refs");
+                }
+                Err(match status {
+                    Ok(status) => Error::Child {
+                        cmd: "fetch",
+                        status,
+                    },
+                    Err(e) => e.into(),
+                })
+            },
+        }
+    }
+}
+
+impl fetch::Fetcher for Fetcher<'_> {
+    type Error = Error;
+    type PeerId = PeerId;
+    type UrnId = Revision;
+
+    fn urn(&self) -> &Urn {
+        &self.urn
+    }
+
+    fn remote_peer(&self) -> &PeerId {
+        &self.remote_peer
+    }
+
+    fn remote_heads(&self) -> &RemoteHeads {
+        &self.remote_heads
+    }
+
+    fn fetch(
+        &mut self,
+        fetchspecs: Fetchspecs<Self::PeerId, Self::UrnId>,
+    ) -> Result<FetchResult, Self::Error> {
+        self.fetch(fetchspecs)
+    }
+}
+
+fn git() -> Command {
+    let mut git = Command::new("git");
+    git.envs(::std::env::vars().filter(|(key, _)| key.starts_with("GIT_TRACE")))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::inherit());
+    git
+}
+
+fn list_heads(storage: &Storage, bundle: &Path) -> Result<RemoteHeads, Error> {
+    let out = git()
+        .current_dir(storage.path())
+        .args(&["bundle", "list-heads"])
+        .arg(bundle)
+        .stdout(Stdio::piped())
+        .output()?;
+    if !out.status.success() {
+        return Err(Error::Child {
+            cmd: "bundle list-heads",
+            status: out.status,
+        });
+    }
+
+    String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .map(|line| {
+            let mut parts = line.splitn(2, ' ');
+            let oid = parts
+                .next()
+                .and_then(|oid| git2::Oid::from_str(oid).ok())
+                .ok_or_else(|| Error::Header(line.to_owned()))?;
+            let name = parts
+                .next()
+                .and_then(|name| ext::RefLike::try_from(name).ok())
+                .ok_or_else(|| Error::Header(line.to_owned()))?;
+            Ok((name, oid.into()))
+        })
+        .collect()
+}
+
+/// The `rad/ids/*` of `urn`, as seen by us and all tracked peers.
+fn delegates(storage: &Storage, urn: &Urn) -> Result<BTreeSet<Urn>, Error> {
+    let ids = glob::rad_ids(Some(urn));
+
+    let mut delegates = BTreeSet::new();
+    for name in storage.reference_names_glob(ids)? {
+        let name = name?;
+        let id = name.as_str().rsplit('/').next().unwrap_or_default();
+        match ext::RefLike::try_from(format!("refs/namespaces/{}", id))
+            .ok()
+            .and_then(|refl| Urn::try_from(refl).ok())
+        {
+            Some(delegate) => {
+                delegates.insert(delegate);
+            },
+            None => tracing::warn!(name = %name, "invalid delegate ref"),
+        }
+    }
+
+    Ok(delegates)
+}
@@ -9,6 +9,9 @@ use git_ext as ext;
 
 use crate::identities::Urn;
 
+mod filter;
+pub use filter::{Filter, Globs};
+
 mod specs;
 pub use specs::Fetchspecs;
 
@@ -73,6 +76,17 @@ pub struct FetchResult {
     pub updated_tips: BTreeMap<ext::RefLike, ext::Oid>,
 }
 
+/// Transfer progress of a single [`Fetcher::fetch`], as reported by the
+/// transport.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Transfer {
+    /// Number of objects the remote end is going to send, if known.
+    pub total_objects: usize,
+    pub received_objects: usize,
+    pub indexed_objects: usize,
+    pub received_bytes: usize,
+}
+
 /// Types which can process [`Fetchspecs`], and update the local storage
 /// accordingly.
 pub trait Fetcher {
@@ -94,4 +108,17 @@ pub trait Fetcher {
         &mut self,
         fetchspecs: Fetchspecs<Self::PeerId, Self::UrnId>,
     ) -> Result<FetchResult, Self::Error>;
+
+    /// Fetch the given [`Fetchspecs`], reporting the [`Transfer`] progress to
+    /// `progress` as it is made.
+    ///
+    /// The default implementation does not report any progress.
+    fn fetch_with_progress(
+        &mut self,
+        fetchspecs: Fetchspecs<Self::PeerId, Self::UrnId>,
+        progress: &mut dyn FnMut(Transfer),
+    ) -> Result<FetchResult, Self::Error> {
+        let _ = progress;
+        self.fetch(fetchspecs)
+    }
 }
@@ -0,0 +1,160 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{
+    collections::{btree_set, BTreeMap, BTreeSet},
+    iter::FromIterator,
+};
+
+use git_ext as ext;
+
+use crate::git::storage::glob::{Pattern as _, RefspecMatcher};
+
+/// Restricts the refs requested by [`super::Fetchspecs::Replicate`].
+///
+/// Globs are matched against the ref names as published in the
+/// `rad/signed_refs` of a peer, eg. `refs/heads/main` or `refs/tags/*`. The
+/// `refs/rad/*` refs are always requested, as they are needed for identity
+/// verification.
+///
+/// The [`Default`] filter requests everything.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Filter<P> {
+    /// The globs for all peers not mentioned in [`Self::peers`]. `None` means
+    /// all refs.
+    pub default: Option<Globs>,
+    /// The globs for specific peers, taking precedence over
+    /// [`Self::default`].
+    pub peers: BTreeMap<P, Globs>,
+}
+
+impl<P> Default for Filter<P> {
+    fn default() -> Self {
+        Self {
+            default: None,
+            peers: BTreeMap::new(),
+        }
+    }
+}
+
+impl<P: Ord> Filter<P> {
+    /// Request only the refs matching `globs`, regardless of the peer.
+    pub fn only<I>(globs: I) -> Self
+    where
+        I: IntoIterator<Item = ext::RefspecPattern>,
+    {
+        Self {
+            default: Some(globs.into_iter().collect()),
+            peers: BTreeMap::new(),
+        }
+    }
+
+    /// `true` if this filter requests everything.
+    pub fn is_everything(&self) -> bool {
+        self.default.is_none() && self.peers.is_empty()
+    }
+
+    /// Determine if the ref `name` published by `peer` should be requested.
+    pub fn allows(&self, peer: &P, name: &ext::RefLike) -> bool {
+        if name.as_str().starts_with("refs/rad/") {
+            return true;
+        }
+
+        match self.peers.get(peer).or_else(|| self.default.as_ref()) {
+            None => true,
+            Some(globs) => globs.matches(name),
+        }
+    }
+}
+
+/// A set of globs, along with their compiled matchers.
+///
+/// The matchers are compiled once when the set is built, so a [`Filter`] can
+/// be applied to many refs cheaply.
+#[derive(Clone, Debug, Default)]
+pub struct Globs {
+    patterns: BTreeSet<ext::RefspecPattern>,
+    matchers: Vec<RefspecMatcher>,
+}
+
+impl Globs {
+    /// Iterate over the globs, in order.
+    pub fn iter(&self) -> btree_set::Iter<'_, ext::RefspecPattern> {
+        self.patterns.iter()
+    }
+
+    /// `true` if any of the globs matches `name`.
+    pub fn matches(&self, name: &ext::RefLike) -> bool {
+        self.matchers
+            .iter()
+            .any(|matcher| matcher.matches(name.as_str()))
+    }
+}
+
+impl PartialEq for Globs {
+    fn eq(&self, other: &Self) -> bool {
+        self.patterns == other.patterns
+    }
+}
+
+impl Eq for Globs {}
+
+impl From<BTreeSet<ext::RefspecPattern>> for Globs {
+    fn from(patterns: BTreeSet<ext::RefspecPattern>) -> Self {
+        let matchers = patterns.iter().cloned().map(RefspecMatcher::from).collect();
+        Self { patterns, matchers }
+    }
+}
+
+impl FromIterator<ext::RefspecPattern> for Globs {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = ext::RefspecPattern>,
+    {
+        Self::from(iter.into_iter().collect::<BTreeSet<_>>())
+    }
+}
+
+impl<'a> IntoIterator for &'a Globs {
+    type Item = &'a ext::RefspecPattern;
+    type IntoIter = btree_set::Iter<'a, ext::RefspecPattern>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_allows_everything() {
+        let filter = Filter::<u8>::default();
+        assert!(filter.is_everything());
+        assert!(filter.allows(&0, &reflike!("refs/heads/next")));
+        assert!(filter.allows(&0, &reflike!("refs/notes/commits")));
+    }
+
+    #[test]
+    fn peer_overrides_default() {
+        let mut filter = Filter::only(vec![
+            refspec_pattern!("refs/heads/main"),
+            refspec_pattern!("refs/tags/*"),
+        ]);
+        filter.peers.insert(
+            1,
+            Some(refspec_pattern!("refs/heads/*")).into_iter().collect(),
+        );
+
+        assert!(filter.allows(&0, &reflike!("refs/heads/main")));
+        assert!(filter.allows(&0, &reflike!("refs/tags/v1.0")));
+        assert!(filter.allows(&0, &reflike!("refs/rad/id")));
+        assert!(!filter.allows(&0, &reflike!("refs/heads/next")));
+
+        assert!(filter.allows(&1, &reflike!("refs/heads/next")));
+        assert!(!filter.allows(&1, &reflike!("refs/tags/v1.0")));
+    }
+}
@@ -8,7 +8,7 @@ use std::collections::{BTreeMap, BTreeSet};
 use git_ext as ext;
 use multihash::Multihash;
 
-use super::{Limit, RemoteHeads};
+use super::{Filter, Limit, RemoteHeads};
 use crate::{
     git::{
         refs::Refs,
@@ -38,9 +38,12 @@ pub enum Fetchspecs<P, R> {
     /// Request the remote heads matching the signed refs of the respective
     /// tracked peers, as well as top-level delegates found in the identity
     /// document.
+    ///
+    /// Only the heads allowed by the [`Filter`] are requested.
     Replicate {
         tracked_sigrefs: BTreeMap<P, Refs>,
         delegates: BTreeSet<Urn<R>>,
+        filter: Filter<P>,
         limit: Limit,
     },
 }
@@ -71,8 +74,16 @@ where
             Self::Replicate {
                 tracked_sigrefs,
                 delegates,
+                filter,
                 ..
-            } => refspecs::replicate(urn, &remote_peer, remote_heads, tracked_sigrefs, delegates),
+            } => refspecs::replicate(
+                urn,
+                &remote_peer,
+                remote_heads,
+                tracked_sigrefs,
+                delegates,
+                filter,
+            ),
         }
     }
 
@@ -217,6 +228,7 @@ pub mod refspecs {
         remote_heads: &RemoteHeads,
         tracked_sigrefs: &BTreeMap<P, Refs>,
         delegates: &BTreeSet<Urn<R>>,
+        filter: &Filter<P>,
     ) -> Vec<Fetchspec>
     where
         P: Clone + Ord + PartialEq + 'static,
@@ -235,6 +247,7 @@ pub mod refspecs {
                     remote_heads,
                     tracked_peer,
                     refs,
+                    filter,
                 )
             })
             .collect::<Vec<_>>();
@@ -303,15 +316,24 @@ pub mod refspecs {
         remote_heads: &'a RemoteHeads,
         tracked_peer: &'a P,
         refs: &'a Refs,
+        filter: &'a Filter<P>,
     ) -> impl Iterator<Item = Fetchspec> + 'a
     where
-        P: Clone + PartialEq,
+        P: Clone + Ord,
         for<'b> &'b P: AsRemote + Into<ext::RefLike>,
 
         R: HasProtocol + Clone + 'a,
         for<'b> &'b R: Into<Multihash>,
     {
         refs.iter_categorised()
+            .filter(move |((name, _), category)| {
+                let qualified = ext::OneLevel::clone(name).into_qualified((*category).into());
+                let allowed = filter.allows(tracked_peer, &qualified.into());
+                if !allowed {
+                    tracing::trace!("{} not allowed by filter", name);
+                }
+                allowed
+            })
             .filter_map(move |((name, target), category)| {
                 let namespaced_name = namespaced(
                     &namespace,
@@ -497,6 +519,7 @@ mod tests {
                     tags: Default::default(),
                     notes: Default::default(),
                     remotes: Remotes::new(),
+                    seq: 0,
                 },
             ),
             (
@@ -513,6 +536,7 @@ mod tests {
                     tags: Default::default(),
                     notes: Default::default(),
                     remotes: Remotes::new(),
+                    seq: 0,
                 },
             ),
         ]
@@ -587,6 +611,7 @@ mod tests {
         let specs = Fetchspecs::Replicate {
             tracked_sigrefs,
             delegates,
+            filter: Default::default(),
             limit: Default::default(),
         }
         .refspecs(&*PROJECT_URN, TOLA.clone(), &remote_heads);
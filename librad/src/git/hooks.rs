@@ -0,0 +1,412 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Hooks run after refs have changed.
+//!
+//! [`Hooks`] are run after [`super::replication::replicate`], and after a push
+//! to the storage via the [`super::local::transport::LocalTransport`]. Two
+//! kinds of hooks are supported:
+//!
+//! * In-process callbacks, see [`Hooks::register`]
+//! * Executables in [`Hooks::dir`], named after the [`Event`] (see
+//!   [`Event::name`])
+//!
+//! Executables receive the [`Event`] in their environment (`RAD_HOOK`,
+//! `RAD_URN`, and for replication `RAD_REMOTE_PEER`, `RAD_MODE` and
+//! `RAD_IDENTITY`), and the affected refs on stdin, one `<oid> <ref>` per line.
+//!
+//! Every hook is run with a timeout, and the failure of one hook neither
+//! affects the other hooks, nor the operation which triggered them. The hooks
+//! run after replication are [`Hooks::spawn`]ed, so replication does not wait
+//! for them while holding on to the storage.
+
+use std::{
+    fmt,
+    io::{self, Write as _},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+    sync::{mpsc, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use git_ext as ext;
+use thiserror::Error;
+
+use super::{
+    refs::Refs,
+    replication::{IdStatus, Mode, ReplicateResult},
+    Urn,
+};
+use crate::{paths::Paths, peer::PeerId};
+
+/// The default for [`Hooks::timeout`].
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often to check whether a hook executable has exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The error returned by in-process callbacks.
+pub type CallbackError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+type Callback = Arc<dyn Fn(&Event) -> Result<(), CallbackError> + Send + Sync>;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("hook failed")]
+    Callback(#[source] CallbackError),
+
+    #[error("hook panicked")]
+    Panic,
+
+    #[error("hook did not finish within {0:?}")]
+    Timeout(Duration),
+
+    #[error("hook {0} exited with {1}")]
+    Exit(PathBuf, ExitStatus),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// The occasion on which hooks are run.
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// [`super::replication::replicate`] succeeded.
+    Replicated {
+        urn: Urn,
+        remote_peer: PeerId,
+        result: ReplicateResult,
+    },
+    /// A push via the [`super::local::transport::LocalTransport`] succeeded,
+    /// resulting in `refs`.
+    Pushed { urn: Urn, refs: Refs },
+}
+
+impl Event {
+    /// The name of the hook executable run for this event.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Replicated { .. } => "post-replicate",
+            Self::Pushed { .. } => "post-receive",
+        }
+    }
+
+    pub fn urn(&self) -> &Urn {
+        match self {
+            Self::Replicated { urn, .. } | Self::Pushed { urn, .. } => urn,
+        }
+    }
+
+    /// The refs affected by this event, along with the [`ext::Oid`] they point
+    /// to.
+    ///
+    /// For [`Event::Replicated`], these are the updated tips. For
+    /// [`Event::Pushed`], these are all refs published by the local peer.
+    pub fn refs(&self) -> Vec<(ext::RefLike, ext::Oid)> {
+        match self {
+            Self::Replicated { result, .. } => result
+                .updated_tips
+                .iter()
+                .map(|(name, oid)| (name.clone(), *oid))
+                .collect(),
+            Self::Pushed { refs, .. } => refs
+                .iter_categorised()
+                .map(|((name, oid), category)| {
+                    let name = ext::OneLevel::clone(name).into_qualified(category.into());
+                    (name.into(), *oid)
+                })
+                .collect(),
+        }
+    }
+
+    fn env(&self) -> Vec<(&'static str, String)> {
+        let mut env = vec![
+            ("RAD_HOOK", self.name().to_owned()),
+            ("RAD_URN", self.urn().to_string()),
+        ];
+        if let Self::Replicated {
+            remote_peer,
+            result,
+            ..
+        } = self
+        {
+            env.push(("RAD_REMOTE_PEER", remote_peer.to_string()));
+            env.push((
+                "RAD_MODE",
+                match result.mode {
+                    Mode::Clone => "clone",
+                    Mode::Fetch => "fetch",
+                }
+                .to_owned(),
+            ));
+            env.push((
+                "RAD_IDENTITY",
+                match result.identity {
+                    IdStatus::Even => "even",
+                    IdStatus::Uneven => "uneven",
+                }
+                .to_owned(),
+            ));
+        }
+        env
+    }
+}
+
+/// A registry of hooks.
+///
+/// The [`Default`] registry has no hooks.
+#[derive(Clone)]
+pub struct Hooks {
+    /// The directory to look up hook executables in, if any.
+    pub dir: Option<PathBuf>,
+    /// The maximum amount of time a single hook may take.
+    ///
+    /// Executables which exceed it are killed. In-process callbacks can't be
+    /// interrupted, so they are left running in the background.
+    pub timeout: Duration,
+    callbacks: Vec<Callback>,
+}
+
+impl Default for Hooks {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            timeout: DEFAULT_TIMEOUT,
+            callbacks: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("dir", &self.dir)
+            .field("timeout", &self.timeout)
+            .field("callbacks", &self.callbacks.len())
+            .finish()
+    }
+}
+
+impl Hooks {
+    /// Run the executables in [`Paths::hooks_dir`].
+    pub fn new(paths: &Paths) -> Self {
+        Self {
+            dir: Some(paths.hooks_dir().to_path_buf()),
+            ..Self::default()
+        }
+    }
+
+    /// Register an in-process callback, run on every [`Event`].
+    pub fn register<F>(&mut self, f: F)
+    where
+        F: Fn(&Event) -> Result<(), CallbackError> + Send + Sync + 'static,
+    {
+        self.callbacks.push(Arc::new(f))
+    }
+
+    /// `true` if there are no hooks to run.
+    pub fn is_empty(&self) -> bool {
+        self.dir.is_none() && self.callbacks.is_empty()
+    }
+
+    /// Run all hooks for `event`, in order of registration, followed by the
+    /// executable.
+    ///
+    /// Failures are logged, and returned for inspection.
+    #[tracing::instrument(skip(self, event), fields(hook = event.name(), urn = %event.urn()))]
+    pub fn run(&self, event: Event) -> Vec<Error> {
+        let event = Arc::new(event);
+        let mut failures = Vec::new();
+
+        for callback in &self.callbacks {
+            if let Err(e) = call(Arc::clone(callback), Arc::clone(&event), self.timeout) {
+                failures.push(e)
+            }
+        }
+
+        if let Some(dir) = &self.dir {
+            let path = dir.join(event.name());
+            if is_executable(&path) {
+                if let Err(e) = exec(&path, &event, self.timeout) {
+                    failures.push(e)
+                }
+            }
+        }
+
+        for e in &failures {
+            tracing::warn!(err = %e, "hook failed");
+        }
+        failures
+    }
+}
+
+impl Hooks {
+    /// Like [`Hooks::run`], but in a background thread.
+    ///
+    /// The returned handle may be joined to obtain the failures, or dropped to
+    /// not wait for the hooks to finish.
+    pub fn spawn(&self, event: Event) -> thread::JoinHandle<Vec<Error>> {
+        let hooks = self.clone();
+        thread::spawn(move || hooks.run(event))
+    }
+}
+
+fn call(callback: Callback, event: Arc<Event>, timeout: Duration) -> Result<(), Error> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let res = panic::catch_unwind(AssertUnwindSafe(|| callback(&event)));
+        tx.send(res).ok();
+    });
+
+    match rx.recv_timeout(timeout) {
+        Ok(Ok(res)) => res.map_err(Error::Callback),
+        Ok(Err(_)) | Err(mpsc::RecvTimeoutError::Disconnected) => Err(Error::Panic),
+        Err(mpsc::RecvTimeoutError::Timeout) => Err(Error::Timeout(timeout)),
+    }
+}
+
+fn exec(path: &Path, event: &Event, timeout: Duration) -> Result<(), Error> {
+    let mut child = Command::new(path)
+        .envs(event.env())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+
+    // Write stdin from a separate thread, so a hook which doesn't read it can't
+    // block us beyond the timeout
+    if let Some(mut stdin) = child.stdin.take() {
+        let refs = event.refs();
+        thread::spawn(move || {
+            for (name, oid) in refs {
+                if writeln!(stdin, "{} {}", oid, name).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait()? {
+            Some(status) if status.success() => return Ok(()),
+            Some(status) => return Err(Error::Exit(path.to_path_buf(), status)),
+            None if Instant::now() >= deadline => {
+                child.kill()?;
+                child.wait()?;
+                return Err(Error::Timeout(timeout));
+            },
+            None => thread::sleep(POLL_INTERVAL),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    path.metadata()
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    };
+
+    use crate::keys::SecretKey;
+
+    fn replicated() -> Event {
+        Event::Replicated {
+            urn: Urn::new(git2::Oid::zero().into()),
+            remote_peer: PeerId::from(SecretKey::new()),
+            result: ReplicateResult {
+                updated_tips: Default::default(),
+                identity: IdStatus::Even,
+                mode: Mode::Clone,
+            },
+        }
+    }
+
+    #[test]
+    fn failures_are_isolated() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut hooks = Hooks {
+            timeout: Duration::from_millis(100),
+            ..Hooks::default()
+        };
+        hooks.register(|_| Err("nope".into()));
+        hooks.register(|_| panic!("hook panic"));
+        hooks.register(|_| {
+            thread::sleep(Duration::from_secs(1));
+            Ok(())
+        });
+        hooks.register({
+            let calls = Arc::clone(&calls);
+            move |event| {
+                assert_eq!(event.name(), "post-replicate");
+                calls.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            }
+        });
+
+        let failures = hooks.run(replicated());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(matches!(
+            failures.as_slice(),
+            [Error::Callback(_), Error::Panic, Error::Timeout(_)]
+        ));
+    }
+
+    #[test]
+    fn spawn_runs_in_background() {
+        let (tx, rx) = mpsc::channel();
+        let rx = Mutex::new(rx);
+        let mut hooks = Hooks::default();
+        hooks.register(move |_| {
+            // Only proceed once the caller got hold of the handle
+            rx.lock().unwrap().recv_timeout(Duration::from_secs(5))?;
+            Ok(())
+        });
+
+        let handle = hooks.spawn(replicated());
+        tx.send(()).unwrap();
+        assert!(handle.join().unwrap().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn runs_executable() {
+        use std::{fs, os::unix::fs::PermissionsExt as _};
+
+        let tmp = tempfile::tempdir().unwrap();
+        let paths = Paths::from_root(tmp.path()).unwrap();
+        let out = tmp.path().join("out");
+        let hook = paths.hooks_dir().join("post-replicate");
+        fs::write(
+            &hook,
+            format!("#!/bin/sh\necho \"$RAD_MODE\" > {}\n", out.display()),
+        )
+        .unwrap();
+        fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let failures = Hooks::new(&paths).run(replicated());
+        assert!(failures.is_empty());
+        assert_eq!(fs::read_to_string(&out).unwrap(), "clone\n");
+    }
+}
@@ -5,10 +5,12 @@
 
 pub mod any;
 pub mod error;
+pub mod fork;
 pub mod local;
 pub mod person;
 pub mod project;
 pub mod relations;
+pub mod review;
 
 pub(super) mod common;
 
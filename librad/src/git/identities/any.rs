@@ -9,7 +9,7 @@ use git_ext::is_not_found_err;
 
 use super::{
     super::{
-        storage::{self, glob, Storage},
+        storage::{self, glob, ReadOnly},
         types::Reference,
     },
     error::Error,
@@ -27,7 +27,7 @@ pub use identities::git::Urn;
 /// tip of the branch it resolves to. If that branch is not found, `None` is
 /// returned.
 #[tracing::instrument(level = "debug", skip(storage), err)]
-pub fn get(storage: &Storage, urn: &Urn) -> Result<Option<SomeIdentity>, Error> {
+pub fn get(storage: &ReadOnly, urn: &Urn) -> Result<Option<SomeIdentity>, Error> {
     let branch = Reference::try_from(urn)?;
     tracing::trace!(
         "trying to resolve unknown identity at {} from {}",
@@ -37,7 +37,7 @@ pub fn get(storage: &Storage, urn: &Urn) -> Result<Option<SomeIdentity>, Error>
     match storage.reference(&branch) {
         Ok(Some(reference)) => {
             let tip = reference.peel_to_commit()?.id();
-            Ok(Some(identities(&storage).some_identity(tip)?))
+            Ok(Some(identities(storage).some_identity(tip)?))
         },
 
         Ok(None) => Ok(None),
@@ -49,7 +49,7 @@ pub fn get(storage: &Storage, urn: &Urn) -> Result<Option<SomeIdentity>, Error>
 /// List all identities found in `storage`.
 #[tracing::instrument(level = "debug", skip(storage), err)]
 pub fn list<'a>(
-    storage: &'a Storage,
+    storage: &'a ReadOnly,
 ) -> Result<impl Iterator<Item = Result<SomeIdentity, Error>> + 'a, Error> {
     let iter = self::list_urns(storage)?.filter_map(move |urn| match urn {
         Ok(urn) => self::get(storage, &urn).transpose(),
@@ -65,7 +65,7 @@ pub fn list<'a>(
 /// [`Urn`], but neither the existence nor the validity of the identity
 /// histories is guaranteed.
 pub fn list_urns(
-    storage: &Storage,
+    storage: &ReadOnly,
 ) -> Result<impl Iterator<Item = Result<Urn, Error>> + '_, Error> {
     lazy_static! {
         static ref GLOB: glob::RefspecMatcher =
@@ -79,6 +79,6 @@ pub fn list_urns(
     Ok(iter)
 }
 
-fn identities(storage: &Storage) -> Identities<!> {
+fn identities(storage: &ReadOnly) -> Identities<!> {
     storage.identities()
 }
@@ -7,7 +7,7 @@ use git_ext::is_exists_err;
 use std_ext::result::ResultExt as _;
 
 use super::super::{
-    storage::Storage,
+    storage::{events::Event, Storage},
     types::{Force, Namespace, Reference},
 };
 use crate::identities::git::Urn;
@@ -31,15 +31,21 @@ impl<'a> IdRef<'a> {
         storage: &Storage,
         target: impl AsRef<git2::Oid>,
     ) -> Result<(), git2::Error> {
-        Reference::rad_id(Namespace::from(self.0))
+        let created = Reference::rad_id(Namespace::from(self.0))
             .create(
                 storage.as_raw(),
                 *target.as_ref(),
                 Force::False,
                 &format!("Initial rad/id for {}", self.0),
             )
-            .and(Ok(()))
-            .or_matches(is_exists_err, || Ok(()))
+            .and(Ok(true))
+            .or_matches(is_exists_err, || Ok(false))?;
+        if created {
+            storage.emit(Event::UrnCreated {
+                urn: self.0.clone(),
+            });
+        }
+        Ok(())
     }
 
     pub fn update(
@@ -48,8 +54,17 @@ impl<'a> IdRef<'a> {
         target: impl AsRef<git2::Oid>,
         msg: &str,
     ) -> Result<(), git2::Error> {
-        Reference::rad_id(Namespace::from(self.0))
-            .create(storage.as_raw(), *target.as_ref(), Force::True, msg)
-            .and(Ok(()))
+        let target = *target.as_ref();
+        Reference::rad_id(Namespace::from(self.0)).create(
+            storage.as_raw(),
+            target,
+            Force::True,
+            msg,
+        )?;
+        storage.emit(Event::IdentityUpdated {
+            urn: self.0.clone(),
+            tip: target.into(),
+        });
+        Ok(())
     }
 }
@@ -9,10 +9,13 @@ use super::{
     super::{refs, storage, types::reference},
     local,
 };
-use crate::identities::{
-    self,
-    git::{Urn, VerificationError},
-    urn,
+use crate::{
+    identities::{
+        self,
+        git::{Urn, VerificationError},
+        urn,
+    },
+    peer::PeerId,
 };
 
 #[derive(Debug, Error)]
@@ -21,6 +24,18 @@ pub enum Error {
     #[error("the URN {0} does not exist")]
     NotFound(Urn),
 
+    #[error("the identity {0} is not forked")]
+    NotForked(Urn),
+
+    #[error("{tip} is not the tip of a side of the fork of {urn}")]
+    NoSuchSide { urn: Urn, tip: git_ext::Oid },
+
+    #[error("{peer} is not a delegate of {urn}")]
+    NotADelegate { urn: Urn, peer: PeerId },
+
+    #[error("the rad/id of {urn} as seen by {peer} is not ahead of ours")]
+    NotAhead { urn: Urn, peer: PeerId },
+
     #[error("failed to build ref from URN")]
     RefFromUrn(#[from] reference::FromUrnError),
 
@@ -60,6 +75,9 @@ pub enum Error {
     #[error(transparent)]
     ProjHist(#[from] identities::git::error::History<identities::git::ProjectDoc>),
 
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
     #[error(transparent)]
     Git(#[from] git2::Error),
 }
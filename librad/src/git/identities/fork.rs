@@ -0,0 +1,465 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Inspection and resolution of forked identity histories.
+//!
+//! An identity is forked if the `rad/id` branches of two or more peers
+//! (including the local peer) point to histories neither of which is an
+//! ancestor of the other. Replication refuses to pick a side in this case.
+//!
+//! [`inspect`] describes the fork, and [`resolve`] adopts one of its sides.
+//! The resolution is recorded in the storage's git config as
+//! `fork.<urn>.adopted`, so subsequent replications follow the adopted side
+//! (see [`choose`]) instead of failing.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+};
+
+use git_ext::{self as ext, is_not_found_err};
+
+use super::{
+    super::{
+        refs::Refs as Sigrefs,
+        storage::{self, glob, ReadOnly, Storage},
+        types::{Namespace, Reference},
+    },
+    any,
+    common,
+    error::Error,
+    person,
+    project,
+};
+use crate::{
+    identities::{
+        git::{SomeIdentity, Urn},
+        sign::Signatures,
+    },
+    keys::PublicKey,
+    peer::PeerId,
+};
+
+/// A forked identity, as returned by [`inspect`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fork {
+    pub urn: Urn,
+    /// The most recent revision all [`Self::sides`] have in common, if any.
+    pub ancestor: Option<ext::Oid>,
+    /// The diverging histories, ie. at least two.
+    pub sides: Vec<Side>,
+    /// The tip recorded by a previous [`resolve`], if any.
+    pub adopted: Option<ext::Oid>,
+}
+
+/// One of the diverging histories of a [`Fork`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Side {
+    pub tip: ext::Oid,
+    /// Whether the local `rad/id` is on this side.
+    pub local: bool,
+    /// The remote peers whose `rad/id` is on this side.
+    pub peers: BTreeSet<PeerId>,
+    /// The revisions of this side which are not shared with the other sides,
+    /// most recent first.
+    pub history: Vec<ext::Oid>,
+    /// The keys which signed any of the revisions in [`Self::history`].
+    pub signed_by: BTreeSet<PublicKey>,
+}
+
+/// Which side of a [`Fork`] to adopt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resolution {
+    /// Keep the side the local `rad/id` is on.
+    KeepLocal,
+    /// Adopt the side with the given [`Side::tip`].
+    Adopt(ext::Oid),
+}
+
+/// Determine if the identity `urn` is forked.
+///
+/// Returns `None` if all `rad/id`s are on the same history.
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn inspect(storage: &ReadOnly, urn: &Urn) -> Result<Option<Fork>, Error> {
+    let repo = storage.as_raw();
+    let views = views(storage, urn)?;
+
+    let mut tips = BTreeSet::new();
+    for tip in views.values() {
+        tips.insert(*tip);
+    }
+    // Only tips which aren't behind any other tip start a side
+    let mut heads = Vec::new();
+    for tip in &tips {
+        let mut behind = false;
+        for other in &tips {
+            if other != tip && repo.graph_descendant_of(**other, **tip)? {
+                behind = true;
+                break;
+            }
+        }
+        if !behind {
+            heads.push(*tip);
+        }
+    }
+    if heads.len() < 2 {
+        return Ok(None);
+    }
+
+    let ancestor = match repo.merge_base_many(&heads.iter().map(|h| **h).collect::<Vec<_>>()) {
+        Ok(oid) => Some(ext::Oid::from(oid)),
+        Err(e) if is_not_found_err(&e) => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut sides = Vec::with_capacity(heads.len());
+    for head in &heads {
+        let mut side = Side {
+            tip: *head,
+            local: false,
+            peers: BTreeSet::new(),
+            history: Vec::new(),
+            signed_by: BTreeSet::new(),
+        };
+
+        // A view belongs to this side if it is not also behind another side
+        for (peer, tip) in &views {
+            let on_side = |head: &ext::Oid| -> Result<bool, git2::Error> {
+                Ok(tip == head || repo.graph_descendant_of(**head, **tip)?)
+            };
+            if !on_side(head)? {
+                continue;
+            }
+            let mut elsewhere = false;
+            for other in heads.iter().filter(|other| *other != head) {
+                if on_side(other)? {
+                    elsewhere = true;
+                    break;
+                }
+            }
+            if !elsewhere {
+                match peer {
+                    None => side.local = true,
+                    Some(peer) => {
+                        side.peers.insert(*peer);
+                    },
+                }
+            }
+        }
+
+        let mut walk = repo.revwalk()?;
+        walk.set_sorting(git2::Sort::TOPOLOGICAL)?;
+        walk.push(**head)?;
+        if let Some(ancestor) = ancestor {
+            walk.hide(*ancestor)?;
+        }
+        for oid in walk {
+            let commit = repo.find_commit(oid?)?;
+            if let Some(signatures) = commit
+                .message()
+                .and_then(|msg| Signatures::from_trailers(msg).ok())
+            {
+                side.signed_by.extend(signatures.keys().copied());
+            }
+            side.history.push(commit.id().into());
+        }
+
+        sides.push(side);
+    }
+
+    Ok(Some(Fork {
+        urn: urn.clone(),
+        ancestor,
+        sides,
+        adopted: adopted(storage, urn)?,
+    }))
+}
+
+/// Resolve the [`Fork`] of `urn` by adopting one of its sides.
+///
+/// The adopted side must verify, as seen by a peer whose `rad/id` is at its
+/// tip. If it does, the local `rad/id` is updated to point to it, and the
+/// choice is recorded for [`choose`].
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn resolve(storage: &Storage, urn: &Urn, resolution: Resolution) -> Result<ext::Oid, Error> {
+    let fork = inspect(storage, urn)?.ok_or_else(|| Error::NotForked(urn.clone()))?;
+    let tip = match resolution {
+        Resolution::KeepLocal => fork
+            .sides
+            .iter()
+            .find(|side| side.local)
+            .map(|side| side.tip)
+            .ok_or_else(|| Error::NotFound(urn.clone()))?,
+        Resolution::Adopt(tip) => {
+            if !fork.sides.iter().any(|side| side.tip == tip) {
+                return Err(Error::NoSuchSide {
+                    urn: urn.clone(),
+                    tip,
+                });
+            }
+            tip
+        },
+    };
+
+    verify_side(storage, urn, tip)?;
+
+    let mut config = storage::Config::try_from(storage)?;
+    config
+        .as_raw_mut()
+        .set_str(&config_key(urn), &tip.to_string())?;
+
+    let local = common::IdRef::from(urn).oid(storage).map(ext::Oid::from);
+    match local {
+        Ok(local) if local == tip => {},
+        Err(e) if !is_not_found_err(&e) => return Err(e.into()),
+        _ => {
+            common::IdRef::from(urn).update(storage, tip, &format!("adopt fork side {}", tip))?;
+            Sigrefs::update(storage, urn)?;
+        },
+    }
+
+    Ok(tip)
+}
+
+/// The tip adopted by the last [`resolve`] of `urn`, if any.
+pub fn adopted(storage: &ReadOnly, urn: &Urn) -> Result<Option<ext::Oid>, Error> {
+    let config = storage.as_raw().config()?;
+    match config.get_string(&config_key(urn)) {
+        Ok(tip) => Ok(Some(git2::Oid::from_str(&tip)?.into())),
+        Err(e) if is_not_found_err(&e) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Given the tips `left` and `right` of two diverging histories of `urn`,
+/// choose the one which is on the side adopted by [`resolve`].
+///
+/// Returns `None` if no side was adopted, or if the adopted side is not
+/// unambiguously one of `left` or `right`.
+pub fn choose(
+    storage: &ReadOnly,
+    urn: &Urn,
+    left: ext::Oid,
+    right: ext::Oid,
+) -> Result<Option<ext::Oid>, Error> {
+    let adopted = match adopted(storage, urn)? {
+        Some(adopted) => adopted,
+        None => return Ok(None),
+    };
+    let repo = storage.as_raw();
+    let follows = |tip: ext::Oid| -> Result<bool, git2::Error> {
+        Ok(tip == adopted || repo.graph_descendant_of(*tip, *adopted)?)
+    };
+
+    match (follows(left)?, follows(right)?) {
+        (true, false) => Ok(Some(left)),
+        (false, true) => Ok(Some(right)),
+        _ => Ok(None),
+    }
+}
+
+/// Verify the identity `urn` at `tip`, as seen by us or a remote whose `rad/id`
+/// points to `tip`.
+fn verify_side(storage: &Storage, urn: &Urn, tip: ext::Oid) -> Result<(), Error> {
+    let peer = views(storage, urn)?
+        .into_iter()
+        .find_map(|(peer, view)| (view == tip).then_some(peer))
+        .ok_or_else(|| Error::NoSuchSide {
+            urn: urn.clone(),
+            tip,
+        })?;
+    let view = Urn::try_from(Reference::rad_id(Namespace::from(urn)).with_remote(peer))
+        .expect("namespace is set");
+
+    let verified = match any::get(storage, &view)?.ok_or_else(|| Error::NotFound(view.clone()))? {
+        SomeIdentity::Person(_) => person::verify(storage, &view)?.map(|pers| pers.content_id),
+        SomeIdentity::Project(_) => project::verify(storage, &view)?.map(|proj| proj.content_id),
+    };
+    match verified {
+        Some(verified) if verified == tip => Ok(()),
+        _ => Err(Error::NotFound(view)),
+    }
+}
+
+/// The tips of the `rad/id` of `urn` as seen by us (`None`) and all remotes.
+fn views(storage: &ReadOnly, urn: &Urn) -> Result<BTreeMap<Option<PeerId>, ext::Oid>, Error> {
+    let mut views = BTreeMap::new();
+    if let Some(tip) = storage
+        .reference(&Reference::rad_id(Namespace::from(urn)))?
+        .and_then(|reference| reference.target())
+    {
+        views.insert(None, tip.into());
+    }
+
+    let remotes = reflike!("refs/namespaces")
+        .join(urn)
+        .join(reflike!("refs/remotes"));
+    for reference in storage.references_glob(glob::RefspecMatcher::from(
+        remotes.with_pattern_suffix(refspec_pattern!("*/rad/id")),
+    ))? {
+        let reference = reference?;
+        let peer = reference
+            .name()
+            .and_then(|name| name.strip_prefix(&format!("{}/", remotes.as_str())))
+            .and_then(|suffix| suffix.split('/').next())
+            .and_then(|peer| peer.parse::<PeerId>().ok());
+        if let (Some(peer), Some(tip)) = (peer, reference.target()) {
+            views.insert(Some(peer), tip.into());
+        }
+    }
+
+    Ok(views)
+}
+
+fn config_key(urn: &Urn) -> String {
+    format!("fork.{}.adopted", urn.encode_id())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        git::types::Force,
+        identities::{
+            delegation,
+            git::{Identities, Person, Verifying},
+            payload::{self, PersonPayload},
+        },
+        keys::SecretKey,
+        paths::Paths,
+    };
+
+    #[test]
+    fn inspect_and_resolve() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let key = SecretKey::new();
+            let paths = Paths::from_root(&tmp).unwrap();
+            let storage = Storage::open(&paths, key.clone()).unwrap();
+            let dylan = person::create(
+                &storage,
+                payload::Person {
+                    name: "dylan".into(),
+                },
+                Some(key.public()).into_iter().collect(),
+            )
+            .unwrap();
+            let urn = dylan.urn();
+            assert_eq!(inspect(&storage, &urn).unwrap(), None);
+
+            // Diverge from the root revision
+            let repo = storage.as_raw();
+            let identities: Identities<Person> = storage.identities();
+            let diverge = |name: &str| -> ext::Oid {
+                let base = Verifying::from(dylan.clone()).signed().unwrap();
+                identities
+                    .update(
+                        base,
+                        Some(PersonPayload::new(payload::Person { name: name.into() })),
+                        None::<delegation::Direct>,
+                        &key,
+                    )
+                    .unwrap()
+                    .content_id
+            };
+            let ours = diverge("ours");
+            let theirs = diverge("theirs");
+
+            let peer = PeerId::from(SecretKey::new());
+            let rad_id = Reference::rad_id(Namespace::from(&urn));
+            rad_id.create(repo, *ours, Force::True, "ours").unwrap();
+            rad_id
+                .clone()
+                .with_remote(peer)
+                .create(repo, *theirs, Force::True, "theirs")
+                .unwrap();
+
+            let fork = inspect(&storage, &urn).unwrap().unwrap();
+            assert_eq!(fork.ancestor, Some(dylan.content_id));
+            assert_eq!(fork.adopted, None);
+            assert_eq!(fork.sides.len(), 2);
+            let local = fork.sides.iter().find(|side| side.local).unwrap();
+            assert_eq!(local.tip, ours);
+            assert_eq!(local.history, vec![ours]);
+            let remote = fork.sides.iter().find(|side| !side.local).unwrap();
+            assert_eq!(remote.tip, theirs);
+            assert_eq!(remote.peers, Some(peer).into_iter().collect());
+
+            assert_eq!(choose(&storage, &urn, ours, theirs).unwrap(), None);
+            assert_eq!(
+                resolve(&storage, &urn, Resolution::Adopt(theirs)).unwrap(),
+                theirs
+            );
+            assert_eq!(adopted(&storage, &urn).unwrap(), Some(theirs));
+            assert_eq!(common::IdRef::from(&urn).oid(&storage).unwrap(), *theirs);
+            assert_eq!(choose(&storage, &urn, ours, theirs).unwrap(), Some(theirs));
+        }
+    }
+
+    #[test]
+    fn reject_unverified_side() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let key = SecretKey::new();
+            let paths = Paths::from_root(&tmp).unwrap();
+            let storage = Storage::open(&paths, key.clone()).unwrap();
+            let dylan = person::create(
+                &storage,
+                payload::Person {
+                    name: "dylan".into(),
+                },
+                Some(key.public()).into_iter().collect(),
+            )
+            .unwrap();
+            let urn = dylan.urn();
+
+            // An unsigned revision on top of the root
+            let repo = storage.as_raw();
+            let root = repo.find_commit(*dylan.content_id).unwrap();
+            let author = repo.signature().unwrap();
+            let forged: ext::Oid = repo
+                .commit(
+                    None,
+                    &author,
+                    &author,
+                    "forged",
+                    &root.tree().unwrap(),
+                    &[&root],
+                )
+                .unwrap()
+                .into();
+            let ours = {
+                let base = Verifying::from(dylan.clone()).signed().unwrap();
+                storage
+                    .identities::<Person>()
+                    .update(
+                        base,
+                        Some(PersonPayload::new(payload::Person {
+                            name: "ours".into(),
+                        })),
+                        None::<delegation::Direct>,
+                        &key,
+                    )
+                    .unwrap()
+                    .content_id
+            };
+
+            let peer = PeerId::from(SecretKey::new());
+            let rad_id = Reference::rad_id(Namespace::from(&urn));
+            rad_id.create(repo, *ours, Force::True, "ours").unwrap();
+            rad_id
+                .clone()
+                .with_remote(peer)
+                .create(repo, *forged, Force::True, "forged")
+                .unwrap();
+            assert!(inspect(&storage, &urn).unwrap().is_some());
+
+            assert!(resolve(&storage, &urn, Resolution::Adopt(forged)).is_err());
+            assert_eq!(adopted(&storage, &urn).unwrap(), None);
+            assert_eq!(common::IdRef::from(&urn).oid(&storage).unwrap(), *ours);
+        }
+    }
+}
@@ -10,7 +10,7 @@ use radicle_git_ext::{is_not_found_err, OneLevel};
 use super::{
     super::{
         refs::Refs,
-        storage::{self, Storage},
+        storage::{self, ReadOnly, Storage},
         types::Reference,
     },
     common,
@@ -36,7 +36,7 @@ pub use identities::{
 ///
 /// If the ref is not found, `None` is returned.
 #[tracing::instrument(level = "trace", skip(storage), err)]
-pub fn get(storage: &Storage, urn: &Urn) -> Result<Option<Person>, Error> {
+pub fn get(storage: &ReadOnly, urn: &Urn) -> Result<Option<Person>, Error> {
     match storage.reference(&Reference::try_from(urn)?) {
         Ok(Some(reference)) => {
             let tip = reference.peel_to_commit()?.id();
@@ -60,7 +60,7 @@ pub fn get(storage: &Storage, urn: &Urn) -> Result<Option<Person>, Error> {
 /// function cannot be used to assert that the state after an [`update`] is
 /// valid.
 #[tracing::instrument(level = "debug", skip(storage), err)]
-pub fn verify(storage: &Storage, urn: &Urn) -> Result<Option<VerifiedPerson>, Error> {
+pub fn verify(storage: &ReadOnly, urn: &Urn) -> Result<Option<VerifiedPerson>, Error> {
     let branch = Reference::try_from(urn)?;
     tracing::debug!("verifying {} from {}", urn, branch);
     match storage.reference(&branch) {
@@ -180,7 +180,7 @@ pub fn newer(
     Ok(verified(storage).newer(a, b)?)
 }
 
-fn identities(storage: &Storage) -> Identities<Person> {
+fn identities(storage: &ReadOnly) -> Identities<Person> {
     storage.identities()
 }
 
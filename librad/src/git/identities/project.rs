@@ -11,7 +11,7 @@ use git_ext::{is_not_found_err, OneLevel};
 use super::{
     super::{
         refs::Refs as Sigrefs,
-        storage::{self, Storage},
+        storage::{self, ReadOnly, Storage},
         types::{namespace, reference, Force, Reference, Single, SymbolicRef},
     },
     common,
@@ -35,7 +35,7 @@ type Namespace = namespace::Namespace<Revision>;
 ///
 /// If the ref is not found, `None` is returned.
 #[tracing::instrument(level = "trace", skip(storage), err)]
-pub fn get(storage: &Storage, urn: &Urn) -> Result<Option<Project>, Error> {
+pub fn get(storage: &ReadOnly, urn: &Urn) -> Result<Option<Project>, Error> {
     match storage.reference(&Reference::try_from(urn)?) {
         Ok(Some(reference)) => {
             let tip = reference.peel_to_commit()?.id();
@@ -59,7 +59,7 @@ pub fn get(storage: &Storage, urn: &Urn) -> Result<Option<Project>, Error> {
 /// function cannot be used to assert that the state after an [`update`] is
 /// valid.
 #[tracing::instrument(level = "debug", skip(storage), err)]
-pub fn verify(storage: &Storage, urn: &Urn) -> Result<Option<VerifiedProject>, Error> {
+pub fn verify(storage: &ReadOnly, urn: &Urn) -> Result<Option<VerifiedProject>, Error> {
     match storage.reference(&Reference::try_from(urn)?) {
         Ok(Some(reference)) => {
             let tip = reference.peel_to_commit()?.id();
@@ -223,10 +223,10 @@ impl<'a> ProjectRefs<'a> {
     }
 }
 
-fn identities(storage: &Storage) -> Identities<Project> {
+fn identities(storage: &ReadOnly) -> Identities<Project> {
     storage.identities()
 }
 
-fn verified(storage: &Storage) -> Identities<VerifiedProject> {
+fn verified(storage: &ReadOnly) -> Identities<VerifiedProject> {
     storage.identities()
 }
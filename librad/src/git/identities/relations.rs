@@ -87,7 +87,7 @@ pub fn tracked(storage: &Storage, urn: &Urn) -> Result<Vec<Peer<Status<Person>>>
 
     let mut peers = vec![];
 
-    for peer_id in tracking::tracked(storage, &urn)? {
+    for peer_id in tracking::tracked_peers(storage, &urn)? {
         let rad_self = Urn::try_from(Reference::rad_self(Namespace::from(urn.clone()), peer_id))
             .expect("namespace is set");
         let status = if storage.has_urn(&rad_self)? {
@@ -0,0 +1,410 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Review of identity updates published by delegates.
+//!
+//! When replication reports [`crate::git::replication::IdStatus::Uneven`],
+//! the `rad/id` of some delegates is ahead of the local one. [`pending`] lists
+//! these updates, [`accept`] adopts one, and [`reject`] hides it from
+//! [`pending`] until the delegate publishes a newer revision.
+//!
+//! Rejections are recorded in the storage's git config as
+//! `review.<urn>/<peer>.rejected`.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+};
+
+use either::Either;
+use git_ext::{self as ext, is_not_found_err};
+use std_ext::result::ResultExt as _;
+
+use super::{
+    super::{
+        refs::Refs as Sigrefs,
+        storage::{self, ReadOnly, Storage},
+        types::{Namespace, Reference},
+    },
+    common,
+    error::Error,
+    person,
+    project,
+};
+use crate::{
+    identities::git::{Identities, Project, SomeIdentity, Urn},
+    keys::PublicKey,
+    peer::PeerId,
+};
+
+/// A change of a single value between two revisions of an identity.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change<T> {
+    Added(T),
+    Removed(T),
+    Modified { ours: T, theirs: T },
+}
+
+/// A direct or indirect delegation of an identity.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Delegate {
+    Key(PublicKey),
+    Person(Urn),
+}
+
+/// The difference between the local revision of an identity and the revision
+/// published by a delegate.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Diff {
+    /// Changes to the payload, keyed by the namespace of the payload entry
+    /// (eg. `https://radicle.xyz/link/identities/project/v1`).
+    pub payload: BTreeMap<String, Change<serde_json::Value>>,
+    /// Delegations present only in the delegate's revision.
+    pub added: BTreeSet<Delegate>,
+    /// Delegations present only in the local revision.
+    pub removed: BTreeSet<Delegate>,
+}
+
+/// An identity update published by a delegate, as returned by [`pending`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Update {
+    pub peer: PeerId,
+    /// The tip of the delegate's `rad/id`.
+    pub tip: ext::Oid,
+    /// The number of revisions the delegate's `rad/id` is ahead of ours.
+    pub ahead: usize,
+    pub diff: Diff,
+    /// The keys which signed [`Self::tip`].
+    pub signatures: BTreeSet<PublicKey>,
+}
+
+/// List the updates to the identity `urn` which delegates have published, but
+/// which are not reflected in the local `rad/id` yet.
+///
+/// Only `rad/id`s which are strictly ahead of ours are considered, diverged
+/// histories are handled by [`super::fork`]. Updates rejected via [`reject`],
+/// and updates which don't pass verification, are omitted.
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn pending(storage: &ReadOnly, urn: &Urn) -> Result<Vec<Update>, Error> {
+    let repo = storage.as_raw();
+    let (ours_tip, ours) = load(storage, urn, None)?.ok_or_else(|| Error::NotFound(urn.clone()))?;
+
+    let mut updates = Vec::new();
+    for peer in delegates(&ours) {
+        if &peer == storage.peer_id() {
+            continue;
+        }
+        let (tip, theirs) = match load(storage, urn, Some(peer))? {
+            Some(view) => view,
+            None => continue,
+        };
+        if tip == ours_tip || !repo.graph_descendant_of(*tip, *ours_tip)? {
+            continue;
+        }
+        if rejected(storage, urn, peer)? == Some(tip) {
+            continue;
+        }
+
+        let ahead = {
+            let mut walk = repo.revwalk()?;
+            walk.push(*tip)?;
+            walk.hide(*ours_tip)?;
+            walk.count()
+        };
+        let diff = match diff(&ours, &theirs)? {
+            Some(diff) => diff,
+            None => {
+                tracing::warn!(peer = %peer, "identity type changed");
+                continue;
+            },
+        };
+        let signatures = match verified_signatures(storage, urn, peer, &theirs) {
+            Ok(Some((verified, signatures))) if verified == tip => signatures,
+            Ok(_) => {
+                tracing::warn!(peer = %peer, tip = %tip, "identity update does not verify");
+                continue;
+            },
+            Err(Error::Verify(e)) => {
+                tracing::warn!(
+                    peer = %peer,
+                    tip = %tip,
+                    err = %e,
+                    "identity update does not verify"
+                );
+                continue;
+            },
+            Err(e) => return Err(e),
+        };
+
+        updates.push(Update {
+            peer,
+            tip,
+            ahead,
+            diff,
+            signatures,
+        })
+    }
+
+    Ok(updates)
+}
+
+/// Accept the update to `urn` published by `peer`.
+///
+/// If the local peer is a delegate, the update is merged and signed via
+/// [`person::merge`] or [`project::merge`]. Otherwise, the local `rad/id` is
+/// set to the most recent revision of the delegate's history which passes
+/// verification.
+///
+/// As for [`pending`], `peer` must be a delegate of the local revision, and its
+/// `rad/id` must be strictly ahead of ours. Otherwise, [`Error::NotADelegate`]
+/// or [`Error::NotAhead`] is returned.
+///
+/// A previous [`reject`] of an update by `peer` is cleared.
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn accept(storage: &Storage, urn: &Urn, peer: PeerId) -> Result<SomeIdentity, Error> {
+    let (ours_tip, ours) = load(storage, urn, None)?.ok_or_else(|| Error::NotFound(urn.clone()))?;
+    if !delegates(&ours).contains(&peer) {
+        return Err(Error::NotADelegate {
+            urn: urn.clone(),
+            peer,
+        });
+    }
+    let theirs = remote_urn(urn, peer);
+    let (tip, _) =
+        load(storage, urn, Some(peer))?.ok_or_else(|| Error::NotFound(theirs.clone()))?;
+    ensure_ahead(storage, urn, peer, tip, ours_tip)?;
+    let is_delegate = delegates(&ours).contains(storage.peer_id());
+
+    let accepted = match ours {
+        SomeIdentity::Person(_) if is_delegate => {
+            SomeIdentity::Person(person::merge(storage, urn, peer)?)
+        },
+        SomeIdentity::Project(_) if is_delegate => {
+            SomeIdentity::Project(project::merge(storage, urn, peer)?)
+        },
+        SomeIdentity::Person(_) => {
+            let verified =
+                person::verify(storage, &theirs)?.ok_or_else(|| Error::NotFound(theirs.clone()))?;
+            ensure_ahead(storage, urn, peer, verified.content_id, ours_tip)?;
+            adopt(storage, urn, verified.content_id, peer)?;
+            SomeIdentity::Person(verified.into_inner())
+        },
+        SomeIdentity::Project(_) => {
+            let verified = project::verify(storage, &theirs)?
+                .ok_or_else(|| Error::NotFound(theirs.clone()))?;
+            ensure_ahead(storage, urn, peer, verified.content_id, ours_tip)?;
+            adopt(storage, urn, verified.content_id, peer)?;
+            SomeIdentity::Project(verified.into_inner())
+        },
+    };
+
+    let mut config = storage::Config::try_from(storage)?;
+    config
+        .as_raw_mut()
+        .remove(&config_key(urn, peer))
+        .or_matches::<Error, _, _>(is_not_found_err, || Ok(()))?;
+
+    Ok(accepted)
+}
+
+/// Reject the update to `urn` published by `peer` at `tip`.
+///
+/// The update is no longer returned by [`pending`], unless `peer` publishes a
+/// revision other than `tip`.
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn reject(storage: &Storage, urn: &Urn, peer: PeerId, tip: ext::Oid) -> Result<(), Error> {
+    let mut config = storage::Config::try_from(storage)?;
+    config
+        .as_raw_mut()
+        .set_str(&config_key(urn, peer), &tip.to_string())?;
+    Ok(())
+}
+
+/// The tip last passed to [`reject`] for `urn` and `peer`, if any.
+pub fn rejected(storage: &ReadOnly, urn: &Urn, peer: PeerId) -> Result<Option<ext::Oid>, Error> {
+    let config = storage.as_raw().config()?;
+    match config.get_string(&config_key(urn, peer)) {
+        Ok(tip) => Ok(Some(git2::Oid::from_str(&tip)?.into())),
+        Err(e) if is_not_found_err(&e) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Fail with [`Error::NotAhead`] unless `tip` strictly descends from
+/// `ours_tip`.
+fn ensure_ahead(
+    storage: &ReadOnly,
+    urn: &Urn,
+    peer: PeerId,
+    tip: ext::Oid,
+    ours_tip: ext::Oid,
+) -> Result<(), Error> {
+    if tip == ours_tip || !storage.as_raw().graph_descendant_of(*tip, *ours_tip)? {
+        return Err(Error::NotAhead {
+            urn: urn.clone(),
+            peer,
+        });
+    }
+    Ok(())
+}
+
+fn adopt(storage: &Storage, urn: &Urn, tip: ext::Oid, peer: PeerId) -> Result<(), Error> {
+    common::IdRef::from(urn).update(storage, tip, &format!("accept update from {}", peer))?;
+    Sigrefs::update(storage, urn)?;
+    Ok(())
+}
+
+/// Load the identity from the tip of the `rad/id` of `urn` as seen by `peer`,
+/// or by the local peer if `None`.
+fn load(
+    storage: &ReadOnly,
+    urn: &Urn,
+    peer: Option<PeerId>,
+) -> Result<Option<(ext::Oid, SomeIdentity)>, Error> {
+    let rad_id = Reference::rad_id(Namespace::from(urn)).with_remote(peer);
+    match storage.reference(&rad_id)?.and_then(|r| r.target()) {
+        None => Ok(None),
+        Some(tip) => {
+            let identity = identities(storage).some_identity(tip)?;
+            Ok(Some((tip.into(), identity)))
+        },
+    }
+}
+
+/// Verify the `rad/id` of `urn` as seen by `peer`, returning the tip which
+/// passed verification and the keys which signed it.
+///
+/// `identity` is the unverified identity at the tip, which determines whether
+/// to verify a person or a project.
+fn verified_signatures(
+    storage: &ReadOnly,
+    urn: &Urn,
+    peer: PeerId,
+    identity: &SomeIdentity,
+) -> Result<Option<(ext::Oid, BTreeSet<PublicKey>)>, Error> {
+    let theirs = remote_urn(urn, peer);
+    Ok(match identity {
+        SomeIdentity::Person(_) => person::verify(storage, &theirs)?
+            .map(|pers| (pers.content_id, pers.signatures.keys().copied().collect())),
+        SomeIdentity::Project(_) => project::verify(storage, &theirs)?
+            .map(|proj| (proj.content_id, proj.signatures.keys().copied().collect())),
+    })
+}
+
+/// The peers delegated to by `identity`, including the delegates of
+/// indirectly delegating persons.
+fn delegates(identity: &SomeIdentity) -> BTreeSet<PeerId> {
+    match identity {
+        SomeIdentity::Person(person) => person
+            .delegations()
+            .iter()
+            .copied()
+            .map(PeerId::from)
+            .collect(),
+        SomeIdentity::Project(proj) => proj
+            .delegations()
+            .iter()
+            .flat_map(|delegate| match delegate {
+                Either::Left(key) => vec![*key],
+                Either::Right(person) => person.delegations().iter().copied().collect(),
+            })
+            .map(PeerId::from)
+            .collect(),
+    }
+}
+
+/// Compare `ours` to `theirs`. `None` if they are not of the same type.
+fn diff(ours: &SomeIdentity, theirs: &SomeIdentity) -> Result<Option<Diff>, Error> {
+    let (payload, ours_delegations, theirs_delegations) = match (ours, theirs) {
+        (SomeIdentity::Person(ours), SomeIdentity::Person(theirs)) => (
+            payload(&ours.doc.payload, &theirs.doc.payload)?,
+            ours.delegations()
+                .iter()
+                .copied()
+                .map(Delegate::Key)
+                .collect::<BTreeSet<_>>(),
+            theirs
+                .delegations()
+                .iter()
+                .copied()
+                .map(Delegate::Key)
+                .collect::<BTreeSet<_>>(),
+        ),
+        (SomeIdentity::Project(ours), SomeIdentity::Project(theirs)) => {
+            let delegations = |proj: &Project| {
+                proj.delegations()
+                    .iter()
+                    .map(|delegate| match delegate {
+                        Either::Left(key) => Delegate::Key(*key),
+                        Either::Right(person) => Delegate::Person(person.urn()),
+                    })
+                    .collect::<BTreeSet<_>>()
+            };
+            (
+                payload(&ours.doc.payload, &theirs.doc.payload)?,
+                delegations(ours),
+                delegations(theirs),
+            )
+        },
+        _ => return Ok(None),
+    };
+
+    Ok(Some(Diff {
+        payload,
+        added: theirs_delegations
+            .difference(&ours_delegations)
+            .cloned()
+            .collect(),
+        removed: ours_delegations
+            .difference(&theirs_delegations)
+            .cloned()
+            .collect(),
+    }))
+}
+
+fn payload<T: serde::Serialize>(
+    ours: &T,
+    theirs: &T,
+) -> Result<BTreeMap<String, Change<serde_json::Value>>, Error> {
+    let entries = |payload: &T| -> Result<serde_json::Map<String, serde_json::Value>, Error> {
+        match serde_json::to_value(payload)? {
+            serde_json::Value::Object(entries) => Ok(entries),
+            _ => Ok(serde_json::Map::new()),
+        }
+    };
+    let mut ours = entries(ours)?;
+    let theirs = entries(theirs)?;
+
+    let mut changes = BTreeMap::new();
+    for (key, theirs) in theirs {
+        match ours.remove(&key) {
+            None => {
+                changes.insert(key, Change::Added(theirs));
+            },
+            Some(ours) if ours != theirs => {
+                changes.insert(key, Change::Modified { ours, theirs });
+            },
+            Some(_) => {},
+        }
+    }
+    for (key, ours) in ours {
+        changes.insert(key, Change::Removed(ours));
+    }
+
+    Ok(changes)
+}
+
+fn remote_urn(urn: &Urn, peer: PeerId) -> Urn {
+    Urn::try_from(Reference::rad_id(Namespace::from(urn)).with_remote(peer))
+        .expect("namespace is set")
+}
+
+fn config_key(urn: &Urn, peer: PeerId) -> String {
+    format!("review.{}/{}.rejected", urn.encode_id(), peer)
+}
+
+fn identities(storage: &ReadOnly) -> Identities<!> {
+    storage.identities()
+}
@@ -17,6 +17,7 @@ use thiserror::Error;
 
 use super::{
     super::{
+        hooks::{self, Hooks},
         identities,
         refs::{self, Refs},
         storage::{self, glob, Storage},
@@ -64,6 +65,11 @@ pub type OpenStorageError = Box<dyn std::error::Error + Send + Sync + 'static>;
 
 pub trait CanOpenStorage: Send + Sync {
     fn open_storage(&self) -> Result<Box<dyn AsRef<Storage>>, OpenStorageError>;
+
+    /// The [`Hooks`] to run after a successful push.
+    fn hooks(&self) -> Hooks {
+        Hooks::default()
+    }
 }
 
 pub(crate) fn with_local_transport<F, G, A>(
@@ -143,6 +149,10 @@ impl CanOpenStorage for Settings {
         let storage = Storage::open(&self.paths, self.signer.clone())?;
         Ok(Box::new(storage))
     }
+
+    fn hooks(&self) -> Hooks {
+        Hooks::new(&self.paths)
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -237,13 +247,14 @@ impl LocalTransport {
             match service {
                 Service::ReceivePack => {
                     let storage = Arc::clone(&self.storage);
+                    let post_receive = self.storage.hooks();
                     let hook = move || {
                         let _box = storage.open_storage()?;
                         let _dyn = _box.as_ref();
                         let storage = _dyn.as_ref();
 
                         // Update `rad/signed_refs`
-                        Refs::update(storage, &urn)?;
+                        let refs = Refs::update(storage, &urn)?;
 
                         // Ensure we have a `rad/self`
                         let local_id = identities::local::load(storage, urn.clone())
@@ -251,9 +262,21 @@ impl LocalTransport {
                             .or_else(|| identities::local::default(storage).transpose())
                             .transpose()?;
                         match local_id {
-                            None => Err(Error::NoLocalIdentity),
-                            Some(local_id) => Ok(local_id.link(storage, &urn)?),
+                            None => return Err(Error::NoLocalIdentity),
+                            Some(local_id) => local_id.link(storage, &urn)?,
                         }
+
+                        if !post_receive.is_empty() {
+                            let refs = match refs {
+                                Some(refs) => Some(refs),
+                                None => Refs::load(storage, &urn, None)?,
+                            };
+                            if let Some(refs) = refs {
+                                post_receive.run(hooks::Event::Pushed { urn, refs });
+                            }
+                        }
+
+                        Ok(())
                     };
 
                     Some(Box::new(hook))
@@ -23,7 +23,7 @@ use serde::{
 use thiserror::Error;
 
 use super::{
-    storage::{self, Storage},
+    storage::{self, ReadOnly, Storage},
     tracking,
     types::{Namespace, Reference, RefsCategory},
 };
@@ -196,6 +196,9 @@ pub mod stored {
         #[error(transparent)]
         Store(#[from] storage::Error),
 
+        #[error(transparent)]
+        Transaction(#[from] storage::transaction::Error),
+
         #[error(transparent)]
         Git(#[from] git2::Error),
     }
@@ -221,10 +224,28 @@ pub struct Refs {
     /// Note that this does does not include the oids, as they can be determined
     /// by inspecting the `rad/signed_refs` of the respective remote.
     pub remotes: Remotes<PeerId>,
+
+    /// Sequence number, incremented by every [`Refs::update`] which changes
+    /// the published state.
+    ///
+    /// This allows to detect a remote serving an outdated, but validly signed,
+    /// state (see [`crate::git::replication::Error::Rollback`]). [`Refs`]
+    /// published before sequence numbers were introduced have a `seq` of `0`,
+    /// which is omitted from the serialised form so as to not invalidate
+    /// their signatures.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub seq: u64,
+}
+
+fn is_zero(seq: &u64) -> bool {
+    *seq == 0
 }
 
 impl Refs {
     /// Compute the [`Refs`] from the current storage state at [`Urn`].
+    ///
+    /// The [`Refs::seq`] of the result is always `0`, it is only assigned by
+    /// [`Refs::update`].
     #[tracing::instrument(level = "debug", skip(storage, urn), fields(urn = %urn), err)]
     pub fn compute(storage: &Storage, urn: &Urn) -> Result<Self, stored::Error> {
         let namespace = Namespace::from(urn);
@@ -268,7 +289,7 @@ impl Refs {
             .map(refined)
             .collect::<Result<_, _>>()?;
 
-        let mut remotes = tracking::tracked(storage, urn)?.collect::<Remotes<PeerId>>();
+        let mut remotes = tracking::tracked_peers(storage, urn)?.collect::<Remotes<PeerId>>();
         for (peer, tracked) in remotes.iter_mut() {
             if let Some(refs) = Self::load(storage, urn, *peer)? {
                 *tracked = Box::new(refs.remotes.cutoff(TRACKING_GRAPH_DEPTH));
@@ -281,24 +302,40 @@ impl Refs {
             tags,
             notes,
             remotes,
+            seq: 0,
         })
     }
 
     /// Load the [`Refs`] of [`Urn`] (and optionally a remote `peer`) from
     /// storage, and verify the signature.
     ///
-    /// If `peer` is `None`, the signer's public key is used for signature
+    /// If `peer` is `None`, the local peer's public key is used for signature
     /// verification.
     ///
     /// If the blob where the signed [`Refs`] are expected to be stored is not
     /// found, `None` is returned.
     #[tracing::instrument(skip(storage, urn), fields(urn = %urn), err)]
-    pub fn load<P>(storage: &Storage, urn: &Urn, peer: P) -> Result<Option<Self>, stored::Error>
+    pub fn load<P>(storage: &ReadOnly, urn: &Urn, peer: P) -> Result<Option<Self>, stored::Error>
+    where
+        P: Into<Option<PeerId>> + Debug,
+    {
+        Self::load_since(storage, urn, peer, None)
+    }
+
+    /// Like [`Self::load`], but reject [`Refs`] which don't supersede
+    /// `previous`, see [`Signed::verify`].
+    #[tracing::instrument(skip(storage, urn), fields(urn = %urn), err)]
+    pub fn load_since<P>(
+        storage: &ReadOnly,
+        urn: &Urn,
+        peer: P,
+        previous: Option<&Refs>,
+    ) -> Result<Option<Self>, stored::Error>
     where
         P: Into<Option<PeerId>> + Debug,
     {
         let peer = peer.into();
-        let signer = peer.unwrap_or_else(|| PeerId::from_signer(storage.signer()));
+        let signer = peer.unwrap_or_else(|| *storage.peer_id());
 
         let blob_ref = Reference::rad_signed_refs(Namespace::from(urn), peer);
         let blob_path = Path::new(stored::BLOB_PATH);
@@ -311,23 +348,75 @@ impl Refs {
 
         let maybe_blob = storage.blob(&blob_ref, &blob_path)?;
         maybe_blob
-            .map(|blob| Signed::from_json(blob.content(), &signer).map(|signed| signed.refs))
+            .map(|blob| {
+                Signed::from_json(blob.content(), &signer, previous).map(|signed| signed.refs)
+            })
             .transpose()
             .map_err(stored::Error::from)
     }
 
+    /// Iterate over the states of the [`Refs`] of [`Urn`] (and optionally a
+    /// remote `peer`) ever published, most recent first.
+    ///
+    /// The signature of every state is verified as it is yielded, using the
+    /// same key as [`Self::load`].
+    ///
+    /// If the `rad/signed_refs` branch is not found, the iterator is empty.
+    #[tracing::instrument(skip(storage, urn), fields(urn = %urn), err)]
+    pub fn history<'a, P>(
+        storage: &'a ReadOnly,
+        urn: &Urn,
+        peer: P,
+    ) -> Result<History<'a>, stored::Error>
+    where
+        P: Into<Option<PeerId>> + Debug,
+    {
+        let peer = peer.into();
+        let signer = peer.unwrap_or_else(|| *storage.peer_id());
+
+        let branch = Reference::rad_signed_refs(Namespace::from(urn), peer);
+        let walk = match storage.reference(&branch)? {
+            None => None,
+            Some(tip) => {
+                let mut walk = storage.as_raw().revwalk()?;
+                walk.set_sorting(git2::Sort::TOPOLOGICAL)?;
+                walk.simplify_first_parent()?;
+                walk.push(tip.peel_to_commit()?.id())?;
+                Some(walk)
+            },
+        };
+
+        Ok(History {
+            repo: storage.as_raw(),
+            signer,
+            walk,
+        })
+    }
+
     /// Compute the current [`Refs`], sign them, and store them at the
     /// `rad/signed_refs` branch of [`Urn`].
     ///
     /// If the result of [`Self::compute`] is the same as the alread-stored
     /// [`Refs`], no commit is made and `None` is returned. Otherwise, the
     /// new and persisted [`Refs`] are returned in a `Some`.
+    ///
+    /// The new commit has the previously stored state as its parent, so the
+    /// branch records all [`Refs`] ever published (see [`Self::history`]).
     #[tracing::instrument(skip(storage, urn), fields(urn = %urn), err)]
     pub fn update(storage: &Storage, urn: &Urn) -> Result<Option<Self>, stored::Error> {
         let branch = Reference::rad_signed_refs(Namespace::from(urn), None);
         tracing::debug!("updating signed refs for {}", branch);
 
-        let signed_refs = Self::compute(storage, urn)?.sign(storage.signer())?;
+        let mut refs = Self::compute(storage, urn)?;
+        if let Some(prev) = Self::load(storage, urn, None)? {
+            refs.seq = prev.seq;
+            if refs.canonical_form()? == prev.canonical_form()? {
+                tracing::debug!("signed refs already up-to-date");
+                return Ok(None);
+            }
+            refs.seq = prev.seq + 1;
+        }
+        let signed_refs = refs.sign(storage.signer())?;
 
         let raw_git = storage.as_raw();
 
@@ -348,30 +437,36 @@ impl Refs {
             raw_git.find_tree(oid)
         }?;
 
-        if let Some(ref parent) = parent {
-            if parent.tree()?.id() == tree.id() {
-                tracing::debug!("signed refs already up-to-date");
-                return Ok(None);
-            }
-        }
-
+        let msg = format!("Update rad/signed_refs for {}", urn);
         let commit_id = {
             let author = raw_git.signature()?;
             raw_git.commit(
-                Some(reference::RefLike::from(&branch).as_str()),
+                None,
                 &author,
                 &author,
-                &format!("Update rad/signed_refs for {}", urn),
+                &msg,
                 &tree,
                 &parent.iter().collect::<Vec<&git2::Commit>>(),
             )?
         };
+        // Only move the branch if nobody else did in the meantime
+        let mut tx = storage.transaction(msg);
+        tx.update(
+            &branch,
+            commit_id,
+            parent.as_ref().map(|parent| parent.id()).into(),
+        );
+        tx.commit()?;
         tracing::trace!(
             "updated signed refs at {} to {}: {:?}",
             branch,
             commit_id,
             signed_refs.refs
         );
+        storage.emit(storage::events::Event::SignedRefsUpdated {
+            urn: urn.clone(),
+            peer: *storage.peer_id(),
+        });
 
         Ok(Some(signed_refs.refs))
     }
@@ -400,6 +495,7 @@ impl Refs {
             tags,
             notes,
             remotes: _,
+            seq: _,
         } = self;
         heads
             .iter()
@@ -420,6 +516,56 @@ impl<V> From<Signed<V>> for Refs {
     }
 }
 
+/// A previously published state of `rad/signed_refs`, as yielded by
+/// [`History`].
+#[derive(Clone, Debug)]
+pub struct Published {
+    /// The `rad/signed_refs` commit.
+    pub commit: Oid,
+    /// The commit time.
+    pub time: git2::Time,
+    /// The verified [`Refs`].
+    pub refs: Refs,
+}
+
+/// Iterator over the history of `rad/signed_refs`, see [`Refs::history`].
+pub struct History<'a> {
+    repo: &'a git2::Repository,
+    signer: PeerId,
+    walk: Option<git2::Revwalk<'a>>,
+}
+
+impl<'a> History<'a> {
+    fn published(&self, oid: git2::Oid) -> Result<Published, stored::Error> {
+        let commit = self.repo.find_commit(oid)?;
+        let blob = commit
+            .tree()?
+            .get_path(Path::new(stored::BLOB_PATH))?
+            .to_object(self.repo)?
+            .peel_to_blob()?;
+        // Older states naturally have lower sequence numbers
+        let refs = Signed::from_json(blob.content(), &self.signer, None)?.refs;
+
+        Ok(Published {
+            commit: oid.into(),
+            time: commit.time(),
+            refs,
+        })
+    }
+}
+
+impl<'a> Iterator for History<'a> {
+    type Item = Result<Published, stored::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let oid = match self.walk.as_mut()?.next()? {
+            Ok(oid) => oid,
+            Err(e) => return Some(Err(e.into())),
+        };
+        Some(self.published(oid))
+    }
+}
+
 pub mod signed {
     use super::*;
 
@@ -429,6 +575,12 @@ pub mod signed {
         #[error("invalid signature")]
         InvalidSignature(Refs),
 
+        #[error("sequence number went backwards from {previous} to {received}")]
+        Rollback { previous: u64, received: u64 },
+
+        #[error("different refs published with the same sequence number {seq}")]
+        Equivocation { seq: u64 },
+
         #[error(transparent)]
         Json(#[from] serde_json::error::Error),
 
@@ -455,6 +607,10 @@ pub struct Unverified;
 /// [`PeerId`], using [`Signed::verify`]. A shorthand for verifying bytes with a
 /// `PeerId` is given by [`Signed::from_json`].
 ///
+/// Both take the state previously seen from the same [`PeerId`], if any, and
+/// reject states with a lower [`Refs::seq`], or with the same [`Refs::seq`] but
+/// different contents.
+///
 /// Note that we may only persist a `Signed<Verified>`, and can only deserialize
 /// a `Signed<Unverified>`.
 pub struct Signed<V> {
@@ -464,14 +620,40 @@ pub struct Signed<V> {
 }
 
 impl Signed<Verified> {
-    pub fn from_json(data: &[u8], signer: &PeerId) -> Result<Self, signed::Error> {
+    pub fn from_json(
+        data: &[u8],
+        signer: &PeerId,
+        previous: Option<&Refs>,
+    ) -> Result<Self, signed::Error> {
         let unknown = serde_json::from_slice(data)?;
-        Self::verify(unknown, signer)
+        Self::verify(unknown, signer, previous)
     }
 
-    pub fn verify(unknown: Signed<Unverified>, signer: &PeerId) -> Result<Self, signed::Error> {
+    pub fn verify(
+        unknown: Signed<Unverified>,
+        signer: &PeerId,
+        previous: Option<&Refs>,
+    ) -> Result<Self, signed::Error> {
         let canonical = unknown.refs.canonical_form()?;
         if unknown.signature.verify(&canonical, &*signer) {
+            if let Some(previous) = previous {
+                let received = unknown.refs.seq;
+                if received < previous.seq {
+                    return Err(signed::Error::Rollback {
+                        previous: previous.seq,
+                        received,
+                    });
+                }
+                // States published before sequence numbers were introduced
+                // all have a `seq` of `0`
+                if received == previous.seq
+                    && received > 0
+                    && canonical != previous.canonical_form()?
+                {
+                    return Err(signed::Error::Equivocation { seq: received });
+                }
+            }
+
             Ok(Signed {
                 refs: unknown.refs,
                 signature: unknown.signature,
@@ -15,20 +15,37 @@ use std_ext::result::ResultExt as _;
 use thiserror::Error;
 
 use super::{
+    blocklist,
     fetch,
+    hooks,
     identities::{self, local::LocalIdentity},
     refs::{self, Refs},
-    storage::{self, Storage},
+    storage::{self, glob, transaction, Storage},
     tracking,
     types::{reference, Force, Namespace, Reference},
 };
 use crate::{
-    identities::git::{Person, Project, Revision, SomeIdentity, VerifiedPerson, VerifiedProject},
+    identities::git::{
+        error::History,
+        Person,
+        Project,
+        Revision,
+        SomeIdentity,
+        VerifiedIdentity,
+        VerifiedPerson,
+        VerifiedProject,
+    },
     peer::PeerId,
 };
 
 pub use crate::identities::git::Urn;
 
+pub mod filter;
+pub mod progress;
+
+mod plan;
+pub use plan::{plan, Change, Plan};
+
 #[derive(Debug, Error)]
 #[non_exhaustive]
 pub enum Error {
@@ -53,15 +70,37 @@ pub enum Error {
         source: reference::FromUrnError,
     },
 
+    #[error("refusing to replicate from blocked peer {0}")]
+    Blocked(PeerId),
+
     #[error("fork detected between `{mine}` and `{theirs}`")]
     Fork { mine: Urn, theirs: Urn },
 
+    #[error(
+        "`{remote_peer}` served outdated rad/signed_refs of {} peer(s) for `{urn}`",
+        .rolled_back.len()
+    )]
+    Rollback {
+        urn: Urn,
+        remote_peer: PeerId,
+        rolled_back: BTreeMap<PeerId, Rollback>,
+    },
+
     #[error(transparent)]
     Refs(#[from] refs::stored::Error),
 
     #[error(transparent)]
     Track(#[from] tracking::Error),
 
+    #[error(transparent)]
+    Blocklist(#[from] blocklist::Error),
+
+    #[error(transparent)]
+    Filter(#[from] filter::Error),
+
+    #[error(transparent)]
+    Transaction(#[from] transaction::Error),
+
     #[error("signer error: {0}")]
     Sign(#[source] Box<dyn std::error::Error + Send + Sync + 'static>),
 
@@ -75,19 +114,41 @@ pub enum Error {
     Store(#[from] storage::Error),
 }
 
+/// The `rad/signed_refs` of a peer which went backwards, see
+/// [`Error::Rollback`].
+///
+/// `received` is equal to `previous` if the peer published different refs
+/// with the same [`Refs::seq`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rollback {
+    pub previous: u64,
+    pub received: u64,
+}
+
 impl From<identities::error::Error> for Error {
     fn from(e: identities::error::Error) -> Self {
         Self::Identities(Box::new(e))
     }
 }
 
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct Config {
     pub fetch_limit: fetch::Limit,
+    /// Restrict the refs replicated for the given [`Urn`]s.
+    ///
+    /// A [`filter::Filter`] given here is persisted once the [`Urn`] was
+    /// replicated successfully, so subsequent replications honour it even if
+    /// it is not given again. [`Urn`]s for which no filter was ever given are
+    /// replicated in full.
+    pub filters: BTreeMap<Urn, filter::Filter<PeerId>>,
+    /// Receives the [`progress::Progress`] of the replication.
+    pub progress: progress::Reporter,
+    /// Run after a successful replication, see [`hooks::Event::Replicated`].
+    pub hooks: hooks::Hooks,
 }
 
 /// The success outcome of [`self::replicate`].
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct ReplicateResult {
     /// The set of refs which were updated during the sync, along with the
     /// [`ext::Oid`] they are now pointing to.
@@ -105,7 +166,7 @@ pub struct ReplicateResult {
 }
 
 /// The "freshness" of the local view of a repo identity wrt the delegates.
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum IdStatus {
     /// Up-to-date, no further action is required.
     Even,
@@ -115,7 +176,7 @@ pub enum IdStatus {
 }
 
 /// The "mode" `replicate` was operating in.
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum Mode {
     /// The git tree corresponding to [`Urn`] was previously **not** present
     /// locally, so the operation was equivalent to `git clone`.
@@ -152,6 +213,10 @@ enum ModeInternal {
 ///    also update local tracking relationships based on the identity
 ///    information.
 ///
+///    The `rad/signed_refs` fetched in this stage must not go back in their
+///    [`Refs::seq`], otherwise [`Error::Rollback`] is returned before any
+///    heads are fetched.
+///
 /// 2. Fetch the `rad/signed_refs` of all tracked peers, and compute the
 ///    eligible heads (i.e. where the `remote_peer` advertises the same tip
 ///    oid as found in the signed refs)
@@ -184,14 +249,25 @@ where
     if local_peer_id == &remote_peer {
         return Err(Error::SelfReplication);
     }
+    if blocklist::is_blocked(storage, remote_peer)? {
+        return Err(Error::Blocked(remote_peer));
+    }
     let urn = Urn::new(fetcher.urn().id);
+    let filter = match config.filters.get(&urn) {
+        Some(filter) => filter.clone(),
+        None => filter::get(storage, &urn)?,
+    };
+    let signed_refs_before = load_signed_refs(storage, &urn)?;
     let (mut updated_tips, next) = determine_mode(
         storage,
         &mut fetcher,
         config.fetch_limit,
+        &config.progress,
         urn.clone(),
         remote_peer,
     )?;
+    // Only identity branches and `rad/signed_refs` have been fetched so far
+    guard_rollback(storage, &urn, remote_peer, &signed_refs_before)?;
     let (result, mut remove) = match next {
         ModeInternal::Clone {
             urn,
@@ -214,12 +290,14 @@ where
                         storage,
                         &mut fetcher,
                         config.fetch_limit,
+                        &filter,
+                        &config.progress,
                         delegates,
                         &rad_id,
                         proj,
                     )?;
                     updated_tips.append(&mut project_tips);
-                    let tracked = tracking::tracked(storage, &urn)?.collect::<BTreeSet<_>>();
+                    let tracked = tracking::tracked_peers(storage, &urn)?.collect::<BTreeSet<_>>();
                     allowed.extend(tracked);
 
                     (allowed, id_status)
@@ -273,6 +351,8 @@ where
                         &storage,
                         &mut fetcher,
                         config.fetch_limit,
+                        &filter,
+                        &config.progress,
                         delegate_views,
                         &rad_id,
                         proj,
@@ -280,7 +360,7 @@ where
                     updated_tips.append(&mut project_tips);
 
                     let mut updated_tracked =
-                        tracking::tracked(storage, &urn)?.collect::<BTreeSet<_>>();
+                        tracking::tracked_peers(storage, &urn)?.collect::<BTreeSet<_>>();
                     updated_tracked.append(&mut updated_delegations);
                     (
                         ReplicateResult {
@@ -300,7 +380,7 @@ where
                             identity: id_status,
                             mode: Mode::Fetch,
                         },
-                        tracking::tracked(storage, &urn)?.collect::<BTreeSet<_>>(),
+                        tracking::tracked_peers(storage, &urn)?.collect::<BTreeSet<_>>(),
                     )
                 },
             };
@@ -310,12 +390,33 @@ where
         },
     }?;
 
+    // The last stage fetches the `rad/signed_refs` of the remote peer once more,
+    // which may have moved since. The heads fetched are the ones signed in the
+    // state checked above, so only the branch itself needs guarding.
+    guard_rollback(storage, &urn, remote_peer, &signed_refs_before)?;
+
     // Ensure we're not tracking ourselves
     remove.insert(*local_peer_id);
 
     // Remove any remote tracking branches we don't need
     prune(storage, &urn, remove.iter())?;
 
+    // Only persist the filter once it was applied successfully
+    if let Some(filter) = config.filters.get(&urn) {
+        filter::set(storage, &urn, filter)?;
+    }
+
+    emit_events(storage, &urn, &result, &signed_refs_before)?;
+
+    // Don't hold on to the storage while the hooks run
+    if !config.hooks.is_empty() {
+        config.hooks.spawn(hooks::Event::Replicated {
+            urn,
+            remote_peer,
+            result: result.clone(),
+        });
+    }
+
     // TODO: At this point, the tracking graph may have changed, and/or we
     // created top-level person namespaces. We will eventually converge, but
     // perhaps we'd want to return some kind of continuation here, so the caller
@@ -342,6 +443,7 @@ fn determine_mode<F>(
     storage: &Storage,
     fetcher: &mut F,
     limit: fetch::Limit,
+    progress: &progress::Reporter,
     urn: Urn,
     remote_peer: PeerId,
 ) -> Result<(BTreeMap<ext::RefLike, ext::Oid>, ModeInternal), Error>
@@ -350,20 +452,20 @@ where
     F::Error: std::error::Error + Send + Sync + 'static,
 {
     if !storage.has_urn(&urn)? {
-        let updated = fetcher
-            .fetch(fetch::Fetchspecs::PeekAll { limit })
-            .map_err(|e| Error::Fetch(e.into()))?;
+        let updated = fetch_reporting(fetcher, fetch::Fetchspecs::PeekAll { limit }, progress)?;
         let fetched_peers = project::fetched_peers(&updated)?;
 
         let mut tips = updated.updated_tips;
         // We can't fetch `refs/remotes/*/rad/ids/*` since we can't have two globs, so
         // we fetch `refs/remotes/{fetched_peer}/rad/ids/*`.
-        let peeked = fetcher
-            .fetch(fetch::Fetchspecs::Peek {
+        let peeked = fetch_reporting(
+            fetcher,
+            fetch::Fetchspecs::Peek {
                 remotes: fetched_peers.clone(),
                 limit,
-            })
-            .map_err(|e| Error::Fetch(e.into()))?;
+            },
+            progress,
+        )?;
         tips.extend(peeked.updated_tips);
 
         let remote_ident =
@@ -382,20 +484,25 @@ where
         let existing = match identity {
             SomeIdentity::Project(ref proj) => {
                 let mut remotes = project::all_delegates(&proj);
-                let mut tracked = tracking::tracked(storage, &urn)?.collect::<BTreeSet<_>>();
+                let mut tracked = tracking::tracked_peers(storage, &urn)?.collect::<BTreeSet<_>>();
                 remotes.append(&mut tracked);
 
                 remotes
             },
-            SomeIdentity::Person(_) => tracking::tracked(storage, &urn)?.collect::<BTreeSet<_>>(),
+            SomeIdentity::Person(_) => {
+                tracking::tracked_peers(storage, &urn)?.collect::<BTreeSet<_>>()
+            },
         };
 
-        let fetch::FetchResult { updated_tips } = fetcher
-            .fetch(fetch::Fetchspecs::Peek {
-                remotes: existing.clone(),
-                limit,
-            })
-            .map_err(|e| Error::Fetch(e.into()))?;
+        // Also peek at `remote_peer`, so its `rad/signed_refs` can be checked for
+        // rollbacks before any heads are fetched
+        let mut remotes = existing.clone();
+        remotes.insert(remote_peer);
+        let fetch::FetchResult { updated_tips } = fetch_reporting(
+            fetcher,
+            fetch::Fetchspecs::Peek { remotes, limit },
+            progress,
+        )?;
 
         Ok((
             updated_tips,
@@ -408,6 +515,79 @@ where
     }
 }
 
+/// Run `fetchspecs` through `fetcher`, reporting the [`progress::Stage`] they
+/// correspond to and the transfer progress to `progress`.
+fn fetch_reporting<F>(
+    fetcher: &mut F,
+    fetchspecs: fetch::Fetchspecs<PeerId, F::UrnId>,
+    progress: &progress::Reporter,
+) -> Result<fetch::FetchResult, Error>
+where
+    F: fetch::Fetcher<PeerId = PeerId>,
+    F::Error: std::error::Error + Send + Sync + 'static,
+{
+    progress.report(progress::Progress::Stage(progress::Stage::from(
+        &fetchspecs,
+    )));
+    fetcher
+        .fetch_with_progress(fetchspecs, &mut |transfer| {
+            progress.report(progress::Progress::Transfer(transfer))
+        })
+        .map_err(|e| Error::Fetch(e.into()))
+}
+
+/// Like [`tracking::track`], but skip peers on the [`blocklist`] instead of
+/// failing.
+fn track_unless_blocked(storage: &Storage, urn: &Urn, peer: PeerId) -> Result<(), Error> {
+    if blocklist::is_blocked(storage, peer)? {
+        tracing::warn!(peer = %peer, "not tracking blocked peer");
+        return Ok(());
+    }
+    tracking::track_with(storage, urn, peer, tracking::Initiator::Replication)?;
+    Ok(())
+}
+
+/// Restrict `filter` by the [`tracking::Policy`] of each tracked peer.
+///
+/// Globs given for a peer in `filter` take precedence over the ones of its
+/// policy.
+fn apply_policies(
+    filter: &fetch::Filter<PeerId>,
+    policies: &BTreeMap<PeerId, tracking::Policy>,
+) -> fetch::Filter<PeerId> {
+    let mut filter = filter.clone();
+    for (peer, policy) in policies {
+        if let Some(refs) = &policy.refs {
+            filter
+                .peers
+                .entry(*peer)
+                .or_insert_with(|| fetch::Globs::from(refs.clone()));
+        }
+    }
+    filter
+}
+
+/// Pick the side of the fork between `left` and `right` adopted via
+/// [`identities::fork::resolve`].
+///
+/// If no side was adopted, the fork is reported as constructed by `fork`.
+fn adopted_side<T, E>(
+    storage: &Storage,
+    urn: &Urn,
+    left: VerifiedIdentity<T>,
+    right: VerifiedIdentity<T>,
+    fork: E,
+) -> Result<VerifiedIdentity<T>, Error>
+where
+    E: FnOnce(VerifiedIdentity<T>, VerifiedIdentity<T>) -> identities::Error,
+{
+    match identities::fork::choose(storage, urn, left.content_id, right.content_id)? {
+        Some(tip) if tip == left.content_id => Ok(left),
+        Some(_) => Ok(right),
+        None => Err(fork(left, right).into()),
+    }
+}
+
 fn unsafe_into_urn(reference: Reference<git_ext::RefLike>) -> Urn {
     reference.try_into().expect("namespace is set")
 }
@@ -419,22 +599,83 @@ fn unsafe_into_urn(reference: Reference<git_ext::RefLike>) -> Urn {
 #[tracing::instrument(level = "trace", skip(storage, urn), fields(urn = %urn), err)]
 fn ensure_rad_id(storage: &Storage, urn: &Urn, tip: ext::Oid) -> Result<ext::Oid, Error> {
     let id_ref = identities::common::IdRef::from(urn);
-    id_ref
-        .create(storage, tip)
-        .map_err(|e| Error::Store(e.into()))?;
-
-    id_ref
-        .oid(storage)
-        .map(Into::into)
-        .map_err(|e| Error::Store(e.into()))
+    let current = || -> Result<ext::Oid, Error> {
+        id_ref
+            .oid(storage)
+            .map(Into::into)
+            .map_err(|e| Error::Store(e.into()))
+    };
+
+    if storage.has_urn(urn)? {
+        return current();
+    }
+
+    let mut tx = storage.transaction(format!("Initial rad/id for {}", urn));
+    tx.create(&Reference::rad_id(Namespace::from(urn)), tip.into());
+    match tx.commit() {
+        Ok(()) => Ok(tip),
+        // Someone else created it in the meantime
+        Err(transaction::Error::Mismatch { .. }) => current(),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The tip of the local `rad/id` of `urn`, if it exists.
+fn rad_id_tip(storage: &Storage, urn: &Urn) -> Result<Option<ext::Oid>, Error> {
+    match identities::common::IdRef::from(urn).oid(storage) {
+        Ok(oid) => Ok(Some(oid.into())),
+        Err(e) if ext::is_not_found_err(&e) => Ok(None),
+        Err(e) => Err(Error::Store(e.into())),
+    }
+}
+
+/// Notify subscribers of the storage about the changes made by a replication.
+///
+/// The refs are updated in transactions, bypassing the code paths which
+/// otherwise emit [`storage::events::Event`]s, so we compare against the state
+/// before the replication instead.
+///
+/// The local `rad/id` is only written when cloning. Otherwise, it moves via
+/// [`identities::common::IdRef::update`], which emits
+/// [`storage::events::Event::IdentityUpdated`] itself.
+fn emit_events(
+    storage: &Storage,
+    urn: &Urn,
+    result: &ReplicateResult,
+    signed_refs_before: &BTreeMap<PeerId, (ext::Oid, Refs)>,
+) -> Result<(), Error> {
+    use storage::events::Event;
+
+    if let Mode::Clone = result.mode {
+        storage.emit(Event::UrnCreated { urn: urn.clone() });
+        if let Some(tip) = rad_id_tip(storage, urn)? {
+            storage.emit(Event::IdentityUpdated {
+                urn: urn.clone(),
+                tip,
+            });
+        }
+    }
+    for (peer, (tip, _)) in load_signed_refs(storage, urn)? {
+        if signed_refs_before.get(&peer).map(|(before, _)| before) != Some(&tip) {
+            storage.emit(Event::SignedRefsUpdated {
+                urn: urn.clone(),
+                peer,
+            });
+        }
+    }
+
+    Ok(())
 }
 
 /// Untrack the list of `PeerId`s, which also has the side-effect of removing
 /// that peer's remote references in the storage.
 ///
-/// **Note**: this function will return early on failure. This could mean that
-/// remotes which were meant for pruning might not have been removed, resulting
-/// in unnecessary remote references.
+/// The remote references of all peers in `prune_list` are removed in a single
+/// [`storage::Transaction`].
+///
+/// Peers are only untracked after the transaction was committed. This function
+/// will return early on failure, so some peers may remain tracked while their
+/// remote references were removed. They are restored by the next fetch.
 #[allow(clippy::unit_arg)]
 #[tracing::instrument(
     level = "trace",
@@ -447,8 +688,15 @@ fn prune<'a>(
     urn: &Urn,
     prune_list: impl Iterator<Item = &'a PeerId>,
 ) -> Result<(), Error> {
-    for peer in prune_list {
-        match tracking::untrack(storage, urn, *peer) {
+    let mut tx = storage.transaction(format!("prune {}", urn));
+    let untracking = prune_list
+        .map(|peer| tracking::untrack_in(storage, &mut tx, urn, *peer))
+        .collect::<Result<Vec<_>, _>>()?;
+    tx.commit()?;
+
+    for untracking in untracking {
+        let peer = *untracking.peer();
+        match untracking.finish(storage) {
             Ok(removed) => {
                 if removed {
                     tracing::info!(peer = %peer, "pruned");
@@ -462,9 +710,143 @@ fn prune<'a>(
             },
         }
     }
+
     Ok(())
 }
 
+/// The tip and [`Refs`] of the `rad/signed_refs` of every remote of `urn`.
+///
+/// Remotes whose `rad/signed_refs` fail to load are skipped.
+fn load_signed_refs(
+    storage: &Storage,
+    urn: &Urn,
+) -> Result<BTreeMap<PeerId, (ext::Oid, Refs)>, Error> {
+    let remotes = reflike!("refs/namespaces")
+        .join(urn)
+        .join(reflike!("refs/remotes"));
+    let signed_refs = glob::RefspecMatcher::from(
+        remotes.with_pattern_suffix(refspec_pattern!("*/rad/signed_refs")),
+    );
+
+    let mut loaded = BTreeMap::new();
+    for reference in storage.references_glob(signed_refs)? {
+        let reference = reference?;
+        let peer = reference
+            .name()
+            .and_then(|name| name.strip_prefix(&format!("{}/", remotes.as_str())))
+            .and_then(|suffix| suffix.split('/').next())
+            .and_then(|peer| peer.parse::<PeerId>().ok());
+        let (peer, tip) = match (peer, reference.target()) {
+            (Some(peer), Some(tip)) => (peer, tip),
+            _ => continue,
+        };
+        match Refs::load(storage, urn, peer) {
+            Ok(Some(refs)) => {
+                loaded.insert(peer, (tip.into(), refs));
+            },
+            Ok(None) => {},
+            Err(e) => tracing::warn!(peer = %peer, err = %e, "invalid signed refs"),
+        }
+    }
+
+    Ok(loaded)
+}
+
+/// Ensure none of the `rad/signed_refs` recorded in `before` went backwards,
+/// ie. were replaced by a state which doesn't supersede it (see
+/// [`refs::Signed::verify`]).
+///
+/// If any did, they are all reset to their previous tips in a single
+/// [`storage::Transaction`], and [`Error::Rollback`] is returned.
+#[tracing::instrument(level = "trace", skip(storage, urn, before), fields(urn = %urn), err)]
+fn guard_rollback(
+    storage: &Storage,
+    urn: &Urn,
+    remote_peer: PeerId,
+    before: &BTreeMap<PeerId, (ext::Oid, Refs)>,
+) -> Result<(), Error> {
+    let rolled_back = rollbacks(storage, urn, before)?;
+    if rolled_back.is_empty() {
+        return Ok(());
+    }
+
+    let mut tx = storage.transaction(format!("Reject rollback of rad/signed_refs of {}", urn));
+    for (peer, (received_tip, _)) in &rolled_back {
+        let (tip, _) = &before[peer];
+        let name = Reference::rad_signed_refs(Namespace::from(urn), Some(*peer));
+        tx.update(&name, **tip, transaction::Expected::Oid(**received_tip));
+    }
+    tx.commit()?;
+
+    Err(Error::Rollback {
+        urn: urn.clone(),
+        remote_peer,
+        rolled_back: rolled_back
+            .into_iter()
+            .map(|(peer, (_, rollback))| (peer, rollback))
+            .collect(),
+    })
+}
+
+/// Like [`guard_rollback`], but without resetting the `rad/signed_refs` which
+/// went backwards.
+fn check_rollback(
+    storage: &Storage,
+    urn: &Urn,
+    remote_peer: PeerId,
+    before: &BTreeMap<PeerId, (ext::Oid, Refs)>,
+) -> Result<(), Error> {
+    let rolled_back = rollbacks(storage, urn, before)?;
+    if rolled_back.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Rollback {
+            urn: urn.clone(),
+            remote_peer,
+            rolled_back: rolled_back
+                .into_iter()
+                .map(|(peer, (_, rollback))| (peer, rollback))
+                .collect(),
+        })
+    }
+}
+
+/// The `rad/signed_refs` recorded in `before` which went backwards, along with
+/// the tips they point to now.
+fn rollbacks(
+    storage: &Storage,
+    urn: &Urn,
+    before: &BTreeMap<PeerId, (ext::Oid, Refs)>,
+) -> Result<BTreeMap<PeerId, (ext::Oid, Rollback)>, Error> {
+    let mut rolled_back = BTreeMap::new();
+    for (peer, (_, previous)) in before {
+        let name = Reference::rad_signed_refs(Namespace::from(urn), Some(*peer));
+        // Read the tip first, so it is the one judged by `load_since`, or one
+        // which went backwards even further
+        let tip = match storage.reference(&name)?.and_then(|r| r.target()) {
+            Some(tip) => ext::Oid::from(tip),
+            None => continue,
+        };
+        let rollback = match Refs::load_since(storage, urn, *peer, Some(previous)) {
+            Ok(_) => continue,
+            Err(refs::stored::Error::Signed(refs::signed::Error::Rollback {
+                previous,
+                received,
+            })) => Rollback { previous, received },
+            Err(refs::stored::Error::Signed(refs::signed::Error::Equivocation { seq })) => {
+                Rollback {
+                    previous: seq,
+                    received: seq,
+                }
+            },
+            Err(e) => return Err(e.into()),
+        };
+        rolled_back.insert(*peer, (tip, rollback));
+    }
+
+    Ok(rolled_back)
+}
+
 // Allowing dead code to keep the other fields
 #[allow(dead_code)]
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -533,7 +915,7 @@ mod person {
                 // Track all delegations
                 for peer_id in delegations.iter() {
                     if peer_id != local_peer {
-                        tracking::track(storage, &urn, *peer_id)?;
+                        track_unless_blocked(storage, &urn, *peer_id)?;
                     }
                 }
 
@@ -572,9 +954,14 @@ mod person {
             for pers in delegates.values().cloned() {
                 match prev {
                     None => prev = Some(pers),
-                    Some(p) => {
-                        let newer = identities::person::newer(storage, p, pers)?;
-                        prev = Some(newer);
+                    Some(p) => match identities::person::newer(storage, p, pers) {
+                        Ok(newer) => prev = Some(newer),
+                        Err(identities::Error::PersHist(History::Fork { left, right })) => {
+                            prev = Some(adopted_side(storage, urn, left, right, |l, r| {
+                                identities::Error::PersHist(History::Fork { left: l, right: r })
+                            })?)
+                        },
+                        Err(e) => return Err(e.into()),
                     },
                 }
             }
@@ -625,6 +1012,8 @@ mod project {
         storage: &Storage,
         fetcher: &mut F,
         limit: fetch::Limit,
+        filter: &fetch::Filter<PeerId>,
+        progress: &progress::Reporter,
         delegates: BTreeMap<PeerId, project::DelegateView>,
         rad_id: &Urn,
         proj: VerifiedProject,
@@ -642,6 +1031,8 @@ mod project {
             storage,
             fetcher,
             limit,
+            filter,
+            progress,
             &urn,
             delegates
                 .values()
@@ -650,7 +1041,7 @@ mod project {
         )?;
         for peer in tracked {
             if peer != *local_peer {
-                tracking::track(&storage, &urn, peer)?;
+                track_unless_blocked(&storage, &urn, peer)?;
             }
         }
 
@@ -662,6 +1053,13 @@ mod project {
 
     /// Fetch `rad/signed_refs` and `refs/heads` of the delegates and our
     /// tracked graph, returning the set of tracked peers.
+    ///
+    /// Only the refs allowed by `filter` and the [`tracking::Policy`] of each
+    /// tracked peer are fetched, and the data fetched from the remote peer is
+    /// limited by its policy. The peers tracked by a tracked peer are only
+    /// returned if its policy is transitive. Peers on the [`blocklist`] are
+    /// neither fetched nor returned. Once fetched, the completion of every
+    /// tracked peer is reported to `progress`.
     #[tracing::instrument(
         level = "trace",
         skip(storage, fetcher, urn),
@@ -672,6 +1070,8 @@ mod project {
         storage: &Storage,
         fetcher: &mut F,
         limit: fetch::Limit,
+        filter: &fetch::Filter<PeerId>,
+        progress: &progress::Reporter,
         urn: &Urn,
         delegates: BTreeSet<Urn>,
     ) -> Result<(fetch::FetchResult, BTreeSet<PeerId>), Error>
@@ -679,10 +1079,13 @@ mod project {
         F: fetch::Fetcher<PeerId = PeerId, UrnId = Revision>,
         F::Error: std::error::Error + Send + Sync + 'static,
     {
-        // Read `signed_refs` for all tracked
-        let tracked = tracking::tracked(storage, &urn)?.collect::<BTreeSet<_>>();
-        let tracked_sigrefs = tracked
-            .into_iter()
+        // Read `signed_refs` for all tracked, except blocked peers
+        let policies = tracking::policies(storage, &urn)?;
+        let blocked = blocklist::blocked(storage)?;
+        let tracked_sigrefs = policies
+            .keys()
+            .copied()
+            .filter(|peer| !blocked.contains_key(peer))
             .filter_map(|peer| match Refs::load(storage, &urn, peer) {
                 Ok(Some(refs)) => Some(Ok((peer, refs))),
 
@@ -693,20 +1096,52 @@ mod project {
 
         // Fetch all the rest
         tracing::debug!("fetching heads: {:?}, {:?}", tracked_sigrefs, delegates);
-        let res = fetcher
-            .fetch(fetch::Fetchspecs::Replicate {
+        let res = fetch_reporting(
+            fetcher,
+            fetch::Fetchspecs::Replicate {
                 tracked_sigrefs: tracked_sigrefs.clone(),
                 delegates,
-                limit,
-            })
-            .map_err(|e| Error::Fetch(e.into()))?;
+                filter: apply_policies(filter, &policies),
+                limit: match policies
+                    .get(fetcher.remote_peer())
+                    .and_then(|policy| policy.data_limit)
+                {
+                    Some(data) => fetch::Limit {
+                        data: limit.data.min(data),
+                        ..limit
+                    },
+                    None => limit,
+                },
+            },
+            progress,
+        )?;
+        let remotes = reflike!("refs/namespaces")
+            .join(urn)
+            .join(reflike!("refs/remotes"));
+        for peer in tracked_sigrefs.keys() {
+            let prefix = remotes.join(*peer);
+            let updated = res
+                .updated_tips
+                .keys()
+                .filter(|name| name.strip_prefix(&prefix).is_ok())
+                .count();
+            progress.report(progress::Progress::Peer {
+                peer: *peer,
+                updated,
+            });
+        }
 
         Refs::update(storage, &urn)?;
         Ok((
             res,
             tracked_sigrefs
                 .iter()
-                .flat_map(|(peer, refs)| iter::once(*peer).chain(refs.remotes.flatten().copied()))
+                .flat_map(|(peer, refs)| {
+                    let transitive = policies.get(peer).map_or(true, |policy| policy.transitive);
+                    iter::once(*peer)
+                        .chain(refs.remotes.flatten().copied().filter(move |_| transitive))
+                })
+                .filter(|peer| !blocked.contains_key(peer))
                 .collect(),
         ))
     }
@@ -775,8 +1210,8 @@ mod project {
     ) -> Result<(), Error> {
         let delegate_urn = person.urn();
         ensure_rad_id(storage, &delegate_urn, person.content_id)?;
-        tracking::track(storage, &delegate_urn, peer)?;
-        tracking::track(storage, &project_urn, peer)?;
+        track_unless_blocked(storage, &delegate_urn, peer)?;
+        track_unless_blocked(storage, &project_urn, peer)?;
 
         // Now point our view to the top-level
         Reference::try_from(&delegate_urn)
@@ -806,7 +1241,7 @@ mod project {
             .direct()
             .filter(|&key| key != local_peer_id.as_public_key())
         {
-            tracking::track(storage, &proj.urn(), PeerId::from(*key))?;
+            track_unless_blocked(storage, &proj.urn(), PeerId::from(*key))?;
         }
 
         Ok(())
@@ -829,9 +1264,14 @@ mod project {
             for proj in delegates.values().map(|view| view.project.clone()) {
                 match prev {
                     None => prev = Some(proj),
-                    Some(p) => {
-                        let newer = identities::project::newer(storage, p, proj)?;
-                        prev = Some(newer);
+                    Some(p) => match identities::project::newer(storage, p, proj) {
+                        Ok(newer) => prev = Some(newer),
+                        Err(identities::Error::ProjHist(History::Fork { left, right })) => {
+                            prev = Some(adopted_side(storage, urn, left, right, |l, r| {
+                                identities::Error::ProjHist(History::Fork { left: l, right: r })
+                            })?)
+                        },
+                        Err(e) => return Err(e.into()),
                     },
                 }
             }
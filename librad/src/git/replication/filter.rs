@@ -0,0 +1,164 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Persistence of [`Filter`]s.
+//!
+//! The filter for a [`Urn`] is stored in the storage's git config: the
+//! [`Filter::default`] globs as the multi-valued `replication.<urn>.ref`, and
+//! the [`Filter::peers`] globs as `replication.<urn>/<peer>.ref`.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    str::FromStr,
+};
+
+use thiserror::Error;
+
+use super::Urn;
+use crate::{
+    git::storage::{self, ReadOnly, Storage},
+    peer::PeerId,
+};
+
+pub use crate::git::fetch::{Filter, Globs};
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("invalid replication filter entry `{name} = {value}`")]
+    Entry { name: String, value: String },
+
+    #[error(transparent)]
+    Config(#[from] storage::config::Error),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+/// Read the [`Filter`] persisted for `urn`.
+///
+/// If none was persisted, the [`Default`] filter is returned.
+pub fn get(storage: &ReadOnly, urn: &Urn) -> Result<Filter<PeerId>, Error> {
+    let config = storage.as_raw().config()?;
+    let entries = config.entries(Some(&entries_regex(urn)))?;
+
+    let mut default = None;
+    let mut peers = BTreeMap::new();
+    for entry in &entries {
+        let entry = entry?;
+        let (name, value) = match (entry.name(), entry.value()) {
+            (Some(name), Some(value)) => (name, value),
+            _ => continue,
+        };
+        let invalid = || Error::Entry {
+            name: name.to_owned(),
+            value: value.to_owned(),
+        };
+
+        let glob = storage::config::parse_glob(value).map_err(|_| invalid())?;
+        let globs = match subsection(name, urn).ok_or_else(invalid)? {
+            None => default.get_or_insert_with(BTreeSet::new),
+            Some(peer) => peers.entry(peer).or_insert_with(BTreeSet::new),
+        };
+        globs.extend(glob);
+    }
+
+    Ok(Filter {
+        default: default.map(Globs::from),
+        peers: peers
+            .into_iter()
+            .map(|(peer, globs)| (peer, Globs::from(globs)))
+            .collect(),
+    })
+}
+
+/// Persist `filter` for `urn`, replacing any previously persisted one.
+pub fn set(storage: &Storage, urn: &Urn, filter: &Filter<PeerId>) -> Result<(), Error> {
+    let mut config = storage::Config::try_from(storage)?;
+    let config = config.as_raw_mut();
+
+    let mut existing = BTreeSet::new();
+    for entry in &config.entries(Some(&entries_regex(urn)))? {
+        if let Some(name) = entry?.name() {
+            existing.insert(name.to_owned());
+        }
+    }
+    for name in existing {
+        config.remove_multivar(&name, ".*")?;
+    }
+
+    let sections = filter
+        .default
+        .iter()
+        .map(|globs| (urn.encode_id(), globs))
+        .chain(
+            filter
+                .peers
+                .iter()
+                .map(|(peer, globs)| (format!("{}/{}", urn.encode_id(), peer), globs)),
+        );
+    for (subsection, globs) in sections {
+        let name = format!("replication.{}.ref", subsection);
+        storage::config::add_globs(config, &name, globs)?;
+    }
+
+    Ok(())
+}
+
+fn entries_regex(urn: &Urn) -> String {
+    format!(r"^replication\.{}(/[^.]+)?\.ref$", urn.encode_id())
+}
+
+/// Parse the peer from the subsection of a config entry name.
+///
+/// `None` if the entry is not well-formed, `Some(None)` if it applies to all
+/// peers.
+fn subsection(name: &str, urn: &Urn) -> Option<Option<PeerId>> {
+    let id = urn.encode_id();
+    let subsection = name
+        .strip_prefix("replication.")
+        .and_then(|name| name.strip_suffix(".ref"))?;
+    if subsection == id {
+        Some(None)
+    } else {
+        subsection
+            .strip_prefix(&format!("{}/", id))
+            .and_then(|peer| PeerId::from_str(peer).ok())
+            .map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{keys::SecretKey, paths::Paths};
+
+    #[test]
+    fn roundtrip() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(&tmp).unwrap();
+            let storage = Storage::open(&paths, SecretKey::new()).unwrap();
+            let urn = Urn::new(git2::Oid::zero().into());
+            let peer = PeerId::from(SecretKey::new());
+
+            assert_eq!(get(&storage, &urn).unwrap(), Filter::default());
+
+            let mut filter = Filter::only(vec![
+                refspec_pattern!("refs/heads/main"),
+                refspec_pattern!("refs/tags/*"),
+            ]);
+            filter.peers.insert(peer, Globs::default());
+            set(&storage, &urn, &filter).unwrap();
+            assert_eq!(get(&storage, &urn).unwrap(), filter);
+
+            let filter = Filter::only(Some(refspec_pattern!("refs/heads/*")));
+            set(&storage, &urn, &filter).unwrap();
+            assert_eq!(get(&storage, &urn).unwrap(), filter);
+        }
+    }
+}
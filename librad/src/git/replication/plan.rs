@@ -0,0 +1,351 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+};
+
+use git_ext as ext;
+
+use super::{
+    apply_policies,
+    check_rollback,
+    determine_mode,
+    filter,
+    load_signed_refs,
+    project,
+    Config,
+    Error,
+    Mode,
+    ModeInternal,
+    Urn,
+};
+use crate::{
+    git::{
+        blocklist,
+        fetch,
+        refs::Refs,
+        storage::{glob, transaction, Storage},
+        tracking,
+    },
+    identities::git::{Revision, SomeIdentity},
+    peer::PeerId,
+};
+
+/// A change to a ref which [`super::replicate`] would make.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    Create {
+        name: ext::RefLike,
+        target: ext::Oid,
+    },
+    Update {
+        name: ext::RefLike,
+        from: ext::Oid,
+        to: ext::Oid,
+    },
+    Delete {
+        name: ext::RefLike,
+        target: ext::Oid,
+    },
+}
+
+/// The outcome of [`plan`].
+#[derive(Debug)]
+pub struct Plan {
+    /// The [`Mode`] [`super::replicate`] would operate in.
+    pub mode: Mode,
+    /// The [`Change`]s to the remote tracking branches of each peer, including
+    /// their `rad/*` branches.
+    pub changes: BTreeMap<PeerId, Vec<Change>>,
+}
+
+impl Plan {
+    /// `true` if replicating would not change any refs.
+    pub fn is_empty(&self) -> bool {
+        self.changes.values().all(Vec::is_empty)
+    }
+}
+
+/// Determine what [`super::replicate`] would do, without fetching any data
+/// beyond [`fetch::Limit::peek`].
+///
+/// The identity and `rad/signed_refs` stages are run as usual (and subject to
+/// the same rollback protection), but the refs they fetch are reset before
+/// returning, so the refs in `storage` are left unchanged. The objects fetched
+/// remain until they are garbage collected. The changes to the `rad/*`
+/// branches are reported as part of the [`Plan`].
+///
+/// The data stage is only simulated: the heads, tags and notes claimed by the
+/// peeked `rad/signed_refs` are compared to the [`fetch::RemoteHeads`]
+/// advertised by the remote peer, and to what is present locally. Only refs
+/// allowed by the [`filter::Filter`] in effect are considered, restricted by
+/// the [`tracking::Policy`] of each tracked peer, but the filter given in
+/// `config` is not persisted.
+///
+/// The result is an estimate: the peers to replicate from are determined
+/// from the current tracking relationships and the delegations of the peeked
+/// identity, without adopting it.
+#[allow(clippy::unit_arg)]
+#[tracing::instrument(skip(storage, fetcher), err)]
+pub fn plan<F>(storage: &Storage, mut fetcher: F, config: Config) -> Result<Plan, Error>
+where
+    F: fetch::Fetcher<PeerId = PeerId, UrnId = Revision>,
+    F::Error: std::error::Error + Send + Sync + 'static,
+{
+    let remote_peer = *fetcher.remote_peer();
+    let local_peer = *storage.peer_id();
+    if local_peer == remote_peer {
+        return Err(Error::SelfReplication);
+    }
+    if blocklist::is_blocked(storage, remote_peer)? {
+        return Err(Error::Blocked(remote_peer));
+    }
+    let urn = Urn::new(fetcher.urn().id);
+    let filter = match config.filters.get(&urn) {
+        Some(filter) => filter.clone(),
+        None => filter::get(storage, &urn)?,
+    };
+    let filter = apply_policies(&filter, &tracking::policies(storage, &urn)?);
+
+    let namespace = reflike!("refs/namespaces")
+        .join(&urn)
+        .join(reflike!("refs"));
+    let remotes = namespace.join(reflike!("remotes"));
+    let before = snapshot(storage, &remotes)?;
+
+    let signed_refs_before = load_signed_refs(storage, &urn)?;
+    let planned = determine_mode(
+        storage,
+        &mut fetcher,
+        config.fetch_limit,
+        &config.progress,
+        urn.clone(),
+        remote_peer,
+    )
+    .and_then(|(_, next)| {
+        check_rollback(storage, &urn, remote_peer, &signed_refs_before)?;
+        Ok(next)
+    })
+    .and_then(|next| {
+        let after = snapshot(storage, &remotes)?;
+        let planned = changes(
+            storage, &fetcher, &filter, &urn, &namespace, &remotes, &before, &after, next,
+        )?;
+        Ok((planned, after))
+    });
+
+    // Undo the peek, whether planning succeeded or not
+    let after = match &planned {
+        Ok((_, after)) => after.clone(),
+        Err(_) => snapshot(storage, &remotes)?,
+    };
+    reset(storage, &urn, &before, &after)?;
+
+    planned.map(|(plan, _)| plan)
+}
+
+/// Compute the [`Plan`] from the state of the remote tracking branches
+/// `before` and `after` peeking.
+#[allow(clippy::too_many_arguments)]
+fn changes<F>(
+    storage: &Storage,
+    fetcher: &F,
+    filter: &fetch::Filter<PeerId>,
+    urn: &Urn,
+    namespace: &ext::RefLike,
+    remotes: &ext::RefLike,
+    before: &BTreeMap<ext::RefLike, ext::Oid>,
+    after: &BTreeMap<ext::RefLike, ext::Oid>,
+    next: ModeInternal,
+) -> Result<Plan, Error>
+where
+    F: fetch::Fetcher<PeerId = PeerId, UrnId = Revision>,
+{
+    let remote_peer = *fetcher.remote_peer();
+    let local_peer = *storage.peer_id();
+    let (mode, identity) = match next {
+        ModeInternal::Clone { identity, .. } => (Mode::Clone, identity),
+        ModeInternal::Fetch { identity, .. } => (Mode::Fetch, identity),
+    };
+
+    let blocked = blocklist::blocked(storage)?;
+    let mut keep = tracking::tracked_peers(storage, urn)?.collect::<BTreeSet<_>>();
+    let replicated = match identity {
+        SomeIdentity::Project(proj) => {
+            let delegates = project::all_delegates(&proj);
+            for delegate in &delegates {
+                if let Some(refs) = Refs::load(storage, urn, *delegate)? {
+                    keep.extend(refs.remotes.flatten().copied());
+                }
+            }
+            keep.extend(delegates);
+            keep.remove(&local_peer);
+            keep.clone()
+        },
+        // Only the identity branches are replicated for persons
+        SomeIdentity::Person(person) => {
+            keep.extend(person.delegations().iter().copied().map(PeerId::from));
+            keep.remove(&local_peer);
+            BTreeSet::new()
+        },
+    };
+
+    let mut local = by_peer(remotes, before);
+    let mut peeked = by_peer(remotes, after);
+    let remote_heads = fetcher.remote_heads();
+
+    let mut changes = BTreeMap::new();
+    for peer in &keep {
+        if blocked.contains_key(peer) {
+            continue;
+        }
+        let existing = local.remove(peer).unwrap_or_default();
+        let mut peer_changes = Vec::new();
+
+        // The `rad/*` branches fetched by peeking
+        for (name, target) in peeked.remove(peer).unwrap_or_default() {
+            if let Some(change) = change(&existing, name, target) {
+                peer_changes.push(change);
+            }
+        }
+
+        let refs = match Refs::load(storage, urn, *peer)? {
+            Some(refs) if replicated.contains(peer) => refs,
+            _ => {
+                changes.insert(*peer, peer_changes);
+                continue;
+            },
+        };
+        for ((name, target), category) in refs.iter_categorised() {
+            let qualified = ext::OneLevel::clone(name).into_qualified(category.into());
+            if !filter.allows(peer, &qualified.into()) {
+                continue;
+            }
+
+            let advertised = if *peer == remote_peer {
+                namespace.join(category).join(name.clone())
+            } else {
+                remotes.join(*peer).join(category).join(name.clone())
+            };
+            if remote_heads.get(&advertised) != Some(target) {
+                continue;
+            }
+
+            let name = remotes.join(*peer).join(category).join(name.clone());
+            if let Some(change) = change(&existing, name, *target) {
+                peer_changes.push(change);
+            }
+        }
+        changes.insert(*peer, peer_changes);
+    }
+
+    // Remote tracking branches of peers we wouldn't keep are pruned
+    for (peer, refs) in local {
+        if keep.contains(&peer) {
+            continue;
+        }
+        changes.insert(
+            peer,
+            refs.into_iter()
+                .map(|(name, target)| Change::Delete { name, target })
+                .collect(),
+        );
+    }
+
+    Ok(Plan { mode, changes })
+}
+
+/// The [`Change`] setting `name` to `target` makes to `existing`, if any.
+fn change(
+    existing: &BTreeMap<ext::RefLike, ext::Oid>,
+    name: ext::RefLike,
+    target: ext::Oid,
+) -> Option<Change> {
+    match existing.get(&name) {
+        None => Some(Change::Create { name, target }),
+        Some(from) if *from != target => Some(Change::Update {
+            name,
+            from: *from,
+            to: target,
+        }),
+        Some(_) => None,
+    }
+}
+
+/// Restore the remote tracking branches of `urn` to the state `before`,
+/// given they are currently in the state `after`.
+///
+/// The restore fails if any of the refs moved in the meantime.
+fn reset(
+    storage: &Storage,
+    urn: &Urn,
+    before: &BTreeMap<ext::RefLike, ext::Oid>,
+    after: &BTreeMap<ext::RefLike, ext::Oid>,
+) -> Result<(), Error> {
+    let mut tx = storage.transaction(format!("Reset refs of {} peeked for planning", urn));
+    for (name, target) in after {
+        match before.get(name) {
+            None => {
+                tx.delete(name.clone(), transaction::Expected::Oid(**target));
+            },
+            Some(prev) if prev != target => {
+                tx.update(name.clone(), **prev, transaction::Expected::Oid(**target));
+            },
+            Some(_) => {},
+        }
+    }
+    for (name, prev) in before {
+        if !after.contains_key(name) {
+            tx.create(name.clone(), **prev);
+        }
+    }
+    Ok(tx.commit()?)
+}
+
+/// All refs below `remotes`.
+fn snapshot(
+    storage: &Storage,
+    remotes: &ext::RefLike,
+) -> Result<BTreeMap<ext::RefLike, ext::Oid>, Error> {
+    let mut refs = BTreeMap::new();
+    for reference in storage.references_glob(glob::RefspecMatcher::from(
+        remotes.with_pattern_suffix(refspec_pattern!("*")),
+    ))? {
+        let reference = reference?;
+        if let (Some(Ok(name)), Some(target)) = (
+            reference.name().map(ext::RefLike::try_from),
+            reference.target(),
+        ) {
+            refs.insert(name, target.into());
+        }
+    }
+
+    Ok(refs)
+}
+
+/// Group the refs below `remotes` by peer.
+fn by_peer(
+    remotes: &ext::RefLike,
+    refs: &BTreeMap<ext::RefLike, ext::Oid>,
+) -> BTreeMap<PeerId, BTreeMap<ext::RefLike, ext::Oid>> {
+    let mut grouped = BTreeMap::<_, BTreeMap<_, _>>::new();
+    for (name, target) in refs {
+        let peer = name
+            .strip_prefix(remotes)
+            .ok()
+            .and_then(|suffix| suffix.as_str().split('/').next().map(str::parse::<PeerId>))
+            .and_then(Result::ok);
+        if let Some(peer) = peer {
+            grouped
+                .entry(peer)
+                .or_default()
+                .insert(name.clone(), *target);
+        }
+    }
+
+    grouped
+}
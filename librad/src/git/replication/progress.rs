@@ -0,0 +1,81 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{fmt, sync::Arc};
+
+use crate::{git::fetch, peer::PeerId};
+
+/// The stages of [`super::replicate`], in the order they are entered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// Fetching all identity documents the remote peer has for the
+    /// [`super::Urn`]. Only entered if the [`super::Urn`] is not present
+    /// locally yet.
+    Identities,
+    /// Fetching the branches needed for identity verification, and the
+    /// `rad/signed_refs`, of the delegates and tracked peers.
+    SignedRefs,
+    /// Fetching the heads, tags and notes of the delegates and tracked peers.
+    Data,
+}
+
+impl<P, R> From<&fetch::Fetchspecs<P, R>> for Stage {
+    fn from(specs: &fetch::Fetchspecs<P, R>) -> Self {
+        match specs {
+            fetch::Fetchspecs::PeekAll { .. } => Self::Identities,
+            fetch::Fetchspecs::Peek { .. } => Self::SignedRefs,
+            fetch::Fetchspecs::Replicate { .. } => Self::Data,
+        }
+    }
+}
+
+/// A progress report of [`super::replicate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Progress {
+    /// A new [`Stage`] was entered.
+    Stage(Stage),
+    /// Transfer progress of the current [`Stage`].
+    Transfer(fetch::Transfer),
+    /// The refs of `peer` have been replicated, of which `updated` have
+    /// changed.
+    ///
+    /// Reported at the end of [`Stage::Data`], which is skipped for person
+    /// identities.
+    Peer { peer: PeerId, updated: usize },
+}
+
+/// Receiver of [`Progress`] reports, see [`super::Config::progress`].
+///
+/// The reporter is invoked on the thread running [`super::replicate`], so
+/// should return quickly. To consume the reports elsewhere, send them to a
+/// channel.
+///
+/// The [`Default`] reporter discards all reports.
+#[derive(Clone, Default)]
+pub struct Reporter(Option<Arc<dyn Fn(Progress) + Send + Sync>>);
+
+impl Reporter {
+    pub fn new<F>(f: F) -> Self
+    where
+        F: Fn(Progress) + Send + Sync + 'static,
+    {
+        Self(Some(Arc::new(f)))
+    }
+
+    pub(super) fn report(&self, progress: Progress) {
+        if let Some(f) = &self.0 {
+            f(progress)
+        }
+    }
+}
+
+impl fmt::Debug for Reporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            None => f.write_str("Reporter(None)"),
+            Some(_) => f.write_str("Reporter(Some(..))"),
+        }
+    }
+}
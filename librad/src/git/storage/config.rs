@@ -26,6 +26,11 @@ const CONFIG_USER_NAME: &str = "user.name";
 const CONFIG_USER_EMAIL: &str = "user.email";
 const CONFIG_RAD_SELF: &str = "rad.self";
 const CONFIG_RAD_PEER_ID: &str = "rad.peerid";
+const CONFIG_RAD_LAYOUT: &str = "rad.layout";
+
+/// Since an empty set of globs can't be expressed as a multi-valued config
+/// entry, it is stored as this glob, which has the same effect.
+const NO_GLOBS: &str = "refs/rad/*";
 
 #[derive(Debug, Error)]
 #[non_exhaustive]
@@ -33,6 +38,9 @@ pub enum Error {
     #[error("storage was already initialised with peer id {0}")]
     AlreadyInitialised(PeerId),
 
+    #[error("invalid storage layout version: {0}")]
+    LayoutVersion(i64),
+
     #[error(transparent)]
     PeerId(#[from] peer::conversion::Error),
 
@@ -108,6 +116,7 @@ where
         this.guard_key_change()?;
         this.set_peer_id(PeerId::from_signer(signer))?;
         this.set_user_info("anonymous")?;
+        this.set_layout_version(super::migration::CURRENT)?;
 
         Ok(this)
     }
@@ -179,6 +188,22 @@ impl<S> Config<'_, S> {
             .and_then(|peer_id| peer_id.parse().map_err(Error::from))
     }
 
+    /// The version of the storage layout, see [`super::migration`].
+    ///
+    /// Storages created before the layout was versioned report version `0`.
+    pub fn layout_version(&self) -> Result<u32, Error> {
+        self.inner
+            .get_i64(CONFIG_RAD_LAYOUT)
+            .or_matches::<Error, _, _>(is_not_found_err, || Ok(0))
+            .and_then(|version| u32::try_from(version).map_err(|_| Error::LayoutVersion(version)))
+    }
+
+    pub(super) fn set_layout_version(&mut self, version: u32) -> Result<(), Error> {
+        self.inner
+            .set_i64(CONFIG_RAD_LAYOUT, version.into())
+            .map_err(Error::from)
+    }
+
     pub fn user(&self) -> Result<Option<Urn>, Error> {
         self.inner
             .get_string(CONFIG_RAD_SELF)
@@ -195,6 +220,37 @@ impl Config<'_, PhantomData<!>> {
     }
 }
 
+/// Add `globs` to the values of the multi-valued config entry `name`.
+///
+/// If `globs` is empty, a placeholder is stored instead, which [`parse_glob`]
+/// skips. This allows to tell an empty set apart from an absent entry.
+pub(crate) fn add_globs<'a, I>(
+    config: &mut git2::Config,
+    name: &str,
+    globs: I,
+) -> Result<(), git2::Error>
+where
+    I: IntoIterator<Item = &'a ext::RefspecPattern>,
+{
+    let mut globs = globs.into_iter().peekable();
+    if globs.peek().is_none() {
+        config.set_multivar(name, "^$", NO_GLOBS)?;
+    }
+    for glob in globs {
+        config.set_multivar(name, "^$", glob.as_str())?;
+    }
+
+    Ok(())
+}
+
+/// Parse a value written by [`add_globs`].
+///
+/// `None` is returned for the placeholder of an empty set of globs.
+pub(crate) fn parse_glob(value: &str) -> Result<Option<ext::RefspecPattern>, ext::name::Error> {
+    let glob = ext::RefspecPattern::try_from(value)?;
+    Ok(Some(glob).filter(|glob| glob.as_str() != NO_GLOBS))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -254,7 +310,11 @@ mod tests {
         let config = setup(&*ALICE_KEY);
 
         assert_eq!(config.peer_id().unwrap(), *ALICE_PEER_ID);
-        assert!(config.user().unwrap().is_none())
+        assert!(config.user().unwrap().is_none());
+        assert_eq!(
+            config.layout_version().unwrap(),
+            super::super::migration::CURRENT
+        )
     }
 
     #[test]
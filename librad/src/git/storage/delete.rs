@@ -0,0 +1,334 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{collections::BTreeSet, convert::TryFrom};
+
+use git_ext as ext;
+use thiserror::Error;
+
+use super::{
+    glob,
+    transaction::{self, Expected},
+    Storage,
+};
+use crate::{
+    git::{tracking, Urn},
+    peer::PeerId,
+};
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("{urn} is still referenced by {}", display_urns(.by))]
+    Referenced { urn: Urn, by: BTreeSet<Urn> },
+
+    #[error(transparent)]
+    Track(#[from] tracking::Error),
+
+    #[error(transparent)]
+    Store(#[from] super::Error),
+
+    #[error(transparent)]
+    Transaction(#[from] transaction::Error),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+fn display_urns(urns: &BTreeSet<Urn>) -> String {
+    urns.iter()
+        .map(|urn| urn.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The outcome of [`Storage::delete_urn`].
+#[derive(Clone, Debug, Default)]
+pub struct Deleted {
+    /// The peers which were tracked in the context of the deleted URN.
+    pub untracked: BTreeSet<PeerId>,
+    /// All refs which were removed.
+    pub removed_refs: BTreeSet<ext::RefLike>,
+}
+
+impl Storage {
+    /// Remove `urn` from the storage entirely.
+    ///
+    /// All peers tracked in the context of `urn` are untracked, their remote
+    /// configuration is removed, and all refs under `refs/namespaces/<urn>`
+    /// are deleted.
+    ///
+    /// If other namespaces refer to `urn` via their `rad/ids/*` refs (eg.
+    /// because `urn` is a delegate of a project), an error is returned unless
+    /// `force` is `true`. In the latter case, the `rad/ids/*` refs pointing
+    /// to `urn` are left dangling -- they can be cleaned up using
+    /// [`Storage::gc`].
+    ///
+    /// Note that objects are not removed from the odb: this is left to a
+    /// subsequent [`Storage::gc`], too.
+    #[tracing::instrument(skip(self), err)]
+    pub fn delete_urn(&self, urn: &Urn, force: bool) -> Result<Deleted, Error> {
+        let urn = urn.clone().with_path(None);
+
+        let referrers = self.referrers(&urn)?;
+        if !referrers.is_empty() {
+            if force {
+                tracing::warn!(referrers = %display_urns(&referrers), "deleting referenced urn");
+            } else {
+                return Err(Error::Referenced { urn, by: referrers });
+            }
+        }
+
+        let mut deleted = Deleted::default();
+
+        let tracked = tracking::tracked_peers(self, &urn)?.collect::<Vec<_>>();
+        for peer in tracked {
+            tracking::untrack(self, &urn, peer)?;
+            deleted.untracked.insert(peer);
+        }
+
+        // `untrack` only removes remotes `libgit2` considers valid (ie. which
+        // have a url). Make sure there are no leftovers.
+        let mut config = self.backend.config()?;
+        let prefix = format!("remote.{}/", urn.encode_id());
+        let leftovers = {
+            let mut names = BTreeSet::new();
+            let entries = config.entries(Some(&format!("{}.*", regex_escape(&prefix))))?;
+            for entry in &entries {
+                let entry = entry?;
+                if let Some(name) = entry.name() {
+                    names.insert(name.to_owned());
+                }
+            }
+            names
+        };
+        for name in leftovers {
+            config.remove_multivar(&name, ".*")?;
+        }
+
+        let namespace = glob::RefspecMatcher::from(
+            reflike!("refs/namespaces")
+                .join(&urn)
+                .with_pattern_suffix(refspec_pattern!("*")),
+        );
+        let names = self
+            .reference_names_glob(namespace)?
+            .collect::<Result<Vec<_>, _>>()?;
+        // Remove all refs at once, so a failure doesn't leave the namespace
+        // half-deleted
+        let mut tx = self.transaction(format!("Delete {}", urn));
+        for name in &names {
+            tx.delete(name.clone(), Expected::Any);
+        }
+        tx.commit()?;
+        deleted.removed_refs.extend(names);
+
+        Ok(deleted)
+    }
+
+    /// The namespaces other than `urn` which refer to `urn` via `rad/ids/*`.
+    fn referrers(&self, urn: &Urn) -> Result<BTreeSet<Urn>, Error> {
+        let ids = glob::rad_ids(None);
+        let encoded = urn.encode_id();
+
+        let mut referrers = BTreeSet::new();
+        for name in self.reference_names_glob(ids)? {
+            let name = name?;
+            if !name.as_str().ends_with(&format!("/rad/ids/{}", encoded)) {
+                continue;
+            }
+            if let Ok(referrer) = Urn::try_from(name) {
+                let referrer = referrer.with_path(None);
+                if &referrer != urn {
+                    referrers.insert(referrer);
+                }
+            }
+        }
+
+        Ok(referrers)
+    }
+}
+
+fn regex_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if "\\.+*?()|[]{}^$".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{keys::SecretKey, paths::Paths};
+
+    fn empty_commit(storage: &Storage) -> git2::Oid {
+        let repo = storage.as_raw();
+        let tree = {
+            let oid = repo.treebuilder(None).unwrap().write().unwrap();
+            repo.find_tree(oid).unwrap()
+        };
+        let author = repo.signature().unwrap();
+        repo.commit(None, &author, &author, "empty", &tree, &[])
+            .unwrap()
+    }
+
+    fn namespace(urn: &Urn) -> ext::RefLike {
+        reflike!("refs/namespaces").join(urn)
+    }
+
+    fn refs(storage: &Storage, urn: &Urn) -> BTreeSet<ext::RefLike> {
+        storage
+            .reference_names_glob(glob::RefspecMatcher::from(
+                namespace(urn).with_pattern_suffix(refspec_pattern!("*")),
+            ))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn untracks_all_peers() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(&tmp).unwrap();
+            let storage = Storage::open(&paths, SecretKey::new()).unwrap();
+            let urn = Urn::new(git2::Oid::zero().into());
+            let peers = (0..3)
+                .map(|_| PeerId::from(SecretKey::new()))
+                .collect::<BTreeSet<_>>();
+            for peer in &peers {
+                tracking::track(&storage, &urn, *peer).unwrap();
+            }
+
+            let deleted = storage.delete_urn(&urn, false).unwrap();
+            assert_eq!(deleted.untracked, peers);
+            assert!(tracking::tracked_peers(&storage, &urn)
+                .unwrap()
+                .next()
+                .is_none())
+        }
+    }
+
+    #[test]
+    fn removes_all_refs() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(&tmp).unwrap();
+            let storage = Storage::open(&paths, SecretKey::new()).unwrap();
+            let urn = Urn::new(git2::Oid::zero().into());
+            let other = Urn::new(
+                git2::Oid::hash_object(git2::ObjectType::Blob, b"other")
+                    .unwrap()
+                    .into(),
+            );
+            let peer = PeerId::from(SecretKey::new());
+            let oid = empty_commit(&storage);
+
+            let mut tx = storage.transaction("test");
+            for name in &[
+                reflike!("refs/rad/id"),
+                reflike!("refs/heads/master"),
+                reflike!("refs/tags/v1"),
+            ] {
+                tx.create(namespace(&urn).join(name), oid);
+            }
+            tx.create(
+                namespace(&urn)
+                    .join(reflike!("refs/remotes"))
+                    .join(peer)
+                    .join(reflike!("heads/master")),
+                oid,
+            );
+            tx.create(namespace(&other).join(reflike!("refs/heads/master")), oid);
+            tx.commit().unwrap();
+            let created = refs(&storage, &urn);
+            assert_eq!(created.len(), 4);
+
+            let deleted = storage.delete_urn(&urn, false).unwrap();
+            assert_eq!(deleted.removed_refs, created);
+            assert!(refs(&storage, &urn).is_empty());
+            // Other namespaces are left alone
+            assert_eq!(refs(&storage, &other).len(), 1);
+        }
+    }
+
+    #[test]
+    fn removes_remote_config() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(&tmp).unwrap();
+            let storage = Storage::open(&paths, SecretKey::new()).unwrap();
+            let urn = Urn::new(git2::Oid::zero().into());
+            let tracked = PeerId::from(SecretKey::new());
+            let leftover = PeerId::from(SecretKey::new());
+
+            tracking::track(&storage, &urn, tracked).unwrap();
+            // A remote without a url, which `libgit2` doesn't consider valid
+            storage
+                .as_raw()
+                .config()
+                .unwrap()
+                .set_str(
+                    &format!("remote.{}/{}.rad-nickname", urn.encode_id(), leftover),
+                    "leftover",
+                )
+                .unwrap();
+
+            storage.delete_urn(&urn, false).unwrap();
+
+            let config = storage.as_raw().config().unwrap();
+            let prefix = format!("remote.{}/", urn.encode_id());
+            let entries = config
+                .entries(Some(&format!("{}.*", regex_escape(&prefix))))
+                .unwrap();
+            let mut names = Vec::new();
+            for entry in &entries {
+                names.push(entry.unwrap().name().unwrap().to_owned());
+            }
+            assert!(names.is_empty(), "leftover config entries: {:?}", names);
+        }
+    }
+
+    #[test]
+    fn refuses_referenced_urn_unless_forced() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(&tmp).unwrap();
+            let storage = Storage::open(&paths, SecretKey::new()).unwrap();
+            let urn = Urn::new(git2::Oid::zero().into());
+            let project = Urn::new(
+                git2::Oid::hash_object(git2::ObjectType::Blob, b"project")
+                    .unwrap()
+                    .into(),
+            );
+            let oid = empty_commit(&storage);
+
+            let rad_id = namespace(&urn).join(reflike!("refs/rad/id"));
+            let delegate = namespace(&project)
+                .join(reflike!("refs/rad/ids"))
+                .join(ext::RefLike::try_from(urn.encode_id()).unwrap());
+            let mut tx = storage.transaction("test");
+            tx.create(rad_id.clone(), oid).create(delegate.clone(), oid);
+            tx.commit().unwrap();
+
+            assert!(matches!(
+                storage.delete_urn(&urn, false),
+                Err(Error::Referenced { by, .. }) if by.iter().collect::<Vec<_>>() == vec![&project]
+            ));
+            assert!(storage.as_raw().find_reference(rad_id.as_str()).is_ok());
+
+            let deleted = storage.delete_urn(&urn, true).unwrap();
+            assert!(deleted.removed_refs.contains(&rad_id));
+            assert!(refs(&storage, &urn).is_empty());
+            // The referrer is left dangling, for `gc` to clean up
+            assert!(storage.as_raw().find_reference(delegate.as_str()).is_ok());
+        }
+    }
+}
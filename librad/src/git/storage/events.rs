@@ -0,0 +1,119 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Notifications about changes to the storage.
+//!
+//! Events are delivered to all subscribers within the same process, regardless
+//! of which [`super::Storage`] (or [`super::Pool`]) instance caused them, as
+//! long as it refers to the same storage location. Changes made by other
+//! processes are not observed.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use git_ext as ext;
+use tokio::sync::broadcast;
+
+use super::Urn;
+use crate::{paths::Paths, peer::PeerId};
+
+pub use broadcast::error::RecvError;
+
+/// The number of events buffered per storage location. Subscribers which fall
+/// further behind receive [`RecvError::Lagged`].
+const CAPACITY: usize = 1024;
+
+lazy_static! {
+    static ref CHANNELS: Mutex<HashMap<PathBuf, broadcast::Sender<Event>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// A change to the storage.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// The identity `urn` is now present in the storage, either because it was
+    /// created locally or because it was replicated for the first time.
+    UrnCreated { urn: Urn },
+    /// The local `rad/id` of `urn` now points to `tip`.
+    IdentityUpdated { urn: Urn, tip: ext::Oid },
+    /// The `rad/signed_refs` of `peer` for `urn` changed. `peer` may be the
+    /// local peer.
+    SignedRefsUpdated { urn: Urn, peer: PeerId },
+    /// `peer` is now tracked in the context of `urn`.
+    TrackingAdded { urn: Urn, peer: PeerId },
+    /// `peer` is no longer tracked in the context of `urn`.
+    TrackingRemoved { urn: Urn, peer: PeerId },
+}
+
+/// Subscribe to the [`Event`]s of the storage at `paths`.
+///
+/// Only events emitted after this function returns are delivered.
+pub fn subscribe(paths: &Paths) -> impl futures::Stream<Item = Result<Event, RecvError>> {
+    subscribe_at(paths.git_dir())
+}
+
+pub(super) fn subscribe_at(
+    git_dir: &Path,
+) -> impl futures::Stream<Item = Result<Event, RecvError>> {
+    let mut r = CHANNELS
+        .lock()
+        .unwrap()
+        .entry(key(git_dir))
+        .or_insert_with(|| broadcast::channel(CAPACITY).0)
+        .subscribe();
+    async_stream::stream! { loop { yield r.recv().await } }
+}
+
+pub(super) fn emit(git_dir: &Path, event: Event) {
+    if let Some(tx) = CHANNELS.lock().unwrap().get(&key(git_dir)) {
+        tx.send(event).ok();
+    }
+}
+
+fn key(git_dir: &Path) -> PathBuf {
+    fs::canonicalize(git_dir).unwrap_or_else(|_| git_dir.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::StreamExt as _;
+
+    use crate::{
+        git::{storage::Storage, tracking},
+        keys::SecretKey,
+    };
+
+    #[async_test]
+    async fn tracking_events() {
+        let tmp = tempfile::tempdir().unwrap();
+        let paths = Paths::from_root(tmp.path()).unwrap();
+        let storage = Storage::open(&paths, SecretKey::new()).unwrap();
+        let events = subscribe(&paths);
+        futures::pin_mut!(events);
+
+        let urn = Urn::new(git2::Oid::zero().into());
+        let peer = PeerId::from(SecretKey::new());
+        tracking::track(&storage, &urn, peer).unwrap();
+        tracking::untrack(&storage, &urn, peer).unwrap();
+
+        assert_eq!(
+            events.next().await.unwrap().unwrap(),
+            Event::TrackingAdded {
+                urn: urn.clone(),
+                peer
+            }
+        );
+        assert_eq!(
+            events.next().await.unwrap().unwrap(),
+            Event::TrackingRemoved { urn, peer }
+        );
+    }
+}
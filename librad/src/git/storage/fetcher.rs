@@ -76,7 +76,15 @@ impl<'a> fetch::Fetcher for Fetcher<'a> {
         &mut self,
         specs: fetch::Fetchspecs<Self::PeerId, Self::UrnId>,
     ) -> Result<fetch::FetchResult, Self::Error> {
-        self.inner.fetch(specs)
+        self.inner.fetch(specs, &mut |_| {})
+    }
+
+    fn fetch_with_progress(
+        &mut self,
+        specs: fetch::Fetchspecs<Self::PeerId, Self::UrnId>,
+        progress: &mut dyn FnMut(fetch::Transfer),
+    ) -> Result<fetch::FetchResult, Self::Error> {
+        self.inner.fetch(specs, progress)
     }
 }
 
@@ -398,6 +406,8 @@ where
 mod imp {
     use super::*;
 
+    use crate::git::storage::{staging::Staging, transaction};
+
     pub struct Info {
         pub urn: Urn,
         pub remote_peer: PeerId,
@@ -406,6 +416,7 @@ mod imp {
 
     pub struct Fetcher<'a> {
         info: Info,
+        storage: &'a Storage,
         remote: git2::Remote<'a>,
     }
 
@@ -439,36 +450,43 @@ mod imp {
                 remote_heads,
             };
 
-            Ok(Self { info, remote })
+            Ok(Self {
+                info,
+                storage,
+                remote,
+            })
         }
 
         pub fn info(&self) -> &Info {
             &self.info
         }
 
-        #[tracing::instrument(skip(self), err)]
+        #[tracing::instrument(skip(self, progress), err)]
         pub fn fetch(
             &mut self,
             fetchspecs: Fetchspecs<PeerId, Revision>,
-        ) -> Result<FetchResult, git2::Error> {
-            let mut updated_tips = BTreeMap::new();
-            {
+            progress: &mut dyn FnMut(fetch::Transfer),
+        ) -> Result<FetchResult, transaction::Error> {
+            let staging = Staging::new();
+            let fetched = {
                 let limit = fetchspecs.fetch_limit();
-                let refspecs = fetchspecs
-                    .refspecs(
-                        &self.info.urn,
-                        self.info.remote_peer,
-                        &self.info.remote_heads,
-                    )
-                    .into_iter()
-                    .map(|spec| spec.to_string())
-                    .collect::<Vec<_>>();
+                let refspecs = staging.refspecs(fetchspecs.refspecs(
+                    &self.info.urn,
+                    self.info.remote_peer,
+                    &self.info.remote_heads,
+                ));
                 tracing::trace!("{:?}", refspecs);
 
                 let mut callbacks = git2::RemoteCallbacks::new();
                 callbacks.transfer_progress(|prog| {
                     let received_bytes = prog.received_bytes();
                     tracing::trace!("Fetch: received {} bytes", received_bytes);
+                    progress(fetch::Transfer {
+                        total_objects: prog.total_objects(),
+                        received_objects: prog.received_objects(),
+                        indexed_objects: prog.indexed_objects(),
+                        received_bytes,
+                    });
                     if received_bytes > limit {
                         tracing::error!("Fetch: exceeded {} bytes", limit);
                         false
@@ -483,18 +501,6 @@ mod imp {
                 // times redundantly.
                 //
                 // Upstream issue: https://github.com/libgit2/libgit2/issues/5799.
-                callbacks.update_tips(|name, old, new| {
-                    tracing::debug!("Fetch: updating tip {}: {} -> {}", name, old, new);
-                    match RefLike::try_from(name) {
-                        Ok(refname) => {
-                            updated_tips.insert(refname, new.into());
-                        },
-                        Err(e) => tracing::warn!("invalid refname `{}`: {}", name, e),
-                    }
-
-                    true
-                });
-
                 self.remote.fetch(
                     &refspecs,
                     Some(
@@ -505,15 +511,26 @@ mod imp {
                             .remote_callbacks(callbacks),
                     ),
                     None,
-                )?;
-            }
+                )
+            };
 
-            Ok(FetchResult { updated_tips })
+            // The fetched refs are staged, move them into place all at once
+            match fetched {
+                Ok(()) => Ok(FetchResult {
+                    updated_tips: staging.apply(self.storage)?,
+                }),
+                Err(e) => {
+                    if let Err(e) = staging.discard(self.storage) {
+                        tracing::warn!(err = %e, "failed to discard staged refs");
+                    }
+                    Err(e.into())
+                },
+            }
         }
     }
 
     impl fetch::Fetcher for Fetcher<'_> {
-        type Error = git2::Error;
+        type Error = transaction::Error;
         type PeerId = PeerId;
         type UrnId = Revision;
 
@@ -533,7 +550,15 @@ mod imp {
             &mut self,
             fetchspecs: Fetchspecs<Self::PeerId, Self::UrnId>,
         ) -> Result<FetchResult, Self::Error> {
-            self.fetch(fetchspecs)
+            self.fetch(fetchspecs, &mut |_| {})
+        }
+
+        fn fetch_with_progress(
+            &mut self,
+            fetchspecs: Fetchspecs<Self::PeerId, Self::UrnId>,
+            progress: &mut dyn FnMut(fetch::Transfer),
+        ) -> Result<FetchResult, Self::Error> {
+            self.fetch(fetchspecs, progress)
         }
     }
 }
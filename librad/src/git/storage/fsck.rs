@@ -0,0 +1,387 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{
+    collections::BTreeSet,
+    convert::TryFrom,
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use git_ext::{self as ext, is_not_found_err};
+use thiserror::Error;
+
+use super::{glob, ReadOnly};
+use crate::{
+    git::{
+        identities,
+        refs::{self, Refs},
+        tracking,
+        types::{Namespace, Reference},
+        Urn,
+    },
+    identities::git::SomeIdentity,
+    peer::PeerId,
+};
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error(transparent)]
+    Identities(#[from] Box<identities::error::Error>),
+
+    #[error(transparent)]
+    Track(#[from] tracking::Error),
+
+    #[error(transparent)]
+    Store(#[from] super::Error),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+impl From<identities::error::Error> for Error {
+    fn from(e: identities::error::Error) -> Self {
+        Self::Identities(Box::new(e))
+    }
+}
+
+/// A problem detected by [`ReadOnly::verify_all`].
+///
+/// `peer` is `None` if the problem concerns the local peer's view.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Finding {
+    /// Data could not be read or parsed.
+    Corrupt {
+        urn: Urn,
+        peer: Option<PeerId>,
+        reason: String,
+    },
+
+    /// An identity history or `rad/signed_refs` failed verification.
+    Unverifiable {
+        urn: Urn,
+        peer: Option<PeerId>,
+        reason: String,
+    },
+
+    /// A ref does not point to what the corresponding `rad/signed_refs` claim.
+    Diverged {
+        urn: Urn,
+        peer: Option<PeerId>,
+        name: ext::RefLike,
+        signed: ext::Oid,
+        actual: ext::Oid,
+    },
+
+    /// A ref which should not exist: either a symbolic ref whose target is
+    /// missing, or a remote ref of a peer which is not tracked.
+    Dangling { urn: Urn, name: ext::RefLike },
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn peer_or_local(peer: &Option<PeerId>) -> String {
+            peer.map(|peer| peer.to_string())
+                .unwrap_or_else(|| "local".to_owned())
+        }
+
+        match self {
+            Self::Corrupt { urn, peer, reason } => {
+                write!(f, "corrupt: {} ({}): {}", urn, peer_or_local(peer), reason)
+            },
+            Self::Unverifiable { urn, peer, reason } => write!(
+                f,
+                "unverifiable: {} ({}): {}",
+                urn,
+                peer_or_local(peer),
+                reason
+            ),
+            Self::Diverged {
+                urn,
+                peer,
+                name,
+                signed,
+                actual,
+            } => write!(
+                f,
+                "diverged: {} ({}): {} is {}, but signed refs claim {}",
+                urn,
+                peer_or_local(peer),
+                name,
+                actual,
+                signed
+            ),
+            Self::Dangling { urn, name } => write!(f, "dangling: {}: {}", urn, name),
+        }
+    }
+}
+
+impl ReadOnly {
+    /// Audit the consistency of all identities in the storage.
+    ///
+    /// For every [`Urn`] returned by [`identities::any::list_urns`], and for
+    /// the local view as well as the view of every tracked peer:
+    ///
+    /// * the identity history is verified
+    /// * the `rad/signed_refs` are verified against the peer's key
+    /// * the refs are compared to what the `rad/signed_refs` claim
+    ///
+    /// In addition, symbolic refs whose target is missing and remote refs of
+    /// peers which are not tracked are reported.
+    ///
+    /// An error is returned only if the storage could not be traversed. All
+    /// problems with the data itself are reported as [`Finding`]s.
+    #[tracing::instrument(skip(self), err)]
+    pub fn verify_all(&self) -> Result<Vec<Finding>, Error> {
+        let mut findings = Vec::new();
+        for urn in identities::any::list_urns(self)? {
+            let urn = urn?;
+            self.verify_urn(&urn, &mut findings)?;
+        }
+
+        tracing::info!(findings = findings.len(), "fsck done");
+        Ok(findings)
+    }
+
+    fn verify_urn(&self, urn: &Urn, findings: &mut Vec<Finding>) -> Result<(), Error> {
+        let tracked = tracking::tracked_peers(self, urn)?.collect::<BTreeSet<_>>();
+
+        match identities::any::get(self, urn) {
+            Ok(Some(identity)) => {
+                for peer in Some(None)
+                    .into_iter()
+                    .chain(tracked.iter().copied().map(Some))
+                {
+                    self.verify_identity(urn, peer, &identity, findings)?;
+                }
+            },
+            Ok(None) => findings.push(Finding::Corrupt {
+                urn: urn.clone(),
+                peer: None,
+                reason: "rad/id vanished".to_owned(),
+            }),
+            Err(e) => findings.push(Finding::Corrupt {
+                urn: urn.clone(),
+                peer: None,
+                reason: e.to_string(),
+            }),
+        }
+
+        for peer in Some(None)
+            .into_iter()
+            .chain(tracked.iter().copied().map(Some))
+        {
+            self.verify_signed_refs(urn, peer, findings)?;
+        }
+
+        self.find_dangling(urn, &tracked, findings)
+    }
+
+    fn verify_identity(
+        &self,
+        urn: &Urn,
+        peer: Option<PeerId>,
+        identity: &SomeIdentity,
+        findings: &mut Vec<Finding>,
+    ) -> Result<(), Error> {
+        let rad_id = Reference::rad_id(Namespace::from(urn)).with_remote(peer);
+        if !self.has_ref(&rad_id)? {
+            return Ok(());
+        }
+        let view = Urn::try_from(rad_id).expect("namespace is set");
+
+        let verified = match identity {
+            SomeIdentity::Person(_) => identities::person::verify(self, &view).map(|p| p.is_some()),
+            SomeIdentity::Project(_) => {
+                identities::project::verify(self, &view).map(|p| p.is_some())
+            },
+        };
+        if let Err(e) = verified {
+            findings.push(Finding::Unverifiable {
+                urn: urn.clone(),
+                peer,
+                reason: e.to_string(),
+            })
+        }
+
+        Ok(())
+    }
+
+    fn verify_signed_refs(
+        &self,
+        urn: &Urn,
+        peer: Option<PeerId>,
+        findings: &mut Vec<Finding>,
+    ) -> Result<(), Error> {
+        let refs = match Refs::load(self, urn, peer) {
+            Ok(Some(refs)) => refs,
+            Ok(None) => return Ok(()),
+            Err(refs::stored::Error::Signed(e @ refs::signed::Error::InvalidSignature(_))) => {
+                findings.push(Finding::Unverifiable {
+                    urn: urn.clone(),
+                    peer,
+                    reason: e.to_string(),
+                });
+                return Ok(());
+            },
+            Err(e) => {
+                findings.push(Finding::Corrupt {
+                    urn: urn.clone(),
+                    peer,
+                    reason: e.to_string(),
+                });
+                return Ok(());
+            },
+        };
+
+        let prefix = {
+            let ns = reflike!("refs/namespaces").join(urn).join(reflike!("refs"));
+            match peer {
+                None => ns,
+                Some(peer) => ns.join(reflike!("remotes")).join(peer),
+            }
+        };
+        for ((name, signed), category) in refs.iter_categorised() {
+            let name = prefix.join(category).join(name.clone());
+            let actual = match self.as_raw().refname_to_id(name.as_str()) {
+                Ok(oid) => oid,
+                // Not (yet) fetched
+                Err(e) if is_not_found_err(&e) => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if actual != **signed {
+                findings.push(Finding::Diverged {
+                    urn: urn.clone(),
+                    peer,
+                    name,
+                    signed: *signed,
+                    actual: actual.into(),
+                })
+            }
+        }
+
+        Ok(())
+    }
+
+    fn find_dangling(
+        &self,
+        urn: &Urn,
+        tracked: &BTreeSet<PeerId>,
+        findings: &mut Vec<Finding>,
+    ) -> Result<(), Error> {
+        let namespace = reflike!("refs/namespaces").join(urn);
+        let remotes = namespace.join(reflike!("refs/remotes"));
+        for reference in self.references_glob(glob::RefspecMatcher::from(
+            namespace.with_pattern_suffix(refspec_pattern!("*")),
+        ))? {
+            let reference = reference?;
+            let name = match reference.name().map(ext::RefLike::try_from) {
+                Some(Ok(name)) => name,
+                _ => continue,
+            };
+
+            let is_dangling_symref = reference.kind() == Some(git2::ReferenceType::Symbolic)
+                && matches!(reference.resolve(), Err(e) if is_not_found_err(&e));
+            let is_untracked_remote = name
+                .strip_prefix(&remotes)
+                .ok()
+                .and_then(|suffix| {
+                    suffix
+                        .as_str()
+                        .split('/')
+                        .next()
+                        .and_then(|peer| PeerId::from_str(peer).ok())
+                })
+                .map(|peer| !tracked.contains(&peer))
+                .unwrap_or(false);
+
+            if is_dangling_symref || is_untracked_remote {
+                findings.push(Finding::Dangling {
+                    urn: urn.clone(),
+                    name,
+                })
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        git::{storage::Storage, types::Force},
+        identities::payload,
+        keys::SecretKey,
+        paths::Paths,
+    };
+
+    #[test]
+    fn clean_storage() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let key = SecretKey::new();
+            let paths = Paths::from_root(&tmp).unwrap();
+            let storage = Storage::open(&paths, key.clone()).unwrap();
+            identities::person::create(
+                &storage,
+                payload::Person {
+                    name: "dylan".into(),
+                },
+                Some(key.public()).into_iter().collect(),
+            )
+            .unwrap();
+
+            assert_eq!(storage.verify_all().unwrap(), vec![])
+        }
+    }
+
+    #[test]
+    fn diverged_head() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let key = SecretKey::new();
+            let paths = Paths::from_root(&tmp).unwrap();
+            let storage = Storage::open(&paths, key.clone()).unwrap();
+            let person = identities::person::create(
+                &storage,
+                payload::Person {
+                    name: "dylan".into(),
+                },
+                Some(key.public()).into_iter().collect(),
+            )
+            .unwrap();
+            let urn = person.urn();
+
+            // Point a head to the identity commit, and sign it
+            let master = Reference::head(Namespace::from(&urn), None, reflike!("master"));
+            master
+                .create(storage.as_raw(), *person.content_id, Force::False, "master")
+                .unwrap();
+            Refs::update(&storage, &urn).unwrap();
+
+            // Move it behind the signed refs' back
+            let tree = storage.as_raw().treebuilder(None).unwrap().write().unwrap();
+            let tree = storage.as_raw().find_tree(tree).unwrap();
+            let author = storage.as_raw().signature().unwrap();
+            let moved = storage
+                .as_raw()
+                .commit(None, &author, &author, "sneaky", &tree, &[])
+                .unwrap();
+            master
+                .create(storage.as_raw(), moved, Force::True, "sneaky")
+                .unwrap();
+
+            let findings = storage.verify_all().unwrap();
+            assert!(matches!(
+                findings.as_slice(),
+                [Finding::Diverged { peer: None, .. }]
+            ))
+        }
+    }
+}
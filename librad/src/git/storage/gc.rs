@@ -0,0 +1,358 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    fs,
+    io,
+    panic,
+    path::Path,
+    process::{Command, ExitStatus, Stdio},
+};
+
+use git_ext::{self as ext, is_not_found_err};
+use thiserror::Error;
+use tokio::task::{spawn_blocking, JoinError};
+
+use super::{
+    glob,
+    transaction::{self, Expected},
+    PoolError,
+    Pooled,
+    Storage,
+};
+use crate::git::{tracking, Urn};
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("`git gc` exited unsuccessfully: {0}")]
+    Child(ExitStatus),
+
+    #[error("spawned task was cancelled")]
+    Cancelled,
+
+    #[error(transparent)]
+    Track(#[from] tracking::Error),
+
+    #[error(transparent)]
+    Store(#[from] super::Error),
+
+    #[error(transparent)]
+    Pool(#[from] PoolError),
+
+    #[error(transparent)]
+    Transaction(#[from] transaction::Error),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl From<JoinError> for Error {
+    fn from(e: JoinError) -> Self {
+        if e.is_cancelled() {
+            Self::Cancelled
+        } else if e.is_panic() {
+            panic::resume_unwind(e.into_panic())
+        } else {
+            panic!("unexpected task error: {:?}", e)
+        }
+    }
+}
+
+/// Parameters for [`Storage::gc`].
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Remove namespaces which have neither a local `rad/id`, nor any tracking
+    /// relationships, nor are referenced from another namespace's `rad/ids`.
+    pub prune_namespaces: bool,
+
+    /// Passed as `--prune=<date>` to `git gc`. Objects younger than this will
+    /// not be removed, even if they are unreachable.
+    ///
+    /// If `None`, `git`'s default (`gc.pruneExpire`) applies. Note that
+    /// "now" is unsafe if other processes (or other [`Storage`]s in a
+    /// [`super::Pool`]) may be writing to the storage concurrently.
+    pub prune_expire: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            prune_namespaces: true,
+            prune_expire: None,
+        }
+    }
+}
+
+/// The outcome of [`Storage::gc`].
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    /// Namespaces which were removed entirely.
+    pub removed_namespaces: BTreeSet<Urn>,
+    /// All refs which were removed, including the ones belonging to
+    /// [`Self::removed_namespaces`].
+    pub removed_refs: BTreeSet<ext::RefLike>,
+    /// Size in bytes of the object database before collection.
+    pub size_before: u64,
+    /// Size in bytes of the object database after collection.
+    pub size_after: u64,
+}
+
+impl Report {
+    /// The number of bytes reclaimed from the object database.
+    pub fn bytes_freed(&self) -> u64 {
+        self.size_before.saturating_sub(self.size_after)
+    }
+}
+
+impl Storage {
+    /// Reclaim space occupied by data which is no longer needed.
+    ///
+    /// This proceeds in three steps:
+    ///
+    /// 1. Remove all refs of namespaces which have no local `rad/id`, no
+    ///    tracked peers, and which are not referenced by any other namespace
+    ///    via `rad/ids/*` (if [`Options::prune_namespaces`] is set)
+    /// 2. Remove symbolic `rad/ids/*` refs whose target no longer exists
+    /// 3. Run `git gc`, which packs refs and objects, and prunes objects which
+    ///    are no longer reachable from any ref
+    #[tracing::instrument(skip(self), err)]
+    pub fn gc(&self, options: Options) -> Result<Report, Error> {
+        let mut report = Report {
+            size_before: dir_size(&self.path().join("objects"))?,
+            ..Report::default()
+        };
+
+        if options.prune_namespaces {
+            self.prune_namespaces(&mut report)?;
+        }
+        self.prune_dangling_ids(&mut report)?;
+
+        let mut git = Command::new("git");
+        git.envs(::std::env::vars().filter(|(key, _)| key.starts_with("GIT_TRACE")))
+            .current_dir(self.path())
+            .args(&["gc", "--quiet"]);
+        if let Some(expire) = options.prune_expire {
+            git.arg(format!("--prune={}", expire));
+        }
+        let status = git
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::inherit())
+            .status()?;
+        if !status.success() {
+            return Err(Error::Child(status));
+        }
+
+        report.size_after = dir_size(&self.path().join("objects"))?;
+        tracing::info!(
+            removed_namespaces = report.removed_namespaces.len(),
+            removed_refs = report.removed_refs.len(),
+            bytes_freed = report.bytes_freed(),
+            "gc done"
+        );
+
+        Ok(report)
+    }
+
+    fn prune_namespaces(&self, report: &mut Report) -> Result<(), Error> {
+        lazy_static! {
+            static ref NAMESPACES: glob::RefspecMatcher =
+                refspec_pattern!("refs/namespaces/*").into();
+        }
+
+        let mut namespaces: BTreeMap<Urn, Vec<ext::RefLike>> = BTreeMap::new();
+        let mut referenced: BTreeSet<Urn> = BTreeSet::new();
+        for reference in self.references_glob(NAMESPACES.clone())? {
+            let reference = reference?;
+            let name = match reference.name().map(ext::RefLike::try_from) {
+                Some(Ok(name)) => name,
+                _ => continue,
+            };
+            let urn = match Urn::try_from(name.clone()) {
+                Ok(urn) => urn.with_path(None),
+                Err(e) => {
+                    tracing::warn!(name = %name, err = %e, "skipping ref outside a namespace");
+                    continue;
+                },
+            };
+
+            if let Some(target) = reference
+                .symbolic_target()
+                .and_then(|target| ext::RefLike::try_from(target).ok())
+                .and_then(|target| Urn::try_from(target).ok())
+            {
+                referenced.insert(target.with_path(None));
+            }
+
+            namespaces.entry(urn).or_default().push(name);
+        }
+
+        let mut orphaned = BTreeSet::new();
+        let mut names = Vec::new();
+        for (urn, refs) in namespaces {
+            if referenced.contains(&urn) || self.has_urn(&urn)? {
+                continue;
+            }
+            if tracking::tracked_peers(self, &urn)?.next().is_some() {
+                continue;
+            }
+
+            tracing::debug!(urn = %urn, "removing orphaned namespace");
+            names.extend(refs);
+            orphaned.insert(urn);
+        }
+        self.remove_refs(names, "gc: remove orphaned namespaces", report)?;
+        report.removed_namespaces.extend(orphaned);
+
+        Ok(())
+    }
+
+    fn prune_dangling_ids(&self, report: &mut Report) -> Result<(), Error> {
+        let ids = glob::rad_ids(None);
+
+        let dangling = self
+            .references_glob(ids)?
+            .filter_map(|reference| match reference {
+                Ok(reference) => {
+                    let is_symbolic = reference.kind() == Some(git2::ReferenceType::Symbolic);
+                    match reference.resolve() {
+                        Err(e) if is_symbolic && is_not_found_err(&e) => reference
+                            .name()
+                            .and_then(|name| ext::RefLike::try_from(name).ok())
+                            .map(Ok),
+                        _ => None,
+                    }
+                },
+                Err(e) => Some(Err(e)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for name in &dangling {
+            tracing::debug!(name = %name, "removing dangling delegate ref");
+        }
+        self.remove_refs(dangling, "gc: remove dangling delegate refs", report)
+    }
+
+    /// Remove all of `names` in a single [`transaction::Transaction`], so
+    /// either all or none of them are removed.
+    fn remove_refs(
+        &self,
+        names: Vec<ext::RefLike>,
+        message: &str,
+        report: &mut Report,
+    ) -> Result<(), Error> {
+        let mut tx = self.transaction(message);
+        for name in &names {
+            tx.delete(name.clone(), Expected::Any);
+        }
+        tx.commit()?;
+        report.removed_refs.extend(names);
+
+        Ok(())
+    }
+}
+
+/// Run [`Storage::gc`] on a [`Storage`] obtained from `pool`.
+///
+/// The collection is run on the blocking thread pool, so this is suitable for
+/// periodic maintenance tasks.
+pub async fn maintenance<P>(pool: &P, options: Options) -> Result<Report, Error>
+where
+    P: Pooled,
+{
+    let storage = pool.get().await?;
+    spawn_blocking(move || storage.gc(options)).await?
+}
+
+fn dir_size(path: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if meta.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else {
+            size += meta.len();
+        }
+    }
+
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        git::types::{Force, Namespace, Reference},
+        keys::SecretKey,
+        paths::Paths,
+        peer::PeerId,
+    };
+
+    fn empty_commit(storage: &Storage) -> git2::Oid {
+        let repo = storage.as_raw();
+        let tree = {
+            let oid = repo.treebuilder(None).unwrap().write().unwrap();
+            repo.find_tree(oid).unwrap()
+        };
+        let author = repo.signature().unwrap();
+        repo.commit(None, &author, &author, "orphan", &tree, &[])
+            .unwrap()
+    }
+
+    #[test]
+    fn removes_orphaned_namespace() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(&tmp).unwrap();
+            let storage = Storage::open(&paths, SecretKey::new()).unwrap();
+            let urn = Urn::new(git2::Oid::zero().into());
+            let head = Reference::head(Namespace::from(&urn), None, reflike!("master"));
+            head.create(
+                storage.as_raw(),
+                empty_commit(&storage),
+                Force::False,
+                "test",
+            )
+            .unwrap();
+
+            let report = storage.gc(Options::default()).unwrap();
+            assert!(report.removed_namespaces.contains(&urn));
+            assert!(!storage.has_ref(&head).unwrap())
+        }
+    }
+
+    #[test]
+    fn keeps_tracked_namespace() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(&tmp).unwrap();
+            let storage = Storage::open(&paths, SecretKey::new()).unwrap();
+            let urn = Urn::new(git2::Oid::zero().into());
+            let remote_peer = PeerId::from(SecretKey::new());
+            tracking::track(&storage, &urn, remote_peer).unwrap();
+
+            let head = Reference::head(Namespace::from(&urn), remote_peer, reflike!("master"));
+            head.create(
+                storage.as_raw(),
+                empty_commit(&storage),
+                Force::False,
+                "test",
+            )
+            .unwrap();
+
+            let report = storage.gc(Options::default()).unwrap();
+            assert!(report.removed_namespaces.is_empty());
+            assert!(storage.has_ref(&head).unwrap())
+        }
+    }
+}
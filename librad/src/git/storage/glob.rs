@@ -7,6 +7,8 @@ use std::path::Path;
 
 use git_ext as ext;
 
+use crate::git::Urn;
+
 pub trait Pattern {
     fn matches<P: AsRef<Path>>(&self, path: P) -> bool;
 }
@@ -23,6 +25,23 @@ impl Pattern for globset::GlobSet {
     }
 }
 
+/// Matches the `rad/ids/*` refs, both our own and those of remote peers, in the
+/// namespace of `urn`, or in all namespaces if `None`.
+pub fn rad_ids(urn: Option<&Urn>) -> globset::GlobSet {
+    let namespace = urn.map_or_else(|| "*".to_owned(), |urn| urn.encode_id());
+    globset::GlobSetBuilder::new()
+        .add(globset::Glob::new(&format!("refs/namespaces/{}/refs/rad/ids/*", namespace)).unwrap())
+        .add(
+            globset::Glob::new(&format!(
+                "refs/namespaces/{}/refs/remotes/*/rad/ids/*",
+                namespace
+            ))
+            .unwrap(),
+        )
+        .build()
+        .unwrap()
+}
+
 #[derive(Clone, Debug)]
 pub struct RefspecMatcher(globset::GlobMatcher);
 
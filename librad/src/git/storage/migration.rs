@@ -0,0 +1,196 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Versioning of the storage layout.
+//!
+//! The layout version of a storage is recorded in its config (see
+//! [`super::Config::layout_version`]). Whenever the layout changes in an
+//! incompatible way, [`CURRENT`] is bumped and a [`Migration`] from the
+//! previous version is added to [`MIGRATIONS`].
+//!
+//! Migrations are run by [`super::Storage::open`]. Since several
+//! [`super::Storage`]s may be opened concurrently (eg. in a [`super::Pool`]),
+//! migrations MUST be idempotent.
+
+use std::convert::TryFrom;
+
+use thiserror::Error;
+
+use super::{config, Config};
+use crate::paths::Paths;
+
+/// The layout version of storages created by this version of the library.
+pub const CURRENT: u32 = 1;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("storage layout version {found} is newer than the supported version {supported}")]
+    Newer { found: u32, supported: u32 },
+
+    #[error("no migration from storage layout version {0}")]
+    Missing(u32),
+
+    #[error("migration from storage layout version {from} failed")]
+    Failed {
+        from: u32,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync + 'static>,
+    },
+
+    #[error(transparent)]
+    Config(#[from] config::Error),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+/// An upgrade of the storage layout from version `from` to `from + 1`.
+pub struct Migration {
+    pub from: u32,
+    pub description: &'static str,
+    run: fn(&git2::Repository) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>>,
+}
+
+/// All known migrations, ordered by [`Migration::from`].
+pub static MIGRATIONS: &[Migration] = &[Migration {
+    from: 0,
+    description: "record the storage layout version",
+    run: v0_record_version,
+}];
+
+/// The unversioned layout is identical to version `1`, so there is nothing to
+/// do besides recording the version.
+fn v0_record_version(
+    _: &git2::Repository,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    Ok(())
+}
+
+/// The [`Migration`]s which need to be run to bring the storage at `paths` up
+/// to the [`CURRENT`] layout, without running them.
+pub fn pending(paths: &Paths) -> Result<Vec<&'static Migration>, Error> {
+    let repo = git2::Repository::open_bare(paths.git_dir())?;
+    let version = Config::readonly(&repo)?.layout_version()?;
+    plan(version)
+}
+
+/// Run all pending [`Migration`]s on `repo`.
+///
+/// The layout version is updated after each successful migration, so an
+/// interrupted upgrade resumes from where it failed.
+#[tracing::instrument(level = "debug", skip(repo), err)]
+pub(super) fn run(repo: &git2::Repository) -> Result<(), Error> {
+    let mut config = Config::try_from(repo)?;
+    let version = config.layout_version()?;
+    for migration in plan(version)? {
+        tracing::info!(
+            from = migration.from,
+            description = migration.description,
+            "migrating storage layout"
+        );
+        (migration.run)(repo).map_err(|source| Error::Failed {
+            from: migration.from,
+            source,
+        })?;
+        config.set_layout_version(migration.from + 1)?;
+    }
+
+    Ok(())
+}
+
+fn plan(version: u32) -> Result<Vec<&'static Migration>, Error> {
+    if version > CURRENT {
+        return Err(Error::Newer {
+            found: version,
+            supported: CURRENT,
+        });
+    }
+
+    (version..CURRENT)
+        .map(|from| {
+            MIGRATIONS
+                .iter()
+                .find(|migration| migration.from == from)
+                .ok_or(Error::Missing(from))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{git::storage::Storage, keys::SecretKey};
+
+    #[test]
+    fn migrations_are_complete() {
+        assert_eq!(plan(0).unwrap().len() as u32, CURRENT);
+        assert!(plan(CURRENT).unwrap().is_empty())
+    }
+
+    #[test]
+    fn upgrade_unversioned() {
+        let tmp = tempfile::tempdir().unwrap();
+        let paths = Paths::from_root(&tmp).unwrap();
+        let key = SecretKey::new();
+        {
+            let storage = Storage::open(&paths, key.clone()).unwrap();
+            // Pretend this is a storage from before the layout was versioned
+            storage
+                .config()
+                .unwrap()
+                .as_raw_mut()
+                .remove("rad.layout")
+                .unwrap();
+        }
+        assert_eq!(pending(&paths).unwrap().len() as u32, CURRENT);
+
+        let storage = Storage::open(&paths, key).unwrap();
+        assert_eq!(storage.config().unwrap().layout_version().unwrap(), CURRENT);
+        assert!(pending(&paths).unwrap().is_empty())
+    }
+
+    #[test]
+    fn no_migration_with_wrong_key() {
+        let tmp = tempfile::tempdir().unwrap();
+        let paths = Paths::from_root(&tmp).unwrap();
+        {
+            let storage = Storage::open(&paths, SecretKey::new()).unwrap();
+            storage
+                .config()
+                .unwrap()
+                .as_raw_mut()
+                .remove("rad.layout")
+                .unwrap();
+        }
+
+        assert!(matches!(
+            Storage::open(&paths, SecretKey::new()),
+            Err(super::super::Error::SignerKeyMismatch)
+        ));
+        assert_eq!(pending(&paths).unwrap().len() as u32, CURRENT);
+    }
+
+    #[test]
+    fn refuse_newer() {
+        let tmp = tempfile::tempdir().unwrap();
+        let paths = Paths::from_root(&tmp).unwrap();
+        let key = SecretKey::new();
+        {
+            let storage = Storage::open(&paths, key.clone()).unwrap();
+            storage
+                .config()
+                .unwrap()
+                .set_layout_version(CURRENT + 1)
+                .unwrap();
+        }
+
+        assert!(matches!(
+            Storage::open(&paths, key),
+            Err(super::super::Error::Migration(Error::Newer { .. }))
+        ))
+    }
+}
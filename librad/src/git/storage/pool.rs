@@ -10,12 +10,15 @@ use std::{
 
 use deadpool::managed::{self, Manager, Object, RecycleResult};
 
-use super::{Error, Fetchers, Storage};
+use super::{Error, Fetchers, ReadOnly, Storage};
 use crate::{paths::Paths, signer::Signer};
 
 pub type Pool = deadpool::managed::Pool<Storage, Error>;
 pub type PoolError = managed::PoolError<Error>;
 
+/// A pool of [`ReadOnly`] storages, see [`ReadOnlyConfig`].
+pub type ReadOnlyPool = deadpool::managed::Pool<ReadOnly, Error>;
+
 #[async_trait]
 pub trait Pooled {
     async fn get(&self) -> Result<PooledRef, PoolError>;
@@ -51,6 +54,12 @@ impl AsRef<Storage> for PooledRef {
     }
 }
 
+impl AsRef<ReadOnly> for PooledRef {
+    fn as_ref(&self) -> &ReadOnly {
+        self
+    }
+}
+
 impl AsMut<Storage> for PooledRef {
     fn as_mut(&mut self) -> &mut Storage {
         self
@@ -105,3 +114,30 @@ where
         Ok(())
     }
 }
+
+/// [`Manager`] for a [`ReadOnlyPool`].
+///
+/// Note that, unlike [`Config`], this does not initialise the storage: the
+/// [`ReadOnly`] handles can only be created once a [`Storage`] was opened at
+/// the same [`Paths`].
+#[derive(Clone)]
+pub struct ReadOnlyConfig {
+    paths: Paths,
+}
+
+impl ReadOnlyConfig {
+    pub fn new(paths: Paths) -> Self {
+        Self { paths }
+    }
+}
+
+#[async_trait]
+impl Manager<ReadOnly, Error> for ReadOnlyConfig {
+    async fn create(&self) -> Result<ReadOnly, Error> {
+        ReadOnly::open(&self.paths)
+    }
+
+    async fn recycle(&self, _: &mut ReadOnly) -> RecycleResult<Error> {
+        Ok(())
+    }
+}
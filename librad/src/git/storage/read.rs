@@ -0,0 +1,342 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{convert::TryFrom, fmt::Debug, marker::PhantomData, path::Path};
+
+use git_ext::{self as ext, blob, is_not_found_err, RefLike, RefspecPattern};
+use std_ext::result::ResultExt as _;
+
+use super::{
+    super::types::{Many, One, Reference},
+    events,
+    glob,
+    migration,
+    Config,
+    Error,
+    Pattern,
+    Urn,
+};
+use crate::{identities::git::Identities, paths::Paths, peer::PeerId};
+
+/// The read-only half of [`super::Storage`].
+///
+/// Unlike [`super::Storage`], a [`ReadOnly`] storage can be opened without
+/// access to the signer it was initialised with, which makes it suitable for
+/// inspection tools. [`super::Storage`] dereferences to [`ReadOnly`], so
+/// functions which only read from the storage should take a `&ReadOnly`.
+pub struct ReadOnly {
+    pub(super) backend: git2::Repository,
+    pub(super) peer_id: PeerId,
+}
+
+impl ReadOnly {
+    /// Open an existing storage at `paths` for reading.
+    ///
+    /// Unlike [`super::Storage::open`], this does not initialise the storage
+    /// if it doesn't exist, nor does it run any [`migration`]s. An error is
+    /// returned if the storage layout is newer than what this version of the
+    /// library understands.
+    pub fn open(paths: &Paths) -> Result<Self, Error> {
+        crate::git::init();
+
+        let backend = git2::Repository::open_bare(paths.git_dir())?;
+        let config = Config::readonly(&backend)?;
+        let version = config.layout_version()?;
+        if version > migration::CURRENT {
+            return Err(migration::Error::Newer {
+                found: version,
+                supported: migration::CURRENT,
+            }
+            .into());
+        }
+        let peer_id = config.peer_id()?;
+
+        Ok(Self { backend, peer_id })
+    }
+
+    pub fn peer_id(&self) -> &PeerId {
+        &self.peer_id
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.backend.path()
+    }
+
+    /// Subscribe to the [`events::Event`]s of this storage, see
+    /// [`events::subscribe`].
+    pub fn subscribe(
+        &self,
+    ) -> impl futures::Stream<Item = Result<events::Event, events::RecvError>> {
+        events::subscribe_at(self.path())
+    }
+
+    pub(crate) fn emit(&self, event: events::Event) {
+        events::emit(self.path(), event)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self), err)]
+    pub fn has_urn(&self, urn: &Urn) -> Result<bool, Error> {
+        self.has_ref(&Reference::try_from(urn)?)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self), err)]
+    pub fn has_ref<'a>(&self, reference: &'a Reference<One>) -> Result<bool, Error> {
+        self.backend
+            .find_reference(RefLike::from(reference).as_str())
+            .and(Ok(true))
+            .or_matches(is_not_found_err, || Ok(false))
+    }
+
+    /// Check the existence of `oid` as a **commit**.
+    ///
+    /// The result will be `false` if:
+    ///
+    /// 1. No commit could be found for `oid`
+    /// 2. The reference path for the `urn` could not be found (it defaults to
+    /// `rad/id` if not provided)
+    /// 3. The tip SHA was not in the history of the commit
+    /// 4. The `oid` was the [`zero`][`git2::Oid::zero`] SHA.
+    #[tracing::instrument(level = "debug", skip(self, urn), fields(urn = %urn), err)]
+    pub fn has_commit<Oid>(&self, urn: &Urn, oid: Oid) -> Result<bool, Error>
+    where
+        Oid: AsRef<git2::Oid> + Debug,
+    {
+        let (oid, kind) = match self.find_object(oid)? {
+            None => return Ok(false),
+            Some(object) => match object.kind() {
+                Some(git2::ObjectType::Commit) => (object.id(), git2::ObjectType::Commit),
+                _ => return Ok(false),
+            },
+        };
+
+        let tip = self.tip(urn, kind)?;
+        Ok(tip
+            .map(|tip| {
+                Ok::<_, git2::Error>(
+                    tip.id() == oid || self.backend.graph_descendant_of(tip.id(), oid)?,
+                )
+            })
+            .transpose()?
+            .unwrap_or(false))
+    }
+
+    /// Check the existence of `oid` as a **tag**.
+    ///
+    /// The result will be `false` if:
+    ///
+    /// 1. No tag could be found for `oid`
+    /// 2. The reference path for the `urn` could not be found (it defaults to
+    /// `rad/id` if not provided)
+    /// 3. The SHA of the tag was not the same as the resolved reference
+    /// 4. The `oid` was the [`zero`][`git2::Oid::zero`] SHA.
+    #[tracing::instrument(level = "debug", skip(self, urn), fields(urn = %urn), err)]
+    pub fn has_tag<Oid>(&self, urn: &Urn, oid: Oid) -> Result<bool, Error>
+    where
+        Oid: AsRef<git2::Oid> + Debug,
+    {
+        let (oid, kind) = match self.find_object(oid)? {
+            None => return Ok(false),
+            Some(object) => match object.kind() {
+                Some(git2::ObjectType::Tag) => (object.id(), git2::ObjectType::Tag),
+                _ => return Ok(false),
+            },
+        };
+
+        let tip = self.tip(urn, kind)?;
+        Ok(tip.map(|tip| tip.id() == oid).unwrap_or(false))
+    }
+
+    #[tracing::instrument(level = "debug", skip(self), err)]
+    pub fn has_object<Oid>(&self, oid: Oid) -> Result<bool, Error>
+    where
+        Oid: AsRef<git2::Oid> + Debug,
+    {
+        let oid = oid.as_ref();
+        if oid.is_zero() {
+            // XXX: should this be a panic or error?
+            tracing::warn!("zero oid");
+            return Ok(false);
+        }
+
+        Ok(self.backend.odb()?.exists(*oid))
+    }
+
+    #[tracing::instrument(level = "debug", skip(self), err)]
+    pub fn find_object<Oid>(&self, oid: Oid) -> Result<Option<git2::Object>, Error>
+    where
+        Oid: AsRef<git2::Oid> + Debug,
+    {
+        let oid = oid.as_ref();
+        if oid.is_zero() {
+            return Ok(None);
+        }
+
+        self.backend
+            .find_object(*oid, None)
+            .map(Some)
+            .or_matches(is_not_found_err, || Ok(None))
+    }
+
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub fn tip(&self, urn: &Urn, kind: git2::ObjectType) -> Result<Option<git2::Object>, Error> {
+        let reference = self
+            .backend
+            .find_reference(RefLike::from(&Reference::try_from(urn)?).as_str())
+            .map(Some)
+            .or_matches::<Error, _, _>(is_not_found_err, || Ok(None))?;
+
+        match reference {
+            None => Ok(None),
+            Some(r) => r.peel(kind).map(Some).map_err(Error::from),
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub fn reference<'a>(
+        &'a self,
+        reference: &Reference<One>,
+    ) -> Result<Option<git2::Reference<'a>>, Error> {
+        reference
+            .find(&self.backend)
+            .map(Some)
+            .or_matches(is_not_found_err, || Ok(None))
+    }
+
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub fn references<'a>(
+        &'a self,
+        reference: &Reference<Many>,
+    ) -> Result<impl Iterator<Item = Result<git2::Reference<'a>, Error>> + 'a, Error> {
+        self.references_glob(glob::RefspecMatcher::from(RefspecPattern::from(reference)))
+    }
+
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub fn reference_names<'a>(
+        &'a self,
+        reference: &Reference<Many>,
+    ) -> Result<impl Iterator<Item = Result<ext::RefLike, Error>> + 'a, Error> {
+        self.reference_names_glob(glob::RefspecMatcher::from(RefspecPattern::from(reference)))
+    }
+
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub fn references_glob<'a, G: 'a>(
+        &'a self,
+        glob: G,
+    ) -> Result<impl Iterator<Item = Result<git2::Reference<'a>, Error>> + 'a, Error>
+    where
+        G: Pattern + Debug,
+    {
+        Ok(self
+            .backend
+            .references()?
+            .filter_map(move |reference| match reference {
+                Ok(reference) => match reference.name() {
+                    Some(name) if glob.matches(name) => Some(Ok(reference)),
+                    _ => None,
+                },
+
+                Err(e) => Some(Err(e.into())),
+            }))
+    }
+
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub fn reference_names_glob<'a, G: 'a>(
+        &'a self,
+        glob: G,
+    ) -> Result<impl Iterator<Item = Result<ext::RefLike, Error>> + 'a, Error>
+    where
+        G: Pattern + Debug,
+    {
+        let iter = ReferenceNames {
+            iter: self.backend.references()?,
+        };
+        Ok(iter.filter_map(move |refname| match refname {
+            Ok(reflike) if glob.matches(Path::new(reflike.as_str())) => Some(Ok(reflike)),
+            Ok(_) => None,
+
+            Err(e) => Some(Err(e)),
+        }))
+    }
+
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub fn blob<'a>(
+        &'a self,
+        reference: &'a Reference<One>,
+        path: &'a Path,
+    ) -> Result<Option<git2::Blob<'a>>, Error> {
+        ext::Blob::Tip {
+            branch: reference.into(),
+            path,
+        }
+        .get(self.as_raw())
+        .map(Some)
+        .or_matches(|e| matches!(e, blob::Error::NotFound(_)), || Ok(None))
+    }
+
+    pub fn config(&self) -> Result<Config<'_, PhantomData<!>>, Error> {
+        Ok(Config::readonly(&self.backend)?)
+    }
+
+    pub(in crate::git) fn identities<'a, T: 'a>(&'a self) -> Identities<'a, T> {
+        Identities::from(self.as_raw())
+    }
+
+    // TODO: we would need to wrap a few more low-level git operations (such as:
+    // create commit, manipulate refs, manipulate config) in order to be able to
+    // model "capabilities" in terms of traits.
+    pub(in crate::git) fn as_raw(&self) -> &git2::Repository {
+        &self.backend
+    }
+}
+
+impl AsRef<ReadOnly> for ReadOnly {
+    fn as_ref(&self) -> &Self {
+        self
+    }
+}
+
+struct ReferenceNames<'a> {
+    iter: git2::References<'a>,
+}
+
+impl<'a> Iterator for ReferenceNames<'a> {
+    type Item = Result<ext::RefLike, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let names = self.iter.names();
+        for name in names {
+            match name {
+                Err(e) => return Some(Err(e.into())),
+                Ok(name) => match ext::RefLike::try_from(name).ok() {
+                    Some(refl) => return Some(Ok(refl)),
+                    None => continue,
+                },
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{git::storage::Storage, keys::SecretKey};
+
+    #[test]
+    fn open_without_signer() {
+        let tmp = tempfile::tempdir().unwrap();
+        let paths = Paths::from_root(&tmp).unwrap();
+        let key = SecretKey::new();
+
+        assert!(ReadOnly::open(&paths).is_err());
+
+        let storage = Storage::open(&paths, key.clone()).unwrap();
+        let read_only = ReadOnly::open(&paths).unwrap();
+        assert_eq!(read_only.peer_id(), storage.peer_id());
+        assert_eq!(*read_only.peer_id(), PeerId::from(key));
+    }
+}
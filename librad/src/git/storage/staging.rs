@@ -0,0 +1,233 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Staging of fetched refs.
+//!
+//! `git` updates the refs matched by a fetch one by one, so a fetch which is
+//! interrupted may leave some of them updated and some not. To avoid this, a
+//! [`Staging`] area rewrites the destinations of the fetchspecs to point to a
+//! scratch location outside of any namespace. Once the fetch is done, the
+//! staged refs are moved into place in a single [`Transaction`].
+//!
+//! [`Transaction`]: super::Transaction
+
+use std::{collections::BTreeMap, convert::TryFrom};
+
+use git_ext::{self as ext, is_not_found_err};
+use std_ext::result::ResultExt as _;
+
+use super::{
+    transaction::{Error, Expected},
+    Storage,
+};
+use crate::git::types::Fetchspec;
+
+/// A scratch location to fetch into, see the [module docs](self).
+pub struct Staging {
+    prefix: String,
+}
+
+impl Default for Staging {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Staging {
+    const FORCED: &'static str = "forced";
+    const FAST_FORWARD: &'static str = "ff";
+
+    /// Create a fresh [`Staging`] area, unique to this fetch.
+    pub fn new() -> Self {
+        Self {
+            prefix: format!("refs/staging/{:016x}", rand::random::<u64>()),
+        }
+    }
+
+    /// Rewrite `specs` to fetch into the staging area.
+    ///
+    /// The staged refs are always updated forcibly: whether a non-forced
+    /// update is a fast-forward is checked by [`Staging::apply`] instead.
+    pub fn refspecs(&self, specs: impl IntoIterator<Item = Fetchspec>) -> Vec<String> {
+        specs
+            .into_iter()
+            .map(|spec| self.stage(&spec.to_string()))
+            .collect()
+    }
+
+    /// Move the staged refs into place in a single transaction, and remove the
+    /// staging area.
+    ///
+    /// Staged refs whose fetchspec was not forced are skipped if they would
+    /// rewrite history. The refs which were updated are returned, along with
+    /// their new targets.
+    #[tracing::instrument(level = "debug", skip(self, storage), fields(prefix = %self.prefix), err)]
+    pub fn apply(self, storage: &Storage) -> Result<BTreeMap<ext::RefLike, ext::Oid>, Error> {
+        let repo = storage.as_raw();
+        let mut tx = storage.transaction("fetch");
+        let mut updated = BTreeMap::new();
+
+        for staged in repo.references_glob(&format!("{}/*", self.prefix))? {
+            let staged = staged?;
+            let (name, target) = match (staged.name(), staged.target()) {
+                (Some(name), Some(target)) => (name, target),
+                _ => continue,
+            };
+            let (forced, dst) = match self.destination(name) {
+                Some(dst) => dst,
+                None => continue,
+            };
+            if let Ok(name) = ext::RefLike::try_from(name) {
+                tx.delete(name, Expected::Oid(target));
+            }
+            let dst = match ext::RefLike::try_from(dst) {
+                Ok(dst) => dst,
+                Err(e) => {
+                    tracing::warn!("invalid refname `{}`: {}", dst, e);
+                    continue;
+                },
+            };
+
+            let current = repo
+                .refname_to_id(dst.as_str())
+                .map(Some)
+                .or_matches::<Error, _, _>(is_not_found_err, || Ok(None))?;
+            match current {
+                Some(current) if current == target => continue,
+                Some(current) if !forced && !repo.graph_descendant_of(target, current)? => {
+                    tracing::warn!(
+                        "rejecting non-fast-forward update of {}: {} -> {}",
+                        dst,
+                        current,
+                        target
+                    );
+                    continue;
+                },
+                _ => {},
+            }
+
+            tracing::debug!("updating tip {}: {:?} -> {}", dst, current, target);
+            tx.update(dst.clone(), target, Expected::from(current));
+            updated.insert(dst, target.into());
+        }
+        tx.commit()?;
+
+        Ok(updated)
+    }
+
+    /// Remove the staging area without applying it.
+    pub fn discard(self, storage: &Storage) -> Result<(), Error> {
+        let mut tx = storage.transaction("discard fetch");
+        for staged in storage
+            .as_raw()
+            .references_glob(&format!("{}/*", self.prefix))?
+        {
+            if let Some(Ok(name)) = staged?.name().map(ext::RefLike::try_from) {
+                tx.delete(name, Expected::Any);
+            }
+        }
+        tx.commit()
+    }
+
+    fn stage(&self, spec: &str) -> String {
+        let forced = spec.starts_with('+');
+        let mut parts = spec.trim_start_matches('+').splitn(2, ':');
+        let src = parts.next().unwrap_or_default();
+        let dst = parts.next().unwrap_or_default();
+        format!(
+            "+{}:{}/{}/{}",
+            src,
+            self.prefix,
+            if forced {
+                Self::FORCED
+            } else {
+                Self::FAST_FORWARD
+            },
+            dst.strip_prefix("refs/").unwrap_or(dst)
+        )
+    }
+
+    /// Map the name of a staged ref back to its destination, and whether it
+    /// may be updated forcibly.
+    fn destination(&self, staged: &str) -> Option<(bool, String)> {
+        let rest = staged.strip_prefix(&self.prefix)?.strip_prefix('/')?;
+        let (mode, dst) = rest.split_at(rest.find('/')?);
+        let forced = match mode {
+            Self::FORCED => true,
+            Self::FAST_FORWARD => false,
+            _ => return None,
+        };
+        Some((forced, format!("refs{}", dst)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{keys::SecretKey, paths::Paths};
+
+    fn commit(storage: &Storage, message: &str, parent: Option<git2::Oid>) -> git2::Oid {
+        let repo = storage.as_raw();
+        let tree = {
+            let oid = repo.treebuilder(None).unwrap().write().unwrap();
+            repo.find_tree(oid).unwrap()
+        };
+        let parent = parent.map(|oid| repo.find_commit(oid).unwrap());
+        let author = repo.signature().unwrap();
+        repo.commit(
+            None,
+            &author,
+            &author,
+            message,
+            &tree,
+            parent.as_ref().into_iter().collect::<Vec<_>>().as_slice(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn apply_moves_staged_refs_into_place() {
+        let tmp = tempfile::tempdir().unwrap();
+        let paths = Paths::from_root(tmp.path()).unwrap();
+        let storage = Storage::open(&paths, SecretKey::new()).unwrap();
+        let repo = storage.as_raw();
+
+        let base = commit(&storage, "base", None);
+        let ahead = commit(&storage, "ahead", Some(base));
+        let fork = commit(&storage, "fork", None);
+        for name in &["refs/heads/ff", "refs/heads/rewrite", "refs/heads/forced"] {
+            repo.reference(name, base, false, "").unwrap();
+        }
+
+        let staging = Staging::new();
+        let refspecs = staging.refspecs(vec![
+            Fetchspec::try_from("refs/heads/*:refs/heads/*").unwrap(),
+            Fetchspec::try_from("+refs/forced/*:refs/heads/*").unwrap(),
+        ]);
+        let staged = |spec: &str, name: &str| spec.rsplit(':').next().unwrap().replace('*', name);
+        repo.reference(&staged(&refspecs[0], "ff"), ahead, false, "")
+            .unwrap();
+        repo.reference(&staged(&refspecs[0], "rewrite"), fork, false, "")
+            .unwrap();
+        repo.reference(&staged(&refspecs[0], "new"), fork, false, "")
+            .unwrap();
+        repo.reference(&staged(&refspecs[1], "forced"), fork, false, "")
+            .unwrap();
+
+        let updated = staging.apply(&storage).unwrap();
+        assert_eq!(
+            updated.keys().map(|name| name.as_str()).collect::<Vec<_>>(),
+            vec!["refs/heads/ff", "refs/heads/forced", "refs/heads/new"]
+        );
+
+        let target = |name: &str| repo.refname_to_id(name).unwrap();
+        assert_eq!(target("refs/heads/ff"), ahead);
+        assert_eq!(target("refs/heads/rewrite"), base);
+        assert_eq!(target("refs/heads/forced"), fork);
+        assert_eq!(target("refs/heads/new"), fork);
+        assert_eq!(repo.references_glob("refs/staging/*").unwrap().count(), 0);
+    }
+}
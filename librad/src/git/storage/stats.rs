@@ -0,0 +1,347 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{
+    collections::BTreeSet,
+    io::{self, Write as _},
+    ops::AddAssign,
+    process::{Command, ExitStatus, Stdio},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use thiserror::Error;
+
+use super::{glob, ReadOnly};
+use crate::git::{identities, tracking, Urn};
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("`git {cmd}` exited unsuccessfully: {status}")]
+    Child {
+        cmd: &'static str,
+        status: ExitStatus,
+    },
+
+    #[error("unexpected output from `git cat-file`: {0}")]
+    CatFile(String),
+
+    #[error(transparent)]
+    Identities(#[from] Box<identities::error::Error>),
+
+    #[error(transparent)]
+    Track(#[from] tracking::Error),
+
+    #[error(transparent)]
+    Store(#[from] super::Error),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl From<identities::error::Error> for Error {
+    fn from(e: identities::error::Error) -> Self {
+        Self::Identities(Box::new(e))
+    }
+}
+
+/// Number of refs per category, counting both our own and the remote refs.
+///
+/// See also [`crate::git::refs::Refs`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RefCounts {
+    pub heads: usize,
+    pub rad: usize,
+    pub tags: usize,
+    pub notes: usize,
+    /// Refs which don't fall into any of the above categories.
+    pub other: usize,
+}
+
+impl RefCounts {
+    pub fn total(&self) -> usize {
+        self.heads + self.rad + self.tags + self.notes + self.other
+    }
+
+    fn count(&mut self, category: &str) {
+        match category {
+            "heads" => self.heads += 1,
+            "rad" => self.rad += 1,
+            "tags" => self.tags += 1,
+            "notes" => self.notes += 1,
+            _ => self.other += 1,
+        }
+    }
+}
+
+impl AddAssign for RefCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.heads += other.heads;
+        self.rad += other.rad;
+        self.tags += other.tags;
+        self.notes += other.notes;
+        self.other += other.other;
+    }
+}
+
+/// Statistics about a single namespace, as returned by [`ReadOnly::stats`].
+#[derive(Clone, Debug)]
+pub struct Stats {
+    pub urn: Urn,
+    /// Number of 1st degree tracked peers.
+    pub tracked_peers: usize,
+    pub refs: RefCounts,
+    /// Number of objects reachable from the refs of the namespace.
+    pub objects: usize,
+    /// Approximate on-disk size of [`Self::objects`], in bytes.
+    ///
+    /// This is approximate, because objects stored as deltas are accounted the
+    /// size of the delta, not the size of the base object.
+    pub size: u64,
+    /// The time of the most recent update of any ref of the namespace, if
+    /// known.
+    ///
+    /// This is determined from the reflog if available, and from the commit
+    /// time of the tips otherwise.
+    pub last_update: Option<SystemTime>,
+}
+
+/// Statistics aggregated over all namespaces, as returned by
+/// [`ReadOnly::stats_all`].
+#[derive(Clone, Debug, Default)]
+pub struct Aggregate {
+    pub namespaces: usize,
+    pub tracked_peers: usize,
+    pub refs: RefCounts,
+    /// Number of objects reachable from any namespace. Objects shared between
+    /// namespaces are counted only once.
+    pub objects: usize,
+    /// Approximate on-disk size of [`Self::objects`], in bytes.
+    pub size: u64,
+    pub last_update: Option<SystemTime>,
+}
+
+impl ReadOnly {
+    /// Compute [`Stats`] for the namespace `urn`.
+    #[tracing::instrument(skip(self), err)]
+    pub fn stats(&self, urn: &Urn) -> Result<Stats, Error> {
+        let (stats, _) = self.stats_with_tips(urn)?;
+        Ok(stats)
+    }
+
+    /// Compute [`Stats`] for all namespaces which have an identity, and an
+    /// [`Aggregate`] thereof.
+    #[tracing::instrument(skip(self), err)]
+    pub fn stats_all(&self) -> Result<(Vec<Stats>, Aggregate), Error> {
+        let mut all = Vec::new();
+        let mut agg = Aggregate::default();
+        let mut all_tips = BTreeSet::new();
+        for urn in identities::any::list_urns(self)? {
+            let (stats, mut tips) = self.stats_with_tips(&urn?)?;
+
+            agg.namespaces += 1;
+            agg.tracked_peers += stats.tracked_peers;
+            agg.refs += stats.refs;
+            agg.last_update = agg.last_update.max(stats.last_update);
+            all_tips.append(&mut tips);
+
+            all.push(stats);
+        }
+        let (objects, size) = self.objects(&all_tips)?;
+        agg.objects = objects;
+        agg.size = size;
+
+        Ok((all, agg))
+    }
+
+    fn stats_with_tips(&self, urn: &Urn) -> Result<(Stats, BTreeSet<git2::Oid>), Error> {
+        let tracked_peers = tracking::tracked_peers(self, urn)?.count();
+
+        let namespace = reflike!("refs/namespaces").join(urn);
+        let own = namespace.join(reflike!("refs"));
+        let remotes = own.join(reflike!("remotes"));
+
+        let mut refs = RefCounts::default();
+        let mut tips = BTreeSet::new();
+        let mut last_update = None;
+        for reference in self.references_glob(glob::RefspecMatcher::from(
+            namespace.with_pattern_suffix(refspec_pattern!("*")),
+        ))? {
+            let reference = reference?;
+            let name = match reference.name() {
+                Some(name) => name,
+                None => continue,
+            };
+
+            // `<category>/...` or `remotes/<peer>/<category>/...`
+            let category = match name.strip_prefix(&format!("{}/", remotes.as_str())) {
+                Some(suffix) => suffix.splitn(3, '/').nth(1),
+                None => name
+                    .strip_prefix(&format!("{}/", own.as_str()))
+                    .and_then(|suffix| suffix.split('/').next()),
+            };
+            refs.count(category.unwrap_or_default());
+
+            if let Some(target) = reference.target() {
+                tips.insert(target);
+            }
+            last_update = last_update.max(self.last_update(&reference)?);
+        }
+
+        let (objects, size) = self.objects(&tips)?;
+
+        Ok((
+            Stats {
+                urn: urn.clone(),
+                tracked_peers,
+                refs,
+                objects,
+                size,
+                last_update,
+            },
+            tips,
+        ))
+    }
+
+    fn last_update(&self, reference: &git2::Reference) -> Result<Option<SystemTime>, Error> {
+        let from_reflog = match reference.name() {
+            Some(name) if self.backend.reference_has_log(name)? => self
+                .backend
+                .reflog(name)?
+                .get(0)
+                .map(|entry| entry.committer().when()),
+            _ => None,
+        };
+        let when = match from_reflog {
+            Some(when) => Some(when),
+            None => reference
+                .peel_to_commit()
+                .ok()
+                .map(|commit| commit.committer().when()),
+        };
+
+        Ok(when.map(|when| {
+            if when.seconds() < 0 {
+                UNIX_EPOCH
+            } else {
+                UNIX_EPOCH + Duration::from_secs(when.seconds() as u64)
+            }
+        }))
+    }
+
+    /// Count the objects reachable from `tips`, and sum up their on-disk size.
+    fn objects(&self, tips: &BTreeSet<git2::Oid>) -> Result<(usize, u64), Error> {
+        if tips.is_empty() {
+            return Ok((0, 0));
+        }
+
+        let objects = pipe(
+            self.git()
+                .args(&["rev-list", "--objects", "--no-object-names", "--stdin"]),
+            "rev-list",
+            tips.iter()
+                .map(|oid| format!("{}\n", oid))
+                .collect::<String>()
+                .as_bytes(),
+        )?;
+        let sizes = pipe(
+            self.git()
+                .args(&["cat-file", "--batch-check=%(objectsize:disk)"]),
+            "cat-file",
+            &objects,
+        )?;
+
+        String::from_utf8_lossy(&sizes)
+            .lines()
+            .try_fold((0, 0), |(count, size), line| {
+                let object_size = line
+                    .trim()
+                    .parse::<u64>()
+                    .map_err(|_| Error::CatFile(line.to_owned()))?;
+                Ok((count + 1, size + object_size))
+            })
+    }
+
+    fn git(&self) -> Command {
+        let mut git = Command::new("git");
+        git.envs(::std::env::vars().filter(|(key, _)| key.starts_with("GIT_TRACE")))
+            .current_dir(self.path());
+        git
+    }
+}
+
+/// Run `cmd`, feeding it `input` on stdin, and return its stdout.
+fn pipe(cmd: &mut Command, name: &'static str, input: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+
+    // Write from a separate thread, so we don't deadlock if the child fills up
+    // its stdout pipe before consuming all of stdin.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = input.to_vec();
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+
+    let out = child.wait_with_output()?;
+    writer.join().expect("stdin writer panicked")?;
+    if !out.status.success() {
+        return Err(Error::Child {
+            cmd: name,
+            status: out.status,
+        });
+    }
+
+    Ok(out.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        git::storage::Storage,
+        identities::payload,
+        keys::SecretKey,
+        paths::Paths,
+        peer::PeerId,
+    };
+
+    #[test]
+    fn person_stats() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let key = SecretKey::new();
+            let paths = Paths::from_root(&tmp).unwrap();
+            let storage = Storage::open(&paths, key.clone()).unwrap();
+            let person = identities::person::create(
+                &storage,
+                payload::Person {
+                    name: "dylan".into(),
+                },
+                Some(key.public()).into_iter().collect(),
+            )
+            .unwrap();
+            let urn = person.urn();
+            tracking::track(&storage, &urn, PeerId::from(SecretKey::new())).unwrap();
+
+            let stats = storage.stats(&urn).unwrap();
+            assert_eq!(stats.tracked_peers, 1);
+            assert!(stats.refs.rad >= 2); // `rad/id` and `rad/signed_refs`
+            assert!(stats.objects > 0);
+            assert!(stats.size > 0);
+            assert!(stats.last_update.is_some());
+
+            let (all, agg) = storage.stats_all().unwrap();
+            assert_eq!(all.len(), 1);
+            assert_eq!(agg.namespaces, 1);
+            assert_eq!(agg.objects, stats.objects);
+        }
+    }
+}
@@ -0,0 +1,257 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::collections::BTreeMap;
+
+use git_ext::{self as ext, is_not_found_err};
+use std_ext::result::ResultExt as _;
+use thiserror::Error;
+
+use super::Storage;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("expected {name} to be {expected:?}, but it is {actual:?}")]
+    Mismatch {
+        name: ext::RefLike,
+        expected: Expected,
+        actual: Option<git2::Oid>,
+    },
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+/// The value a ref is expected to have before a [`Transaction`] is applied.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Expected {
+    /// Don't care.
+    Any,
+    /// The ref must not exist.
+    Absent,
+    /// The ref must exist and (after peeling symbolic refs) point to the given
+    /// object.
+    Oid(git2::Oid),
+}
+
+impl From<Option<git2::Oid>> for Expected {
+    fn from(oid: Option<git2::Oid>) -> Self {
+        oid.map(Self::Oid).unwrap_or(Self::Absent)
+    }
+}
+
+impl Expected {
+    fn matches(&self, actual: Option<git2::Oid>) -> bool {
+        match (self, actual) {
+            (Self::Any, _) => true,
+            (Self::Absent, None) => true,
+            (Self::Oid(expected), Some(actual)) => *expected == actual,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Update {
+    Direct(git2::Oid),
+    Symbolic(ext::RefLike),
+    Delete,
+}
+
+/// A batch of ref creations, updates and deletions which is applied in an
+/// all-or-nothing fashion.
+///
+/// When [`Transaction::commit`]ted, all affected refs are locked first, then
+/// their current values are checked against the [`Expected`] ones. Only if
+/// all of them match, the updates are applied. Otherwise, the locks are
+/// released and nothing is changed.
+///
+/// Staging more than one operation for the same ref replaces the previous one.
+#[must_use = "a transaction does nothing unless committed"]
+pub struct Transaction<'a> {
+    storage: &'a Storage,
+    message: String,
+    updates: BTreeMap<ext::RefLike, (Update, Expected)>,
+}
+
+impl Storage {
+    /// Start a new [`Transaction`]. `message` is used for the reflog entries of
+    /// all updated refs.
+    pub fn transaction(&self, message: impl Into<String>) -> Transaction<'_> {
+        Transaction {
+            storage: self,
+            message: message.into(),
+            updates: BTreeMap::new(),
+        }
+    }
+}
+
+impl<'a> Transaction<'a> {
+    /// Create the direct ref `name` pointing to `target`. The ref must not
+    /// exist.
+    pub fn create(&mut self, name: impl Into<ext::RefLike>, target: git2::Oid) -> &mut Self {
+        self.update(name, target, Expected::Absent)
+    }
+
+    /// Set the direct ref `name` to `target`, creating it if it doesn't exist
+    /// (and `expected` permits it).
+    pub fn update(
+        &mut self,
+        name: impl Into<ext::RefLike>,
+        target: git2::Oid,
+        expected: Expected,
+    ) -> &mut Self {
+        self.updates
+            .insert(name.into(), (Update::Direct(target), expected));
+        self
+    }
+
+    /// Set the symbolic ref `name` to point to the ref `target`.
+    pub fn symbolic(
+        &mut self,
+        name: impl Into<ext::RefLike>,
+        target: impl Into<ext::RefLike>,
+        expected: Expected,
+    ) -> &mut Self {
+        self.updates
+            .insert(name.into(), (Update::Symbolic(target.into()), expected));
+        self
+    }
+
+    /// Delete the ref `name`. Deleting a ref which doesn't exist is not an
+    /// error, unless `expected` requires it to exist.
+    pub fn delete(&mut self, name: impl Into<ext::RefLike>, expected: Expected) -> &mut Self {
+        self.updates.insert(name.into(), (Update::Delete, expected));
+        self
+    }
+
+    /// `true` if no operations have been staged.
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty()
+    }
+
+    /// Apply all staged operations.
+    #[tracing::instrument(level = "debug", skip(self), fields(message = %self.message), err)]
+    pub fn commit(self) -> Result<(), Error> {
+        if self.updates.is_empty() {
+            return Ok(());
+        }
+
+        let repo = self.storage.as_raw();
+        let mut tx = repo.transaction()?;
+        for name in self.updates.keys() {
+            tx.lock_ref(name.as_str())?;
+        }
+
+        let mut deletions = Vec::new();
+        for (name, (update, expected)) in &self.updates {
+            let actual = current(repo, name)?;
+            if !expected.matches(actual) {
+                return Err(Error::Mismatch {
+                    name: name.clone(),
+                    expected: *expected,
+                    actual,
+                });
+            }
+
+            match update {
+                Update::Direct(target) => {
+                    tx.set_target(name.as_str(), *target, None, &self.message)?
+                },
+                Update::Symbolic(target) => {
+                    tx.set_symbolic_target(name.as_str(), target.as_str(), None, &self.message)?
+                },
+                Update::Delete => {
+                    // Removing a non-existent ref would fail the commit
+                    if exists(repo, name)? {
+                        deletions.push(name);
+                    }
+                },
+            }
+        }
+        for name in deletions {
+            tx.remove(name.as_str())?;
+        }
+
+        tracing::trace!(updates = self.updates.len(), "committing ref transaction");
+        Ok(tx.commit()?)
+    }
+}
+
+fn current(repo: &git2::Repository, name: &ext::RefLike) -> Result<Option<git2::Oid>, Error> {
+    repo.find_reference(name.as_str())
+        .and_then(|r| r.resolve())
+        .map(|r| r.target())
+        .or_matches(is_not_found_err, || Ok(None))
+}
+
+fn exists(repo: &git2::Repository, name: &ext::RefLike) -> Result<bool, Error> {
+    repo.find_reference(name.as_str())
+        .and(Ok(true))
+        .or_matches(is_not_found_err, || Ok(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{keys::SecretKey, paths::Paths};
+
+    fn empty_commit(storage: &Storage) -> git2::Oid {
+        let repo = storage.as_raw();
+        let tree = {
+            let oid = repo.treebuilder(None).unwrap().write().unwrap();
+            repo.find_tree(oid).unwrap()
+        };
+        let author = repo.signature().unwrap();
+        repo.commit(None, &author, &author, "empty", &tree, &[])
+            .unwrap()
+    }
+
+    #[test]
+    fn all_or_nothing() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(&tmp).unwrap();
+            let storage = Storage::open(&paths, SecretKey::new()).unwrap();
+            let oid = empty_commit(&storage);
+
+            let mut tx = storage.transaction("test");
+            tx.create(reflike!("refs/heads/one"), oid);
+            tx.commit().unwrap();
+
+            let mut tx = storage.transaction("test");
+            tx.create(reflike!("refs/heads/two"), oid)
+                .create(reflike!("refs/heads/one"), oid);
+            assert!(matches!(tx.commit(), Err(Error::Mismatch { .. })));
+
+            let repo = storage.as_raw();
+            assert!(repo.find_reference("refs/heads/one").is_ok());
+            assert!(repo.find_reference("refs/heads/two").is_err());
+        }
+    }
+
+    #[test]
+    fn delete_expected() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(&tmp).unwrap();
+            let storage = Storage::open(&paths, SecretKey::new()).unwrap();
+            let oid = empty_commit(&storage);
+
+            let mut tx = storage.transaction("test");
+            tx.create(reflike!("refs/heads/one"), oid)
+                .delete(reflike!("refs/heads/nonexistent"), Expected::Any);
+            tx.commit().unwrap();
+
+            let mut tx = storage.transaction("test");
+            tx.delete(reflike!("refs/heads/one"), Expected::Oid(oid));
+            tx.commit().unwrap();
+
+            assert!(storage.as_raw().find_reference("refs/heads/one").is_err());
+        }
+    }
+}
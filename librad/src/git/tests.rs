@@ -3,5 +3,10 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
+mod blocklist;
+mod bundle;
 mod common;
 mod project;
+mod refs;
+mod replication;
+mod review;
@@ -0,0 +1,38 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use super::*;
+use crate::{
+    git::{blocklist, bundle, replication, tracking},
+    keys::SecretKey,
+};
+
+#[test]
+fn import_from_blocked_peer() -> anyhow::Result<()> {
+    let alice = common::exported_dylan(SecretKey::new())?;
+    let bob = common::storage(SecretKey::new())?;
+    let urn = alice.urn.clone();
+
+    blocklist::block(&bob, alice.peer, "spam")?;
+    let result = bundle::import(
+        &bob,
+        urn.clone(),
+        alice.peer,
+        &alice.path,
+        replication::Config::default(),
+        None,
+    );
+    assert!(matches!(
+        result,
+        Err(bundle::Error::Replication(replication::Error::Blocked(peer))) if peer == alice.peer
+    ));
+    assert!(!bob.has_urn(&urn)?);
+    assert!(matches!(
+        tracking::track(&bob, &urn, alice.peer),
+        Err(tracking::Error::Blocked(_))
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,35 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use super::*;
+use crate::{
+    git::{bundle, identities, replication, tracking},
+    keys::SecretKey,
+};
+
+#[test]
+fn export_import_person() -> anyhow::Result<()> {
+    let alice = common::exported_dylan(SecretKey::new())?;
+    let bob = common::storage(SecretKey::new())?;
+    let urn = alice.urn.clone();
+
+    let result = bundle::import(
+        &bob,
+        urn.clone(),
+        alice.peer,
+        &alice.path,
+        replication::Config::default(),
+        None,
+    )?;
+    assert!(matches!(result.mode, replication::Mode::Clone));
+    assert!(bob.has_urn(&urn)?);
+    assert!(tracking::is_tracked(&bob, &urn, alice.peer)?);
+    assert_eq!(
+        identities::person::get(&alice.storage, &urn)?,
+        identities::person::get(&bob, &urn)?
+    );
+
+    Ok(())
+}
@@ -3,18 +3,22 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::io;
+use std::{io, path::PathBuf};
 
 use librad_test::tempdir::WithTmpDir;
+use tempfile::TempDir;
 
 use crate::{
     git::{
+        bundle,
         identities::{self, local},
         storage::Storage,
+        Urn,
     },
     identities::payload,
     keys::SecretKey,
     paths::Paths,
+    peer::PeerId,
 };
 
 pub type TmpStorage = WithTmpDir<Storage>;
@@ -38,3 +42,30 @@ pub fn dylan(storage: &Storage, key: &SecretKey) -> anyhow::Result<local::LocalI
     )?;
     local::load(&storage, dylan.urn())?.ok_or_else(|| anyhow::anyhow!("where did dylan go?"))
 }
+
+/// A bundle of `dylan`, as exported by [`Exported::peer`].
+pub struct Exported {
+    pub storage: TmpStorage,
+    pub peer: PeerId,
+    pub urn: Urn,
+    pub path: PathBuf,
+    _tmp: TempDir,
+}
+
+/// Create `dylan` in a fresh storage owned by `key`, and export it to a bundle.
+pub fn exported_dylan(key: SecretKey) -> anyhow::Result<Exported> {
+    let storage = storage(key.clone())?;
+    let urn = dylan(&storage, &key)?.urn();
+
+    let tmp = tempfile::tempdir()?;
+    let path = tmp.path().join("dylan.bundle");
+    bundle::export(&storage, &urn, &path)?;
+
+    Ok(Exported {
+        storage,
+        peer: PeerId::from(key),
+        urn,
+        path,
+        _tmp: tmp,
+    })
+}
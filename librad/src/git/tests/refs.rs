@@ -0,0 +1,82 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use super::*;
+use crate::{
+    git::{
+        refs::Refs,
+        types::{Force, Namespace, Reference},
+    },
+    keys::SecretKey,
+    peer::PeerId,
+};
+
+#[test]
+fn signed_refs_history() -> anyhow::Result<()> {
+    let key = SecretKey::new();
+    let storage = common::storage(key.clone())?;
+    let whoami = common::dylan(&storage, &key)?;
+    let urn = whoami.urn();
+
+    let initial = Refs::load(&storage, &urn, None)?.expect("signed refs were created");
+
+    Reference::head(Namespace::from(&urn), None, reflike!("master")).create(
+        storage.as_raw(),
+        *whoami.content_id,
+        Force::False,
+        "master",
+    )?;
+    let updated = Refs::update(&storage, &urn)?.expect("signed refs changed");
+
+    let history = Refs::history(&storage, &urn, None)?.collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].refs.heads, updated.heads);
+    assert_eq!(history[1].refs.heads, initial.heads);
+    assert!(history[0]
+        .refs
+        .heads
+        .contains_key(&reflike!("master").into()));
+
+    // No such remote
+    assert_eq!(
+        Refs::history(&storage, &urn, PeerId::from(SecretKey::new()))?.count(),
+        0
+    );
+
+    Ok(())
+}
+
+#[test]
+fn seq_increments_on_change() -> anyhow::Result<()> {
+    let key = SecretKey::new();
+    let storage = common::storage(key.clone())?;
+    let whoami = common::dylan(&storage, &key)?;
+    let urn = whoami.urn();
+
+    let initial = Refs::load(&storage, &urn, None)?.expect("signed refs were created");
+    assert_eq!(initial.seq, 0);
+
+    // Nothing changed
+    assert!(Refs::update(&storage, &urn)?.is_none());
+    assert_eq!(
+        Refs::load(&storage, &urn, None)?.map(|refs| refs.seq),
+        Some(0)
+    );
+
+    Reference::head(Namespace::from(&urn), None, reflike!("master")).create(
+        storage.as_raw(),
+        *whoami.content_id,
+        Force::False,
+        "master",
+    )?;
+    let updated = Refs::update(&storage, &urn)?.expect("signed refs changed");
+    assert_eq!(updated.seq, 1);
+    assert_eq!(
+        Refs::load(&storage, &urn, None)?.map(|refs| refs.seq),
+        Some(1)
+    );
+
+    Ok(())
+}
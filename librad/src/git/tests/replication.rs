@@ -0,0 +1,369 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::collections::BTreeMap;
+
+use either::Either::Left;
+use git_ext as ext;
+
+use super::*;
+use crate::{
+    git::{
+        bundle,
+        identities,
+        refs::Refs,
+        replication,
+        storage::Storage,
+        tracking,
+        types::{Force, Namespace, Reference},
+        Urn,
+    },
+    identities::{delegation, payload},
+    keys::SecretKey,
+    peer::PeerId,
+};
+
+#[test]
+fn reports_progress() -> anyhow::Result<()> {
+    use replication::progress::{Progress, Reporter, Stage};
+    use std::sync::{Arc, Mutex};
+
+    let alice = common::exported_dylan(SecretKey::new())?;
+    let bob = common::storage(SecretKey::new())?;
+
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let config = replication::Config {
+        progress: Reporter::new({
+            let reports = Arc::clone(&reports);
+            move |progress| reports.lock().unwrap().push(progress)
+        }),
+        ..Default::default()
+    };
+    bundle::import(&bob, alice.urn, alice.peer, &alice.path, config, None)?;
+
+    let stages = reports
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|progress| match progress {
+            Progress::Stage(stage) => Some(*stage),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(stages, vec![Stage::Identities, Stage::SignedRefs]);
+
+    Ok(())
+}
+
+#[test]
+fn plan_person() -> anyhow::Result<()> {
+    let alice = common::exported_dylan(SecretKey::new())?;
+    let bob = common::storage(SecretKey::new())?;
+    let urn = alice.urn.clone();
+    let fetcher = || bundle::Fetcher::new(&bob, urn.clone(), alice.peer, &alice.path);
+
+    let before = all_refs(&bob)?;
+    let plan = replication::plan(&bob, fetcher()?, replication::Config::default())?;
+    assert!(matches!(plan.mode, replication::Mode::Clone));
+    assert_eq!(all_refs(&bob)?, before);
+    assert!(!bob.has_urn(&urn)?);
+
+    let rad_id = Reference::rad_id(Namespace::from(&urn));
+    let planned = &plan.changes[&alice.peer];
+    assert!(planned.contains(&replication::Change::Create {
+        name: ext::RefLike::from(&rad_id.clone().with_remote(alice.peer)),
+        target: alice
+            .storage
+            .reference(&rad_id)?
+            .and_then(|r| r.target())
+            .expect("alice has a rad/id")
+            .into(),
+    }));
+
+    bundle::import(
+        &bob,
+        urn.clone(),
+        alice.peer,
+        &alice.path,
+        replication::Config::default(),
+        None,
+    )?;
+    let before = all_refs(&bob)?;
+    let plan = replication::plan(&bob, fetcher()?, replication::Config::default())?;
+    assert!(matches!(plan.mode, replication::Mode::Fetch));
+    assert!(plan.is_empty());
+    assert_eq!(all_refs(&bob)?, before);
+
+    Ok(())
+}
+
+#[test]
+fn reject_rollback() -> anyhow::Result<()> {
+    let alice_key = SecretKey::new();
+    let alice = common::storage(alice_key.clone())?;
+    let bob = common::storage(SecretKey::new())?;
+    let alice_peer = PeerId::from(alice_key.clone());
+    let Published { urn, master, dylan } = publish_project(&alice, &alice_key)?;
+
+    let tmp = tempfile::tempdir()?;
+    let path = tmp.path().join("project.bundle");
+    bundle::export(&alice, &urn, &path)?;
+    bundle::import(
+        &bob,
+        urn.clone(),
+        alice_peer,
+        &path,
+        replication::Config::default(),
+        None,
+    )?;
+
+    let signed_refs = Reference::rad_signed_refs(Namespace::from(&urn), alice_peer);
+    let remote_master = master.clone().with_remote(alice_peer);
+    let signed_refs_before = target(&bob, &signed_refs)?;
+    let master_before = target(&bob, &remote_master)?;
+    assert!(master_before.is_some());
+    let previous = Refs::load(&bob, &urn, alice_peer)?
+        .expect("alice's signed refs were replicated")
+        .seq;
+    assert!(previous > 0);
+
+    // Move `master`, but publish it with a lower `seq`
+    master.create(alice.as_raw(), *dylan, Force::True, "master")?;
+    publish_with_seq(&alice, &alice_key, &urn, previous - 1)?;
+
+    bundle::export(&alice, &urn, &path)?;
+    let result = bundle::import(
+        &bob,
+        urn.clone(),
+        alice_peer,
+        &path,
+        replication::Config::default(),
+        None,
+    );
+    match result {
+        Err(bundle::Error::Replication(replication::Error::Rollback { rolled_back, .. })) => {
+            assert_eq!(
+                rolled_back,
+                Some((
+                    alice_peer,
+                    replication::Rollback {
+                        previous,
+                        received: previous - 1
+                    }
+                ))
+                .into_iter()
+                .collect::<BTreeMap<_, _>>()
+            )
+        },
+        other => panic!("expected rollback, got {:?}", other.map(|_| ())),
+    }
+    assert_eq!(target(&bob, &signed_refs)?, signed_refs_before);
+    assert_eq!(target(&bob, &remote_master)?, master_before);
+
+    Ok(())
+}
+
+#[test]
+fn reject_rollback_of_several_peers() -> anyhow::Result<()> {
+    let alice_key = SecretKey::new();
+    let carol_key = SecretKey::new();
+    let alice = common::storage(alice_key.clone())?;
+    let carol = common::storage(carol_key.clone())?;
+    let bob = common::storage(SecretKey::new())?;
+    let alice_peer = PeerId::from(alice_key.clone());
+    let carol_peer = PeerId::from(carol_key.clone());
+    let Published { urn, master, dylan } = publish_project(&alice, &alice_key)?;
+
+    let tmp = tempfile::tempdir()?;
+    let path = tmp.path().join("project.bundle");
+    let replicate = |to: &Storage, from: &Storage, from_peer: PeerId| {
+        bundle::export(from, &urn, &path)?;
+        bundle::import(
+            to,
+            urn.clone(),
+            from_peer,
+            &path,
+            replication::Config::default(),
+            None,
+        )
+    };
+
+    // Carol clones from alice and publishes a `master` of her own, which alice
+    // then tracks
+    replicate(&carol, &alice, alice_peer)?;
+    master.create(carol.as_raw(), *dylan, Force::False, "master")?;
+    Refs::update(&carol, &urn)?;
+    tracking::track(&alice, &urn, carol_peer)?;
+    replicate(&alice, &carol, carol_peer)?;
+
+    // Bob learns about carol through alice
+    replicate(&bob, &alice, alice_peer)?;
+    let names = [alice_peer, carol_peer]
+        .iter()
+        .map(|peer| Reference::rad_signed_refs(Namespace::from(&urn), *peer))
+        .collect::<Vec<_>>();
+    let before = names
+        .iter()
+        .map(|name| target(&bob, name))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let seq = |peer| -> anyhow::Result<u64> {
+        Ok(Refs::load(&bob, &urn, peer)?
+            .expect("signed refs were replicated")
+            .seq)
+    };
+    let (alice_seq, carol_seq) = (seq(alice_peer)?, seq(carol_peer)?);
+    assert!(alice_seq > 0 && carol_seq > 0);
+
+    // Alice publishes a lower `seq`, and serves a state of carol which has the
+    // same `seq` as the one bob has, but different refs
+    master.create(alice.as_raw(), *dylan, Force::True, "master")?;
+    publish_with_seq(&alice, &alice_key, &urn, alice_seq - 1)?;
+    Reference::head(Namespace::from(&urn), None, reflike!("next")).create(
+        carol.as_raw(),
+        *dylan,
+        Force::False,
+        "next",
+    )?;
+    publish_with_seq(&carol, &carol_key, &urn, carol_seq)?;
+    let equivocated = target(
+        &carol,
+        &Reference::rad_signed_refs(Namespace::from(&urn), None),
+    )?
+    .expect("carol published signed refs");
+    copy_commit(&carol, &alice, equivocated)?;
+    names[1].create(alice.as_raw(), equivocated, Force::True, "equivocate")?;
+
+    match replicate(&bob, &alice, alice_peer) {
+        Err(bundle::Error::Replication(replication::Error::Rollback { rolled_back, .. })) => {
+            assert_eq!(
+                rolled_back,
+                vec![
+                    (
+                        alice_peer,
+                        replication::Rollback {
+                            previous: alice_seq,
+                            received: alice_seq - 1,
+                        }
+                    ),
+                    (
+                        carol_peer,
+                        replication::Rollback {
+                            previous: carol_seq,
+                            received: carol_seq,
+                        }
+                    ),
+                ]
+                .into_iter()
+                .collect::<BTreeMap<_, _>>()
+            )
+        },
+        other => panic!("expected rollback, got {:?}", other.map(|_| ())),
+    }
+    let after = names
+        .iter()
+        .map(|name| target(&bob, name))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    assert_eq!(after, before);
+
+    Ok(())
+}
+
+/// A project created by the owner of `storage`, with a `master` branch
+/// published in its signed refs.
+struct Published {
+    urn: Urn,
+    master: Reference<ext::RefLike>,
+    /// Another commit `master` can be moved to.
+    dylan: ext::Oid,
+}
+
+fn publish_project(storage: &Storage, key: &SecretKey) -> anyhow::Result<Published> {
+    let whoami = common::dylan(storage, key)?;
+    let dylan = whoami.content_id;
+    let proj = identities::project::create(
+        storage,
+        whoami,
+        payload::Project {
+            name: "rollback".into(),
+            description: None,
+            default_branch: Some("master".into()),
+        },
+        delegation::Indirect::try_from_iter(Some(Left(key.public()))).unwrap(),
+    )?;
+    let urn = proj.urn();
+
+    let master = Reference::head(Namespace::from(&urn), None, reflike!("master"));
+    master.create(storage.as_raw(), *proj.content_id, Force::False, "master")?;
+    Refs::update(storage, &urn)?;
+
+    Ok(Published {
+        urn,
+        master,
+        dylan: dylan.into(),
+    })
+}
+
+fn target(storage: &Storage, name: &Reference<ext::RefLike>) -> anyhow::Result<Option<git2::Oid>> {
+    Ok(storage.reference(name)?.and_then(|r| r.target()))
+}
+
+/// Copy a `rad/signed_refs` commit, without its parents, from one storage to
+/// another.
+fn copy_commit(from: &Storage, to: &Storage, commit: git2::Oid) -> anyhow::Result<()> {
+    let (src, dst) = (from.as_raw().odb()?, to.as_raw().odb()?);
+    let tree = from.as_raw().find_commit(commit)?.tree()?;
+    let objects = Some(commit)
+        .into_iter()
+        .chain(Some(tree.id()))
+        .chain(tree.iter().map(|entry| entry.id()));
+    for oid in objects {
+        let object = src.read(oid)?;
+        dst.write(object.kind(), object.data())?;
+    }
+    Ok(())
+}
+
+fn all_refs(storage: &Storage) -> anyhow::Result<BTreeMap<String, git2::Oid>> {
+    let mut refs = BTreeMap::new();
+    for reference in storage.as_raw().references()? {
+        let reference = reference?;
+        if let (Some(name), Some(target)) = (reference.name(), reference.target()) {
+            refs.insert(name.to_owned(), target);
+        }
+    }
+    Ok(refs)
+}
+
+/// Sign the current [`Refs`] of `urn` with the given `seq`, and commit them on
+/// top of `rad/signed_refs`, bypassing [`Refs::update`].
+fn publish_with_seq(storage: &Storage, key: &SecretKey, urn: &Urn, seq: u64) -> anyhow::Result<()> {
+    let mut refs = Refs::compute(storage, urn)?;
+    refs.seq = seq;
+    let signed = refs.sign(key)?;
+
+    let repo = storage.as_raw();
+    let branch = Reference::rad_signed_refs(Namespace::from(urn), None);
+    let parent = storage
+        .reference(&branch)?
+        .expect("signed refs exist")
+        .peel_to_commit()?;
+    let tree = {
+        let blob = repo.blob(&serde_json::to_vec(&signed)?)?;
+        let mut builder = repo.treebuilder(None)?;
+        builder.insert("refs", blob, 0o100_644)?;
+        repo.find_tree(builder.write()?)?
+    };
+    let author = repo.signature()?;
+    repo.commit(
+        Some(&branch.to_string()),
+        &author,
+        &author,
+        "Roll back rad/signed_refs",
+        &tree,
+        &[&parent],
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,146 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use super::*;
+use crate::{
+    git::{
+        bundle,
+        identities::{self, review},
+        replication,
+        types::{Force, Namespace, Reference},
+    },
+    identities::{
+        delegation,
+        payload::{self, PersonPayload},
+    },
+    keys::SecretKey,
+    peer::PeerId,
+};
+
+#[test]
+fn review_person_update() -> anyhow::Result<()> {
+    let alice_key = SecretKey::new();
+    let alice = common::storage(alice_key.clone())?;
+    let bob = common::storage(SecretKey::new())?;
+    let alice_peer = PeerId::from(alice_key.clone());
+
+    let whoami = common::dylan(&alice, &alice_key)?;
+    let urn = whoami.urn();
+
+    let tmp = tempfile::tempdir()?;
+    let exchange = |name: &str| -> anyhow::Result<replication::ReplicateResult> {
+        let path = tmp.path().join(name);
+        bundle::export(&alice, &urn, &path)?;
+        Ok(bundle::import(
+            &bob,
+            urn.clone(),
+            alice_peer,
+            &path,
+            replication::Config::default(),
+            None,
+        )?)
+    };
+
+    exchange("initial.bundle")?;
+    assert!(review::pending(&bob, &urn)?.is_empty());
+
+    let updated = identities::person::update(
+        &alice,
+        &urn,
+        None::<identities::local::LocalIdentity>,
+        Some(PersonPayload::new(payload::Person {
+            name: "dylan the 2nd".into(),
+        })),
+        None::<delegation::Direct>,
+    )?;
+    let result = exchange("update.bundle")?;
+    assert!(matches!(result.identity, replication::IdStatus::Uneven));
+
+    let pending = review::pending(&bob, &urn)?;
+    assert_eq!(pending.len(), 1);
+    let update = &pending[0];
+    assert_eq!(update.peer, alice_peer);
+    assert_eq!(update.tip, updated.content_id);
+    assert_eq!(update.ahead, 1);
+    assert_eq!(update.diff.payload.len(), 1);
+    assert!(update.diff.added.is_empty() && update.diff.removed.is_empty());
+    assert!(update.signatures.contains(&alice_key.public()));
+
+    review::reject(&bob, &urn, alice_peer, update.tip)?;
+    assert!(review::pending(&bob, &urn)?.is_empty());
+
+    review::accept(&bob, &urn, alice_peer)?;
+    assert_eq!(
+        identities::person::get(&bob, &urn)?.map(|person| person.content_id),
+        Some(updated.content_id)
+    );
+    assert_eq!(review::rejected(&bob, &urn, alice_peer)?, None);
+
+    Ok(())
+}
+
+#[test]
+fn accept_only_delegates_ahead_of_us() -> anyhow::Result<()> {
+    let alice_key = SecretKey::new();
+    let alice = common::storage(alice_key.clone())?;
+    let bob = common::storage(SecretKey::new())?;
+    let alice_peer = PeerId::from(alice_key.clone());
+    let carol_peer = PeerId::from(SecretKey::new());
+
+    let whoami = common::dylan(&alice, &alice_key)?;
+    let urn = whoami.urn();
+    let initial = whoami.content_id;
+
+    let tmp = tempfile::tempdir()?;
+    let path = tmp.path().join("dylan.bundle");
+    let updated = identities::person::update(
+        &alice,
+        &urn,
+        None::<identities::local::LocalIdentity>,
+        Some(PersonPayload::new(payload::Person {
+            name: "dylan the 2nd".into(),
+        })),
+        None::<delegation::Direct>,
+    )?;
+    bundle::export(&alice, &urn, &path)?;
+    bundle::import(
+        &bob,
+        urn.clone(),
+        alice_peer,
+        &path,
+        replication::Config::default(),
+        None,
+    )?;
+    let rad_id = Reference::rad_id(Namespace::from(&urn));
+    let local_tip = || -> anyhow::Result<Option<git2::Oid>> {
+        Ok(bob.reference(&rad_id)?.and_then(|r| r.target()))
+    };
+    assert_eq!(local_tip()?, Some(*updated.content_id));
+
+    // A tracked peer which isn't a delegate can't move our `rad/id`, not even to
+    // a valid revision
+    rad_id
+        .clone()
+        .with_remote(carol_peer)
+        .create(bob.as_raw(), *initial, Force::False, "carol")?;
+    assert!(matches!(
+        review::accept(&bob, &urn, carol_peer),
+        Err(identities::Error::NotADelegate { peer, .. }) if peer == carol_peer
+    ));
+    assert_eq!(local_tip()?, Some(*updated.content_id));
+
+    // Neither can a delegate whose `rad/id` is behind ours
+    rad_id
+        .clone()
+        .with_remote(alice_peer)
+        .create(bob.as_raw(), *initial, Force::True, "behind")?;
+    assert!(matches!(
+        review::accept(&bob, &urn, alice_peer),
+        Err(identities::Error::NotAhead { peer, .. }) if peer == alice_peer
+    ));
+    assert_eq!(local_tip()?, Some(*updated.content_id));
+
+    Ok(())
+}
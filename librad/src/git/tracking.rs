@@ -3,52 +3,153 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::{convert::TryFrom, ops::Range, str::FromStr};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    fmt,
+    ops::Range,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-use git_ext::{is_exists_err, is_not_found_err};
+use git_ext::{self as ext, is_exists_err, is_not_found_err};
 use std_ext::result::ResultExt as _;
 use thiserror::Error;
 
 use super::{
+    blocklist,
     p2p::url::GitUrlRef,
-    storage::{self, glob, Storage},
+    storage::{self, events::Event, glob, transaction::Expected, ReadOnly, Storage},
 };
 use crate::peer::PeerId;
 
 pub use crate::identities::git::Urn;
 
+pub mod graph;
+
 #[derive(Debug, Error)]
 #[non_exhaustive]
 pub enum Error {
     #[error("can't track oneself")]
     SelfReferential,
 
+    #[error("can't track blocked peer {0}")]
+    Blocked(PeerId),
+
+    #[error("invalid tracking policy entry `{name} = {value}`")]
+    Policy { name: String, value: String },
+
+    #[error("invalid followed peer `{0}`")]
+    Follow(String),
+
+    #[error("invalid tracking metadata entry `{name} = {value}`")]
+    Metadata { name: String, value: String },
+
+    #[error("invalid nickname `{0}`, must be a single ref name component")]
+    Nickname(String),
+
+    #[error(transparent)]
+    Blocklist(#[from] blocklist::Error),
+
     #[error(transparent)]
     Store(#[from] storage::Error),
 
     #[error(transparent)]
     Config(#[from] storage::config::Error),
 
+    #[error(transparent)]
+    Transaction(#[from] storage::transaction::Error),
+
     #[error(transparent)]
     Git(#[from] git2::Error),
 }
 
+/// What caused a tracking relationship to be established.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Initiator {
+    /// Explicitly requested by the user.
+    User,
+    /// Tracked by a seed node, according to its tracking policy.
+    Seed,
+    /// Tracked during replication, eg. because the peer is a delegate.
+    Replication,
+    /// Tracked because the peer is followed, see [`follow`].
+    Follow,
+}
+
+impl fmt::Display for Initiator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::User => "user",
+            Self::Seed => "seed",
+            Self::Replication => "replication",
+            Self::Follow => "follow",
+        })
+    }
+}
+
+impl FromStr for Initiator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(Self::User),
+            "seed" => Ok(Self::Seed),
+            "replication" => Ok(Self::Replication),
+            "follow" => Ok(Self::Follow),
+            _ => Err(s.to_owned()),
+        }
+    }
+}
+
+/// Information about a tracking relationship.
+///
+/// Stored alongside the tracking remote, as `remote.<urn>/<peer>.rad-created`,
+/// `rad-initiator` and `rad-nickname`. The creation time and initiator are
+/// unknown for relationships established before they were recorded.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metadata {
+    /// When the relationship was established, in seconds since the epoch.
+    pub created_at: Option<u64>,
+    pub initiator: Option<Initiator>,
+    /// A local name for the peer, see [`set_nickname`].
+    pub nickname: Option<String>,
+}
+
 /// Track the given `peer` in the context of `urn`.
 ///
+/// Equivalent to [`track_with`] and [`Initiator::User`].
+#[tracing::instrument(skip(storage), err)]
+pub fn track(storage: &Storage, urn: &Urn, peer: PeerId) -> Result<bool, Error> {
+    track_with(storage, urn, peer, Initiator::User)
+}
+
+/// Track the given `peer` in the context of `urn`, recording the creation time
+/// and `initiator` in its [`Metadata`].
+///
 /// `true` is returned if the tracking relationship didn't exist before and was
 /// created as a side-effect of the function call. Otherwise, `false` is
-/// returned.
+/// returned, and the [`Metadata`] is left untouched.
 ///
 /// # Errors
 ///
-/// Attempting to track oneself (ie. [`Storage::peer_id`]) is an error.
+/// Attempting to track oneself (ie. [`Storage::peer_id`]) is an error, as is
+/// attempting to track a peer on the [`blocklist`].
 #[tracing::instrument(skip(storage), err)]
-pub fn track(storage: &Storage, urn: &Urn, peer: PeerId) -> Result<bool, Error> {
+pub fn track_with(
+    storage: &Storage,
+    urn: &Urn,
+    peer: PeerId,
+    initiator: Initiator,
+) -> Result<bool, Error> {
     let local_peer = storage.peer_id();
 
     if &peer == local_peer {
         return Err(Error::SelfReferential);
     }
+    if blocklist::is_blocked(storage, peer)? {
+        return Err(Error::Blocked(peer));
+    }
 
     let remote_name = tracking_remote_name(urn, &peer);
     let url = GitUrlRef::from_urn(urn, local_peer, &peer, &[]);
@@ -66,14 +167,243 @@ pub fn track(storage: &Storage, urn: &Urn, peer: PeerId) -> Result<bool, Error>
         // the fetchspecs ourselves). We also don't want libgit2 to prune the
         // remote.
         let mut config = storage::Config::try_from(storage)?;
-        config
-            .as_raw_mut()
-            .remove_multivar(&format!("remote.{}.fetch", remote_name), ".*")?;
+        let config = config.as_raw_mut();
+        config.remove_multivar(&format!("remote.{}.fetch", remote_name), ".*")?;
+
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or_default();
+        config.set_i64(
+            &format!("remote.{}.rad-created", remote_name),
+            created_at as i64,
+        )?;
+        config.set_str(
+            &format!("remote.{}.rad-initiator", remote_name),
+            &initiator.to_string(),
+        )?;
+
+        storage.emit(Event::TrackingAdded {
+            urn: urn.clone(),
+            peer,
+        });
     }
 
     Ok(was_created)
 }
 
+/// Read the [`Metadata`] of tracking `peer` in the context of `urn`.
+///
+/// `None` is returned if `peer` is not tracked.
+#[tracing::instrument(level = "trace", skip(storage), err)]
+pub fn metadata(storage: &Storage, urn: &Urn, peer: PeerId) -> Result<Option<Metadata>, Error> {
+    if !is_tracked(storage, urn, peer)? {
+        return Ok(None);
+    }
+
+    let config = storage.as_raw().config()?;
+    read_metadata(&config, urn, peer).map(Some)
+}
+
+fn read_metadata(config: &git2::Config, urn: &Urn, peer: PeerId) -> Result<Metadata, Error> {
+    let remote_name = tracking_remote_name(urn, &peer);
+    let invalid = |name: String, value: String| Error::Metadata { name, value };
+
+    let get = |key: &str| -> Result<Option<(String, String)>, Error> {
+        let name = format!("remote.{}.{}", remote_name, key);
+        match config.get_string(&name) {
+            Ok(value) => Ok(Some((name, value))),
+            Err(e) if is_not_found_err(&e) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    };
+
+    let created_at = get("rad-created")?
+        .map(|(name, value)| value.parse::<u64>().map_err(|_| invalid(name, value)))
+        .transpose()?;
+    let initiator = get("rad-initiator")?
+        .map(|(name, value)| value.parse::<Initiator>().map_err(|_| invalid(name, value)))
+        .transpose()?;
+    let nickname = get("rad-nickname")?.map(|(_, value)| value);
+
+    Ok(Metadata {
+        created_at,
+        initiator,
+        nickname,
+    })
+}
+
+/// Set or, if `None`, remove the local nickname of `peer` in the context of
+/// `urn`.
+///
+/// `true` is returned if `peer` is tracked, and thus the nickname was stored.
+/// Otherwise, `false` is returned.
+///
+/// # Errors
+///
+/// Nicknames are used as ref names (eg. for `include` files), so a nickname
+/// which isn't a valid ref name consisting of a single component is rejected.
+#[tracing::instrument(skip(storage), err)]
+pub fn set_nickname(
+    storage: &Storage,
+    urn: &Urn,
+    peer: PeerId,
+    nickname: Option<&str>,
+) -> Result<bool, Error> {
+    if let Some(nickname) = nickname {
+        let valid = ext::RefLike::try_from(nickname)
+            .map(|name| name.as_str() == nickname && !nickname.contains('/'))
+            .unwrap_or(false);
+        if !valid {
+            return Err(Error::Nickname(nickname.to_owned()));
+        }
+    }
+    if !is_tracked(storage, urn, peer)? {
+        return Ok(false);
+    }
+
+    let key = format!("remote.{}.rad-nickname", tracking_remote_name(urn, &peer));
+    let mut config = storage::Config::try_from(storage)?;
+    let config = config.as_raw_mut();
+    match nickname {
+        Some(nickname) => config.set_str(&key, nickname)?,
+        None => config
+            .remove(&key)
+            .or_matches::<Error, _, _>(is_not_found_err, || Ok(()))?,
+    }
+
+    Ok(true)
+}
+
+/// How a tracked peer is replicated.
+///
+/// The policy is stored alongside the tracking remote, as
+/// `remote.<urn>/<peer>.rad-ref` (multi-valued), `rad-transitive` and
+/// `rad-limit`. Peers tracked without a policy are replicated according to the
+/// [`Default`] policy.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Policy {
+    /// Only replicate the refs of the peer matching these globs, see
+    /// [`crate::git::fetch::Filter`]. `None` means all refs.
+    pub refs: Option<BTreeSet<ext::RefspecPattern>>,
+    /// Whether to also track the peers tracked by this peer.
+    pub transitive: bool,
+    /// Limit the amount of data fetched from this peer, in bytes. `None`
+    /// means the limit given to replication applies.
+    pub data_limit: Option<usize>,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            refs: None,
+            transitive: true,
+            data_limit: None,
+        }
+    }
+}
+
+/// Read the [`Policy`] of `peer` in the context of `urn`.
+///
+/// `None` is returned if `peer` is not tracked.
+#[tracing::instrument(level = "trace", skip(storage), err)]
+pub fn policy(storage: &Storage, urn: &Urn, peer: PeerId) -> Result<Option<Policy>, Error> {
+    if !is_tracked(storage, urn, peer)? {
+        return Ok(None);
+    }
+
+    let remote_name = tracking_remote_name(urn, &peer);
+    let config = storage.as_raw().config()?;
+    let invalid = |name: &str, value: &str| Error::Policy {
+        name: name.to_owned(),
+        value: value.to_owned(),
+    };
+
+    let mut policy = Policy::default();
+
+    let refs_key = format!("remote.{}.rad-ref", remote_name);
+    for entry in &config.multivar(&refs_key, None)? {
+        let entry = entry?;
+        let value = entry.value().unwrap_or_default();
+        let glob = storage::config::parse_glob(value).map_err(|_| invalid(&refs_key, value))?;
+        policy.refs.get_or_insert_with(BTreeSet::new).extend(glob);
+    }
+
+    let transitive_key = format!("remote.{}.rad-transitive", remote_name);
+    match config.get_bool(&transitive_key) {
+        Ok(transitive) => policy.transitive = transitive,
+        Err(e) if is_not_found_err(&e) => {},
+        Err(e) => return Err(e.into()),
+    }
+
+    let limit_key = format!("remote.{}.rad-limit", remote_name);
+    match config.get_i64(&limit_key) {
+        Ok(limit) => {
+            policy.data_limit =
+                Some(usize::try_from(limit).map_err(|_| invalid(&limit_key, &limit.to_string()))?)
+        },
+        Err(e) if is_not_found_err(&e) => {},
+        Err(e) => return Err(e.into()),
+    }
+
+    Ok(Some(policy))
+}
+
+/// Replace the [`Policy`] of `peer` in the context of `urn`.
+///
+/// `true` is returned if `peer` is tracked, and thus the policy was stored.
+/// Otherwise, `false` is returned.
+#[tracing::instrument(skip(storage), err)]
+pub fn set_policy(
+    storage: &Storage,
+    urn: &Urn,
+    peer: PeerId,
+    policy: &Policy,
+) -> Result<bool, Error> {
+    if !is_tracked(storage, urn, peer)? {
+        return Ok(false);
+    }
+
+    let remote_name = tracking_remote_name(urn, &peer);
+    let mut config = storage::Config::try_from(storage)?;
+    let config = config.as_raw_mut();
+
+    let refs_key = format!("remote.{}.rad-ref", remote_name);
+    config
+        .remove_multivar(&refs_key, ".*")
+        .or_matches::<Error, _, _>(is_not_found_err, || Ok(()))?;
+    if let Some(refs) = &policy.refs {
+        storage::config::add_globs(config, &refs_key, refs)?;
+    }
+
+    config.set_bool(
+        &format!("remote.{}.rad-transitive", remote_name),
+        policy.transitive,
+    )?;
+
+    let limit_key = format!("remote.{}.rad-limit", remote_name);
+    match policy.data_limit {
+        Some(limit) => config.set_i64(&limit_key, limit as i64)?,
+        None => config
+            .remove(&limit_key)
+            .or_matches::<Error, _, _>(is_not_found_err, || Ok(()))?,
+    }
+
+    Ok(true)
+}
+
+/// The [`Policy`] of every 1st degree tracked peer in the context of `urn`.
+pub fn policies(storage: &Storage, urn: &Urn) -> Result<BTreeMap<PeerId, Policy>, Error> {
+    let mut policies = BTreeMap::new();
+    for peer in tracked_peers(storage, urn)? {
+        if let Some(policy) = policy(storage, urn, peer)? {
+            policies.insert(peer, policy);
+        }
+    }
+
+    Ok(policies)
+}
+
 /// Remove the tracking of `peer` in the context of `urn`.
 ///
 /// `true` is returned if the tracking relationship existed and was removed as a
@@ -81,22 +411,36 @@ pub fn track(storage: &Storage, urn: &Urn, peer: PeerId) -> Result<bool, Error>
 ///
 /// # Caveats
 ///
-/// Untracking will also attempt to prune any remote branches associated with
-/// `peer` (this mirrors the behaviour of `git`). Since refdb operations are not
-/// (yet) atomic, this may fail, leaving "dangling" refs in the storage. It is
-/// safe to call this function repeatedly, so as to ensure all remote tracking
-/// branches have been pruned.
+/// Untracking will also prune any remote branches associated with `peer` (this
+/// mirrors the behaviour of `git`). The remote branches are removed in a single
+/// [`storage::Transaction`], and the tracking relationship is removed only
+/// after the transaction was committed. If removing the tracking relationship
+/// fails, `peer` remains tracked without any remote branches, which will be
+/// restored by the next fetch. It is safe to call this function repeatedly.
 #[tracing::instrument(skip(storage), err)]
 pub fn untrack(storage: &Storage, urn: &Urn, peer: PeerId) -> Result<bool, Error> {
-    let remote_name = tracking_remote_name(urn, &peer);
-    let was_removed = storage
-        .as_raw()
-        .remote_delete(&remote_name)
-        .map(|()| true)
-        .or_matches::<Error, _, _>(is_not_found_err, || Ok(false))?;
+    let mut tx = storage.transaction(format!("untrack {} in {}", peer, urn));
+    let untracking = untrack_in(storage, &mut tx, urn, peer)?;
+    tx.commit()?;
+
+    untracking.finish(storage)
+}
 
+/// Like [`untrack`], but stage the pruning of remote branches in the given
+/// [`storage::Transaction`] instead of committing it right away.
+///
+/// This allows to untrack several peers, and prune all of their remote
+/// branches in one go. The tracking relationship is only removed by
+/// [`Untracking::finish`], which must be called after the transaction was
+/// committed successfully.
+pub(crate) fn untrack_in(
+    storage: &Storage,
+    tx: &mut storage::Transaction,
+    urn: &Urn,
+    peer: PeerId,
+) -> Result<Untracking, Error> {
     // Prune all remote branches
-    let prune = storage.references_glob(glob::RefspecMatcher::from(
+    let prune = storage.reference_names_glob(glob::RefspecMatcher::from(
         reflike!("refs/namespaces")
             .join(urn)
             .join(reflike!("refs/remotes"))
@@ -105,10 +449,47 @@ pub fn untrack(storage: &Storage, urn: &Urn, peer: PeerId) -> Result<bool, Error
     ))?;
 
     for branch in prune {
-        branch?.delete()?;
+        tx.delete(branch?, Expected::Any);
     }
 
-    Ok(was_removed)
+    Ok(Untracking {
+        urn: urn.clone(),
+        peer,
+    })
+}
+
+/// An untracking staged by [`untrack_in`].
+#[must_use = "the tracking relationship is only removed by `finish`"]
+pub(crate) struct Untracking {
+    urn: Urn,
+    peer: PeerId,
+}
+
+impl Untracking {
+    pub(crate) fn peer(&self) -> &PeerId {
+        &self.peer
+    }
+
+    /// Remove the tracking relationship, once the pruning of remote branches
+    /// was committed.
+    ///
+    /// `true` is returned if the peer was tracked before.
+    pub(crate) fn finish(self, storage: &Storage) -> Result<bool, Error> {
+        let remote_name = tracking_remote_name(&self.urn, &self.peer);
+        let was_removed = storage
+            .as_raw()
+            .remote_delete(&remote_name)
+            .map(|()| true)
+            .or_matches::<Error, _, _>(is_not_found_err, || Ok(false))?;
+        if was_removed {
+            storage.emit(Event::TrackingRemoved {
+                urn: self.urn,
+                peer: self.peer,
+            });
+        }
+
+        Ok(was_removed)
+    }
 }
 
 /// Determine if `peer` is tracked in the context of `urn`.
@@ -121,12 +502,93 @@ pub fn is_tracked(storage: &Storage, urn: &Urn, peer: PeerId) -> Result<bool, Er
         .or_matches(is_not_found_err, || Ok(false))
 }
 
+/// The 1st degree tracked peers in the context of `urn`, along with the
+/// [`Metadata`] of each tracking relationship.
+///
+/// Use [`tracked_peers`] if only the [`PeerId`]s are needed, which avoids
+/// reading the metadata.
+pub fn tracked(storage: &ReadOnly, urn: &Urn) -> Result<BTreeMap<PeerId, Metadata>, Error> {
+    let config = storage.as_raw().config()?;
+    tracked_peers(storage, urn)?
+        .map(|peer| Ok((peer, read_metadata(&config, urn, peer)?)))
+        .collect()
+}
+
 /// Obtain an iterator over the 1st degree tracked peers in the context of
 /// `urn`.
-pub fn tracked(storage: &Storage, urn: &Urn) -> Result<Tracked, Error> {
+pub fn tracked_peers(storage: &ReadOnly, urn: &Urn) -> Result<Tracked, Error> {
     Ok(Tracked::collect(storage.as_raw(), urn)?)
 }
 
+/// The git config key under which followed peers are stored, see [`follow`].
+const FOLLOW: &str = "tracking.follow";
+
+/// Follow `peer` across all [`Urn`]s.
+///
+/// Followed peers are tracked automatically in the context of any [`Urn`] they
+/// announce, see [`crate::net::peer::storage::Storage`]. Following does not
+/// track `peer` in the context of [`Urn`]s which are already present.
+///
+/// `true` is returned if `peer` wasn't followed before. Otherwise, `false` is
+/// returned.
+#[tracing::instrument(skip(storage), err)]
+pub fn follow(storage: &Storage, peer: PeerId) -> Result<bool, Error> {
+    if storage.peer_id() == &peer {
+        return Err(Error::SelfReferential);
+    }
+    if is_followed(storage, peer)? {
+        return Ok(false);
+    }
+
+    let mut config = storage::Config::try_from(storage)?;
+    config
+        .as_raw_mut()
+        .set_multivar(FOLLOW, "^$", &peer.to_string())?;
+
+    Ok(true)
+}
+
+/// Stop following `peer`.
+///
+/// Tracking relationships established while `peer` was followed are left
+/// intact, use [`untrack`] to remove them.
+///
+/// `true` is returned if `peer` was followed before. Otherwise, `false` is
+/// returned.
+#[tracing::instrument(skip(storage), err)]
+pub fn unfollow(storage: &Storage, peer: PeerId) -> Result<bool, Error> {
+    if !is_followed(storage, peer)? {
+        return Ok(false);
+    }
+
+    let mut config = storage::Config::try_from(storage)?;
+    config
+        .as_raw_mut()
+        .remove_multivar(FOLLOW, &format!("^{}$", peer))?;
+
+    Ok(true)
+}
+
+/// Determine if `peer` is followed.
+#[tracing::instrument(level = "trace", skip(storage), err)]
+pub fn is_followed(storage: &Storage, peer: PeerId) -> Result<bool, Error> {
+    Ok(followed(storage)?.contains(&peer))
+}
+
+/// The set of followed peers.
+pub fn followed(storage: &Storage) -> Result<BTreeSet<PeerId>, Error> {
+    let config = storage.as_raw().config()?;
+    let mut followed = BTreeSet::new();
+    for entry in &config.multivar(FOLLOW, None)? {
+        let entry = entry?;
+        let value = entry.value().unwrap_or_default();
+        let peer = value.parse().map_err(|_| Error::Follow(value.to_owned()))?;
+        followed.insert(peer);
+    }
+
+    Ok(followed)
+}
+
 /// Iterator over the 1st degree tracked peers.
 #[must_use = "iterators are lazy and do nothing unless consumed"]
 pub struct Tracked {
@@ -255,11 +717,146 @@ mod tests {
             track(&storage, &urn, peer2).unwrap();
             assert_eq!(
                 [peer1, peer2].iter().copied().collect::<BTreeSet<_>>(),
-                tracked(&storage, &urn).unwrap().collect::<BTreeSet<_>>()
+                tracked_peers(&storage, &urn)
+                    .unwrap()
+                    .collect::<BTreeSet<_>>()
             )
         }
     }
 
+    #[test]
+    fn policy_roundtrip() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(&tmp).unwrap();
+            let storage = Storage::open(&paths, SecretKey::new()).unwrap();
+            let remote_peer = PeerId::from(SecretKey::new());
+            let urn = Urn::new(git2::Oid::zero().into());
+
+            let policy = Policy {
+                refs: Some(Some(refspec_pattern!("refs/heads/*")).into_iter().collect()),
+                transitive: false,
+                data_limit: Some(1024),
+            };
+            assert!(!set_policy(&storage, &urn, remote_peer, &policy).unwrap());
+            assert_eq!(policy(&storage, &urn, remote_peer).unwrap(), None);
+
+            track(&storage, &urn, remote_peer).unwrap();
+            assert_eq!(
+                policy(&storage, &urn, remote_peer).unwrap(),
+                Some(Policy::default())
+            );
+            assert!(set_policy(&storage, &urn, remote_peer, &policy).unwrap());
+            assert_eq!(
+                policy(&storage, &urn, remote_peer).unwrap(),
+                Some(policy.clone())
+            );
+
+            let nothing = Policy {
+                refs: Some(BTreeSet::new()),
+                ..Policy::default()
+            };
+            set_policy(&storage, &urn, remote_peer, &nothing).unwrap();
+            assert_eq!(
+                policies(&storage, &urn).unwrap().remove(&remote_peer),
+                Some(nothing)
+            );
+        }
+    }
+
+    #[test]
+    fn follow_unfollow() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(&tmp).unwrap();
+            let storage = Storage::open(&paths, SecretKey::new()).unwrap();
+            let remote_peer = PeerId::from(SecretKey::new());
+            let other_peer = PeerId::from(SecretKey::new());
+
+            assert!(matches!(
+                follow(&storage, *storage.peer_id()),
+                Err(Error::SelfReferential)
+            ));
+            assert!(follow(&storage, remote_peer).unwrap());
+            assert!(!follow(&storage, remote_peer).unwrap());
+            assert!(follow(&storage, other_peer).unwrap());
+            assert_eq!(
+                followed(&storage).unwrap(),
+                vec![remote_peer, other_peer].into_iter().collect()
+            );
+
+            assert!(unfollow(&storage, remote_peer).unwrap());
+            assert!(!unfollow(&storage, remote_peer).unwrap());
+            assert!(!is_followed(&storage, remote_peer).unwrap());
+            assert!(is_followed(&storage, other_peer).unwrap());
+        }
+    }
+
+    #[test]
+    fn track_with_metadata() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(&tmp).unwrap();
+            let storage = Storage::open(&paths, SecretKey::new()).unwrap();
+            let remote_peer = PeerId::from(SecretKey::new());
+            let urn = Urn::new(git2::Oid::zero().into());
+
+            assert!(!set_nickname(&storage, &urn, remote_peer, Some("dylan")).unwrap());
+            assert!(track_with(&storage, &urn, remote_peer, Initiator::Seed).unwrap());
+            assert!(!track_with(&storage, &urn, remote_peer, Initiator::User).unwrap());
+
+            let meta = metadata(&storage, &urn, remote_peer).unwrap().unwrap();
+            assert!(meta.created_at.is_some());
+            assert_eq!(meta.initiator, Some(Initiator::Seed));
+            assert_eq!(meta.nickname, None);
+
+            assert!(set_nickname(&storage, &urn, remote_peer, Some("dylan")).unwrap());
+            assert_eq!(
+                tracked(&storage, &urn)
+                    .unwrap()
+                    .remove(&remote_peer)
+                    .and_then(|meta| meta.nickname),
+                Some("dylan".to_owned())
+            );
+            set_nickname(&storage, &urn, remote_peer, None).unwrap();
+            assert_eq!(
+                metadata(&storage, &urn, remote_peer)
+                    .unwrap()
+                    .and_then(|meta| meta.nickname),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn reject_invalid_nickname() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(&tmp).unwrap();
+            let storage = Storage::open(&paths, SecretKey::new()).unwrap();
+            let remote_peer = PeerId::from(SecretKey::new());
+            let urn = Urn::new(git2::Oid::zero().into());
+
+            assert!(track(&storage, &urn, remote_peer).unwrap());
+            for nickname in &["dylan/ii", "dy lan", "dylan.lock", "..", ""] {
+                assert!(
+                    matches!(
+                        set_nickname(&storage, &urn, remote_peer, Some(nickname)),
+                        Err(Error::Nickname(invalid)) if invalid.as_str() == *nickname
+                    ),
+                    "expected `{}` to be rejected",
+                    nickname
+                );
+            }
+            assert_eq!(
+                metadata(&storage, &urn, remote_peer)
+                    .unwrap()
+                    .and_then(|meta| meta.nickname),
+                None
+            );
+        }
+    }
+
     #[test]
     fn tracked_ignores_urn_path() {
         let tmp = tempfile::tempdir().unwrap();
@@ -272,7 +869,10 @@ mod tests {
             track(&storage, &urn, remote_peer).unwrap();
 
             let urn = urn.with_path(reflike!("ri/ra/rutsch"));
-            assert_eq!(Some(remote_peer), tracked(&storage, &urn).unwrap().next())
+            assert_eq!(
+                Some(remote_peer),
+                tracked_peers(&storage, &urn).unwrap().next()
+            )
         }
     }
 }
@@ -0,0 +1,306 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Queries over the transitive tracking graph of a [`Urn`].
+//!
+//! The graph is assembled from the [`Remotes`] published in the
+//! `rad/signed_refs` of the tracked peers, as loaded by [`Refs::load`]. If the
+//! `rad/signed_refs` of a peer are not present locally, the part of the graph
+//! published by the peer it was discovered through is used instead, which is
+//! cut off at [`crate::git::refs::TRACKING_GRAPH_DEPTH`]. Peers whose
+//! `rad/signed_refs` fail to load are treated as if they were not present.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use thiserror::Error;
+
+use super::{tracked_peers, Urn};
+use crate::{
+    git::{
+        refs::{Refs, Remotes},
+        storage::ReadOnly,
+    },
+    peer::PeerId,
+};
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error(transparent)]
+    Tracking(#[from] super::Error),
+}
+
+/// All peers reachable from the local peer within `hops` in the tracking graph
+/// of `urn`, along with the shortest path by which they are reachable.
+///
+/// A path starts with a 1st degree tracked peer, and ends with the reachable
+/// peer itself. That is, the 1st degree tracked peers are reachable within one
+/// hop. The local peer is never included.
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn reachable(
+    storage: &ReadOnly,
+    urn: &Urn,
+    hops: usize,
+) -> Result<BTreeMap<PeerId, Vec<PeerId>>, Error> {
+    let local_peer = *storage.peer_id();
+    let mut paths = BTreeMap::new();
+
+    // Breadth-first, so the first path found to a peer is the shortest
+    let mut queue = tracked_peers(storage, urn)?
+        .map(|peer| (vec![peer], Remotes::new()))
+        .collect::<VecDeque<_>>();
+    while let Some((path, published)) = queue.pop_front() {
+        if path.len() > hops {
+            break;
+        }
+        let peer = *path.last().expect("paths are never empty");
+        if peer == local_peer || paths.contains_key(&peer) {
+            continue;
+        }
+        paths.insert(peer, path.clone());
+
+        let remotes = match load(storage, urn, peer) {
+            Some(refs) => refs.remotes,
+            None => published,
+        };
+        for (next, remotes) in remotes.iter() {
+            let mut path = path.clone();
+            path.push(*next);
+            queue.push_back((path, (**remotes).clone()));
+        }
+    }
+
+    Ok(paths)
+}
+
+/// The shortest path by which `peer` is reachable within `hops` in the
+/// tracking graph of `urn`, if any.
+///
+/// See [`reachable`].
+pub fn path(
+    storage: &ReadOnly,
+    urn: &Urn,
+    peer: PeerId,
+    hops: usize,
+) -> Result<Option<Vec<PeerId>>, Error> {
+    Ok(reachable(storage, urn, hops)?.remove(&peer))
+}
+
+/// The 1st degree tracked peers which track `peer` in the context of `urn`,
+/// according to their `rad/signed_refs`.
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn trackers(storage: &ReadOnly, urn: &Urn, peer: PeerId) -> Result<BTreeSet<PeerId>, Error> {
+    let mut trackers = BTreeSet::new();
+    for tracked in tracked_peers(storage, urn)? {
+        if let Some(refs) = load(storage, urn, tracked) {
+            if refs.remotes.contains_key(&peer) {
+                trackers.insert(tracked);
+            }
+        }
+    }
+
+    Ok(trackers)
+}
+
+/// Load the [`Refs`] of `peer`, skipping them if they fail to load.
+fn load(storage: &ReadOnly, urn: &Urn, peer: PeerId) -> Option<Refs> {
+    Refs::load(storage, urn, peer).unwrap_or_else(|e| {
+        tracing::warn!(peer = %peer, err = %e, "invalid signed refs");
+        None
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        git::{
+            storage::Storage,
+            tracking::track,
+            types::{Force, Namespace, Reference},
+        },
+        keys::SecretKey,
+        paths::Paths,
+    };
+
+    /// Store `remotes` as the `rad/signed_refs` of the peer of `key`, signed
+    /// by `signer`, as if they had been fetched.
+    fn publish(
+        storage: &Storage,
+        urn: &Urn,
+        key: &SecretKey,
+        signer: &SecretKey,
+        remotes: Remotes<PeerId>,
+    ) {
+        let signed = Refs {
+            heads: BTreeMap::new(),
+            rad: BTreeMap::new(),
+            tags: BTreeMap::new(),
+            notes: BTreeMap::new(),
+            remotes,
+            seq: 0,
+        }
+        .sign(signer)
+        .unwrap();
+
+        let repo = storage.as_raw();
+        let blob = repo.blob(&serde_json::to_vec(&signed).unwrap()).unwrap();
+        let tree = {
+            let mut builder = repo.treebuilder(None).unwrap();
+            builder.insert("refs", blob, 0o100_644).unwrap();
+            repo.find_tree(builder.write().unwrap()).unwrap()
+        };
+        let author = repo.signature().unwrap();
+        let commit = repo
+            .commit(None, &author, &author, "signed refs", &tree, &[])
+            .unwrap();
+        Reference::rad_signed_refs(Namespace::from(urn), PeerId::from(key.clone()))
+            .create(repo, commit, Force::False, "published")
+            .unwrap();
+    }
+
+    fn remotes(peers: Vec<(PeerId, Remotes<PeerId>)>) -> Remotes<PeerId> {
+        let mut remotes = Remotes::new();
+        for (peer, tracked) in peers {
+            remotes.insert(peer, Box::new(tracked));
+        }
+        remotes
+    }
+
+    #[test]
+    fn reachable_without_signed_refs() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(&tmp).unwrap();
+            let storage = Storage::open(&paths, SecretKey::new()).unwrap();
+            let alice = PeerId::from(SecretKey::new());
+            let bob = PeerId::from(SecretKey::new());
+            let urn = Urn::new(git2::Oid::zero().into());
+
+            track(&storage, &urn, alice).unwrap();
+            track(&storage, &urn, bob).unwrap();
+
+            assert!(reachable(&storage, &urn, 0).unwrap().is_empty());
+            let reached = reachable(&storage, &urn, 1).unwrap();
+            assert_eq!(reached.get(&alice), Some(&vec![alice]));
+            assert_eq!(reached.get(&bob), Some(&vec![bob]));
+            assert_eq!(
+                path(&storage, &urn, PeerId::from(SecretKey::new()), 3).unwrap(),
+                None
+            );
+            assert!(trackers(&storage, &urn, alice).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn reachable_through_signed_refs() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(&tmp).unwrap();
+            let storage = Storage::open(&paths, SecretKey::new()).unwrap();
+            let keys = (0..5).map(|_| SecretKey::new()).collect::<Vec<_>>();
+            let peers = keys.iter().cloned().map(PeerId::from).collect::<Vec<_>>();
+            let (alice, bob, carol, dave, eve) = (peers[0], peers[1], peers[2], peers[3], peers[4]);
+            let urn = Urn::new(git2::Oid::zero().into());
+
+            // alice -> carol -> {dave, eve}, and bob -> dave
+            track(&storage, &urn, alice).unwrap();
+            track(&storage, &urn, bob).unwrap();
+            publish(
+                &storage,
+                &urn,
+                &keys[0],
+                &keys[0],
+                remotes(vec![(carol, Remotes::new())]),
+            );
+            publish(
+                &storage,
+                &urn,
+                &keys[1],
+                &keys[1],
+                remotes(vec![(dave, Remotes::new())]),
+            );
+            publish(
+                &storage,
+                &urn,
+                &keys[2],
+                &keys[2],
+                remotes(vec![(dave, Remotes::new()), (eve, Remotes::new())]),
+            );
+
+            let reached = reachable(&storage, &urn, 1).unwrap();
+            assert_eq!(
+                reached.keys().copied().collect::<BTreeSet<_>>(),
+                vec![alice, bob].into_iter().collect()
+            );
+
+            let reached = reachable(&storage, &urn, 2).unwrap();
+            assert_eq!(reached.get(&carol), Some(&vec![alice, carol]));
+            assert_eq!(reached.get(&dave), Some(&vec![bob, dave]));
+            assert_eq!(reached.get(&eve), None);
+
+            // The shortest path to dave wins over the one through carol
+            let reached = reachable(&storage, &urn, 3).unwrap();
+            assert_eq!(reached.get(&dave), Some(&vec![bob, dave]));
+            assert_eq!(reached.get(&eve), Some(&vec![alice, carol, eve]));
+            assert_eq!(
+                path(&storage, &urn, eve, 3).unwrap(),
+                Some(vec![alice, carol, eve])
+            );
+
+            assert_eq!(
+                trackers(&storage, &urn, carol).unwrap(),
+                vec![alice].into_iter().collect()
+            );
+            assert_eq!(
+                trackers(&storage, &urn, dave).unwrap(),
+                vec![bob].into_iter().collect()
+            );
+        }
+    }
+
+    #[test]
+    fn skip_invalid_signed_refs() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(&tmp).unwrap();
+            let storage = Storage::open(&paths, SecretKey::new()).unwrap();
+            let alice_key = SecretKey::new();
+            let bob_key = SecretKey::new();
+            let alice = PeerId::from(alice_key.clone());
+            let bob = PeerId::from(bob_key.clone());
+            let carol = PeerId::from(SecretKey::new());
+            let urn = Urn::new(git2::Oid::zero().into());
+
+            track(&storage, &urn, alice).unwrap();
+            track(&storage, &urn, bob).unwrap();
+            // Not signed by alice
+            publish(
+                &storage,
+                &urn,
+                &alice_key,
+                &SecretKey::new(),
+                remotes(vec![(carol, Remotes::new())]),
+            );
+            publish(
+                &storage,
+                &urn,
+                &bob_key,
+                &bob_key,
+                remotes(vec![(carol, Remotes::new())]),
+            );
+
+            assert_eq!(
+                path(&storage, &urn, carol, 2).unwrap(),
+                Some(vec![bob, carol])
+            );
+            assert_eq!(
+                trackers(&storage, &urn, carol).unwrap(),
+                vec![bob].into_iter().collect()
+            );
+        }
+    }
+}
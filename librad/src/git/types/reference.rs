@@ -235,6 +235,23 @@ impl<N, R> Reference<N, R, One> {
         }
     }
 
+    /// Build a reference that points to:
+    ///     * `refs/namespaces/<namespace>/refs/rad/issues/<id>`
+    ///     * `refs/namespaces/<namespace>/refs/remotes/<peer_id>/rad/issues/
+    ///       <id>`
+    pub fn rad_issue(
+        namespace: impl Into<Option<N>>,
+        remote: impl Into<Option<R>>,
+        id: &ext::Oid,
+    ) -> Self {
+        Self {
+            remote: remote.into(),
+            category: RefsCategory::Rad,
+            name: reflike!("issues").join(id),
+            namespace: namespace.into(),
+        }
+    }
+
     /// Build a reference that points to:
     ///     * `refs/namespaces/<namespace>/refs/rad/signed_refs`
     ///     * `refs/namespaces/<namespace>/refs/remote/<peer_id>/rad/
@@ -383,6 +400,18 @@ impl<N, R> Reference<N, R, Many> {
         }
     }
 
+    /// Build a reference that points to:
+    ///     * `refs[/namespaces/<namespace>/refs][/remotes/<remote>]/rad/issues/
+    ///       *`
+    pub fn rad_issues(namespace: impl Into<Option<N>>, remote: impl Into<Option<R>>) -> Self {
+        Self {
+            remote: remote.into(),
+            category: RefsCategory::Rad,
+            name: refspec_pattern!("issues/*"),
+            namespace: namespace.into(),
+        }
+    }
+
     /// Build a reference that points to:
     ///     * `refs[/namespaces/<namespace>/refs][/remotes/<remote>]/heads/*`
     pub fn heads(namespace: impl Into<Option<N>>, remote: impl Into<Option<R>>) -> Self {
@@ -111,13 +111,24 @@ pub struct Peer<S> {
     phone: protocol::TinCans,
     peer_store: PeerStorage,
     git_store: git::storage::Pool,
+    read_only_store: git::storage::ReadOnlyPool,
 }
 
 impl<S> Peer<S>
 where
     S: Signer + Clone,
 {
-    pub fn new(config: Config<S>) -> Self {
+    pub fn new(mut config: Config<S>) -> Self {
+        // Run the executable hooks in the well-known location, unless
+        // configured otherwise
+        let hooks_dir = config.protocol.paths.hooks_dir().to_path_buf();
+        config
+            .protocol
+            .replication
+            .hooks
+            .dir
+            .get_or_insert(hooks_dir);
+
         let phone = protocol::TinCans::default();
         let fetchers = Fetchers::default();
         let peer_store = PeerStorage::new(
@@ -130,7 +141,7 @@ where
                 config.storage.protocol.pool_size,
             ),
             storage::Config {
-                replication: config.protocol.replication,
+                replication: config.protocol.replication.clone(),
                 fetch_slot_wait_timeout: config.storage.protocol.fetch_slot_wait_timeout,
             },
         );
@@ -142,12 +153,17 @@ where
             ),
             config.storage.user.pool_size,
         );
+        let read_only_store = git::storage::ReadOnlyPool::new(
+            git::storage::pool::ReadOnlyConfig::new(config.protocol.paths.clone()),
+            config.storage.user.pool_size,
+        );
 
         Self {
             config,
             phone,
             peer_store,
             git_store,
+            read_only_store,
         }
     }
 
@@ -240,6 +256,17 @@ where
         self.phone.subscribe()
     }
 
+    /// Subscribe to changes to the storage, see [`git::storage::events`].
+    ///
+    /// Changes are reported regardless of whether they were made by this peer
+    /// or by other users of the same storage within the process.
+    pub fn storage_events(
+        &self,
+    ) -> impl futures::Stream<Item = Result<git::storage::events::Event, git::storage::events::RecvError>>
+    {
+        git::storage::events::subscribe(&self.config.protocol.paths)
+    }
+
     /// Borrow a [`git::storage::Storage`] from the pool, and run a blocking
     /// computation on it.
     pub async fn using_storage<F, A>(&self, blocking: F) -> Result<A, StorageError>
@@ -248,18 +275,21 @@ where
         A: Send + 'static,
     {
         let storage = self.git_store.get().await?;
-        match spawn_blocking(move || blocking(&storage)).await {
-            Ok(a) => Ok(a),
-            Err(e) => {
-                if e.is_cancelled() {
-                    Err(StorageError::Cancelled)
-                } else if e.is_panic() {
-                    panic::resume_unwind(e.into_panic())
-                } else {
-                    panic!("unknown error awaiting spawned blocking task: {:?}", e)
-                }
-            },
-        }
+        join_blocking(spawn_blocking(move || blocking(&storage)).await)
+    }
+
+    /// Borrow a [`git::storage::ReadOnly`] from the pool, and run a blocking
+    /// computation on it.
+    ///
+    /// Prefer this over [`Self::using_storage`] for computations which don't
+    /// need to write to the storage.
+    pub async fn using_read_only_storage<F, A>(&self, blocking: F) -> Result<A, StorageError>
+    where
+        F: FnOnce(&git::storage::ReadOnly) -> A + Send + 'static,
+        A: Send + 'static,
+    {
+        let storage = self.read_only_store.get().await?;
+        join_blocking(spawn_blocking(move || blocking(&storage)).await)
     }
 
     /// Borrow a [`git::storage::Storage`] from the pool directly.
@@ -284,6 +314,15 @@ where
             .await
     }
 
+    /// Borrow a [`git::storage::ReadOnly`] from the pool directly.
+    ///
+    /// The same caveats as for [`Self::storage`] apply.
+    pub async fn read_only_storage(
+        &self,
+    ) -> Result<impl AsRef<git::storage::ReadOnly>, PoolError<git::storage::Error>> {
+        self.read_only_store.get().await
+    }
+
     pub async fn bind(&self) -> Result<protocol::Bound<PeerStorage>, protocol::error::Bootstrap> {
         protocol::bind(
             self.phone.clone(),
@@ -295,6 +334,21 @@ where
     }
 }
 
+fn join_blocking<A>(res: Result<A, tokio::task::JoinError>) -> Result<A, StorageError> {
+    match res {
+        Ok(a) => Ok(a),
+        Err(e) => {
+            if e.is_cancelled() {
+                Err(StorageError::Cancelled)
+            } else if e.is_panic() {
+                panic::resume_unwind(e.into_panic())
+            } else {
+                panic!("unknown error awaiting spawned blocking task: {:?}", e)
+            }
+        },
+    }
+}
+
 impl<S> git::local::transport::CanOpenStorage for Peer<S>
 where
     S: Signer + Clone,
@@ -310,4 +364,8 @@ where
             Ok(s) => Ok(Box::new(s)),
         }
     }
+
+    fn hooks(&self) -> git::hooks::Hooks {
+        self.config.protocol.replication.hooks.clone()
+    }
 }
@@ -11,6 +11,8 @@ use tokio::task::spawn_blocking;
 
 use crate::{
     git::{
+        blocklist,
+        refs::Refs,
         replication,
         storage::{self, fetcher, Pool, PoolError, PooledRef},
         tracking,
@@ -24,7 +26,7 @@ use crate::{
 mod error;
 pub use error::Error;
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct Config {
     pub replication: replication::Config,
     pub fetch_slot_wait_timeout: Duration,
@@ -67,13 +69,13 @@ impl Storage {
         }
 
         let (remote_peer, addr_hints) = from.into();
-        let config = self.config;
+        let config = self.config.clone();
         fetcher::retrying(
             self.pool.clone(),
             fetcher::PeerToPeer::new(urn.clone(), remote_peer, addr_hints),
             config.fetch_slot_wait_timeout,
             move |storage, fetcher| {
-                replication::replicate(storage, fetcher, config.replication, None)
+                replication::replicate(storage, fetcher, config.replication.clone(), None)
                     .map_err(Error::from)
             },
         )
@@ -100,10 +102,46 @@ impl Storage {
         .unwrap_or(false)
     }
 
+    async fn is_blocked(&self, peer: PeerId) -> Result<bool, Error> {
+        let git = self.pool.get().await?;
+        Ok(spawn_blocking(move || blocklist::is_blocked(&git, peer)).await??)
+    }
+
     async fn is_tracked(&self, urn: Urn, peer: PeerId) -> Result<bool, Error> {
         let git = self.pool.get().await?;
         Ok(spawn_blocking(move || tracking::is_tracked(&git, &urn, peer)).await??)
     }
+
+    /// Track `peer` in the context of `urn` if `peer` is followed, see
+    /// [`tracking::follow`].
+    ///
+    /// Returns `true` if `peer` is tracked as a result.
+    async fn track_followed(&self, urn: Urn, peer: PeerId) -> Result<bool, Error> {
+        let git = self.pool.get().await?;
+        Ok(spawn_blocking(move || {
+            if tracking::is_followed(&git, peer)? {
+                tracking::track_with(&git, &urn, peer, tracking::Initiator::Follow)?;
+                Ok(true)
+            } else {
+                Ok::<_, tracking::Error>(false)
+            }
+        })
+        .await??)
+    }
+
+    async fn untrack(&self, urn: Urn, peer: PeerId) -> Result<bool, Error> {
+        let git = self.pool.get().await?;
+        Ok(spawn_blocking(move || tracking::untrack(&git, &urn, peer)).await??)
+    }
+
+    /// Determine if the `rad/signed_refs` of `peer` are present, and verify.
+    async fn has_signed_refs(&self, urn: Urn, peer: PeerId) -> Result<bool, Error> {
+        let git = self.pool.get().await?;
+        Ok(
+            spawn_blocking(move || Refs::load(&git, &urn, peer).map(|refs| refs.is_some()))
+                .await??,
+        )
+    }
 }
 
 /// If applicable, map the `path` of the given [`Urn`] to
@@ -157,61 +195,115 @@ impl broadcast::LocalStorage<SocketAddr> for Storage {
         // If the `has` doesn't tell us to look into a specific remote-tracking
         // branch, assume we want the `provider`'s.
         let origin = has.origin.unwrap_or(provider);
-        let is_tracked = match self.is_tracked(has.urn.clone(), origin).await {
-            Ok(b) => b,
+        match self.is_blocked(origin).await {
+            Ok(false) => {},
+            Ok(true) => {
+                tracing::debug!(origin = %origin, "ignoring update of blocked peer");
+                return PutResult::Uninteresting;
+            },
+            Err(e) => {
+                tracing::error!(err = %e, "error determining blocklist status");
+                return PutResult::Error;
+            },
+        }
+        // A followed `origin` is only tracked provisionally: replication
+        // fetches the remotes of tracked peers only, so it must be tracked
+        // for the fetch to pick it up. It is untracked again below unless the
+        // update turns out to be valid.
+        let tracked = match self.is_tracked(has.urn.clone(), origin).await {
+            Ok(false) => self
+                .track_followed(has.urn.clone(), origin)
+                .await
+                .map(|tracked| (tracked, tracked)),
+            res => res.map(|tracked| (tracked, false)),
+        };
+        let (is_tracked, provisional) = match tracked {
+            Ok(tracked) => tracked,
             Err(e) => {
                 tracing::error!(err = %e, "error determining tracking status");
                 return PutResult::Error;
             },
         };
 
-        if is_tracked {
-            let urn = Right(Originates {
-                from: origin,
-                value: has.urn.clone(),
-            });
-            let head = has.rev.as_ref().map(|gossip::Rev::Git(head)| *head);
+        if !is_tracked {
+            return PutResult::Uninteresting;
+        }
 
-            match self
-                .git_fetch((provider, addr_hints), urn.clone(), head)
-                .await
-            {
-                Ok(_) => {
-                    // Verify that the announced data is stored locally now.
-                    //
-                    // If it is, rewrite the gossip message to use the `origin`
-                    // we determined -- everyone down the line may now fetch
-                    // the that remote from us.
-                    //
-                    // Otherwise, the `provider` must be lying -- we are
-                    // tracking them, and there was no error, but the data is
-                    // still not there. In this case, returning `Stale` will
-                    // just terminate the broadcast here.
-                    if self.git_has(urn, head).await {
-                        PutResult::Applied(gossip::Payload {
-                            origin: Some(origin),
-                            ..has
-                        })
-                    } else {
-                        tracing::warn!(
-                            provider = %provider,
-                            announced = ?has,
-                            "provider announced non-existent rev"
-                        );
-                        PutResult::Stale
-                    }
+        let has_urn = has.urn.clone();
+        let urn = Right(Originates {
+            from: origin,
+            value: has.urn.clone(),
+        });
+        let head = has.rev.as_ref().map(|gossip::Rev::Git(head)| *head);
+
+        let result = match self
+            .git_fetch((provider, addr_hints), urn.clone(), head)
+            .await
+        {
+            Ok(_) => {
+                // Verify that the announced data is stored locally now.
+                //
+                // If it is, rewrite the gossip message to use the `origin`
+                // we determined -- everyone down the line may now fetch
+                // the that remote from us.
+                //
+                // Otherwise, the `provider` must be lying -- we are
+                // tracking them, and there was no error, but the data is
+                // still not there. In this case, returning `Stale` will
+                // just terminate the broadcast here.
+                if self.git_has(urn, head).await {
+                    PutResult::Applied(gossip::Payload {
+                        origin: Some(origin),
+                        ..has
+                    })
+                } else {
+                    tracing::warn!(
+                        provider = %provider,
+                        announced = ?has,
+                        "provider announced non-existent rev"
+                    );
+                    PutResult::Stale
+                }
+            },
+
+            Err(e) => match e {
+                Error::KnownObject(_) => PutResult::Stale,
+                x => {
+                    tracing::error!(err = %x, "fetch error");
+                    PutResult::Error
                 },
+            },
+        };
+
+        if !provisional {
+            return result;
+        }
 
-                Err(e) => match e {
-                    Error::KnownObject(_) => PutResult::Stale,
-                    x => {
-                        tracing::error!(err = %x, "fetch error");
-                        PutResult::Error
-                    },
+        // The `origin` is claimed by the `provider`, so only trust it if it
+        // _is_ the `provider`, or if its `rad/signed_refs` verify.
+        let keep = match result {
+            PutResult::Applied(_) if origin == provider => true,
+            PutResult::Applied(_) => match self.has_signed_refs(has_urn.clone(), origin).await {
+                Ok(verified) => verified,
+                Err(e) => {
+                    tracing::warn!(err = %e, origin = %origin, "error verifying signed refs");
+                    false
                 },
-            }
-        } else {
-            PutResult::Uninteresting
+            },
+            _ => false,
+        };
+        if keep {
+            return result;
+        }
+
+        tracing::debug!(origin = %origin, "untracking followed peer");
+        match (self.untrack(has_urn, origin).await, result) {
+            (Err(e), _) => {
+                tracing::error!(err = %e, "error untracking followed peer");
+                PutResult::Error
+            },
+            (Ok(_), PutResult::Applied(_)) => PutResult::Stale,
+            (Ok(_), result) => result,
         }
     }
 
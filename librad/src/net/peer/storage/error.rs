@@ -8,7 +8,7 @@ use std::panic;
 use thiserror::Error;
 use tokio::task::JoinError;
 
-use crate::git::{self, replication, storage::fetcher, tracking};
+use crate::git::{self, blocklist, refs, replication, storage::fetcher, tracking};
 
 #[derive(Debug, Error)]
 #[non_exhaustive]
@@ -19,9 +19,15 @@ pub enum Error {
     #[error(transparent)]
     Tracking(#[from] tracking::Error),
 
+    #[error(transparent)]
+    Blocklist(#[from] blocklist::Error),
+
     #[error(transparent)]
     Replication(#[from] replication::Error),
 
+    #[error(transparent)]
+    Refs(#[from] refs::stored::Error),
+
     #[error("unable to obtain fetcher")]
     Fetcher(#[from] fetcher::error::Retrying<git2::Error>),
 
@@ -20,6 +20,7 @@ use futures::{
 };
 use nonempty::NonEmpty;
 use rand_pcg::Pcg64Mcg;
+use tokio::task::spawn_blocking;
 use tracing::Instrument as _;
 
 use super::{
@@ -73,6 +74,7 @@ pub struct Config {
     pub network: Network,
     pub replication: replication::Config,
     pub fetch: config::Fetch,
+    pub blocklist: config::Blocklist,
     // TODO: transport, ...
 }
 
@@ -91,6 +93,23 @@ pub mod config {
             }
         }
     }
+
+    #[derive(Clone, Copy, Debug)]
+    pub struct Blocklist {
+        /// Whether a peer is considered not blocked if the
+        /// [`crate::git::blocklist`] can't be read.
+        ///
+        /// Defaults to `true`, so that a broken storage doesn't make us reject
+        /// all connections. Set to `false` to reject connections and gossip
+        /// from peers whose blocklist status is unknown instead.
+        pub fail_open: bool,
+    }
+
+    impl Default for Blocklist {
+        fn default() -> Self {
+            Self { fail_open: true }
+        }
+    }
 }
 
 /// Binding of a peer to a network socket.
@@ -156,6 +175,7 @@ where
         config.listen_addr,
         config.advertised_addrs,
         config.network,
+        blocked(config.paths.clone(), config.blocklist),
     )
     .await?;
     let (membership, periodic) = membership::Hpv::<_, SocketAddr>::new(
@@ -187,6 +207,39 @@ where
     })
 }
 
+/// Consult the [`git::blocklist`] of the storage at `paths`.
+///
+/// The storage is accessed on the blocking thread pool. Errors reading the
+/// blocklist are logged, and the peer is considered blocked unless
+/// [`config::Blocklist::fail_open`] is set.
+fn blocked(paths: Paths, config: config::Blocklist) -> quic::Blocked {
+    Arc::new(move |peer: PeerId| {
+        let paths = paths.clone();
+        async move {
+            let blocked = spawn_blocking(move || {
+                let storage = storage::ReadOnly::open(&paths).map_err(|e| e.to_string())?;
+                git::blocklist::is_blocked(&storage, peer).map_err(|e| e.to_string())
+            })
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|blocked| blocked);
+            match blocked {
+                Ok(blocked) => blocked,
+                Err(e) => {
+                    tracing::warn!(
+                        peer = %peer,
+                        err = %e,
+                        fail_open = config.fail_open,
+                        "error reading blocklist"
+                    );
+                    !config.fail_open
+                },
+            }
+        }
+        .boxed()
+    })
+}
+
 #[tracing::instrument(
     skip(phone, state, incoming, periodic, disco),
     fields(peer_id = %state.local_id),
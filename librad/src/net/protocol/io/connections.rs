@@ -60,7 +60,7 @@ where
                         tasks.push(tokio::spawn(streams::incoming(state.clone(), streams).in_current_span()));
                     },
                     Err(err)=> match err {
-                        Connection(_) | PeerId(_) | RemoteIdUnavailable | SelfConnect => {
+                        Connection(_) | PeerId(_) | RemoteIdUnavailable | SelfConnect | Blocked(_) => {
                             tracing::warn!(err = %err, "ingress connections error");
                         },
                         Connect(_) | Endpoint(_) | Io(_) | Shutdown | Signer(_) => {
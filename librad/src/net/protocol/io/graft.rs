@@ -95,7 +95,7 @@ where
             if is_interesting(remote_peer, &remote_heads, &refs.remotes) {
                 tracing::debug!("interesting");
                 Ok(Some(
-                    replication::replicate(&storage, fetcher, config.replication, None)
+                    replication::replicate(&storage, fetcher, config.replication.clone(), None)
                         .map_err(error::Rere::from)?,
                 ))
             } else {
@@ -91,7 +91,7 @@ where
     tokio::spawn({
         let pool = state.storage.clone();
         let config = graft::config::Rere {
-            replication: state.config.replication,
+            replication: state.config.replication.clone(),
             fetch_slot_wait_timeout: state.config.fetch.fetch_slot_wait_timeout,
         };
         let span = tracing::info_span!("rere", urn = %urn, remote_peer = %remote_peer);
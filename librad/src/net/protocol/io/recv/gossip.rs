@@ -35,6 +35,10 @@ pub(in crate::net::protocol) async fn gossip<S, T>(
 {
     let mut recv = FramedRead::new(stream.into_stream(), codec::Gossip::new());
     let remote_id = recv.remote_peer_id();
+    if state.endpoint.is_blocked(remote_id).await {
+        tracing::warn!(remote_id = %remote_id, "dropping gossip from blocked peer");
+        return;
+    }
 
     while let Some(x) = recv.next().await {
         match x {
@@ -25,7 +25,7 @@ use crate::{
 };
 use futures::future::TryFutureExt as _;
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub(super) struct StateConfig {
     pub replication: replication::Config,
     pub fetch: config::Fetch,
@@ -9,7 +9,7 @@ mod connection;
 pub use connection::{BoxedIncomingStreams, Connection, ConnectionId, Conntrack, IncomingStreams};
 
 mod endpoint;
-pub use endpoint::{BoundEndpoint, Endpoint, IncomingConnections};
+pub use endpoint::{Blocked, BoundEndpoint, Endpoint, IncomingConnections};
 
 pub mod error;
 pub use error::{Error, Result};
@@ -9,7 +9,10 @@ use std::{
     sync::Arc,
 };
 
-use futures::stream::{BoxStream, StreamExt as _, TryStreamExt as _};
+use futures::{
+    future::BoxFuture,
+    stream::{BoxStream, StreamExt as _, TryStreamExt as _},
+};
 use nonempty::NonEmpty;
 use pnet_datalink::interfaces as network_interfaces;
 use quinn::{NewConnection, TransportConfig};
@@ -29,6 +32,12 @@ use crate::{
 
 pub type IncomingConnections<'a> = BoxStream<'a, Result<(Connection, BoxedIncomingStreams<'a>)>>;
 
+/// Determines if a peer is blocked, see [`Endpoint::bind`].
+///
+/// The returned future is awaited on the executor, so implementations which
+/// need to access storage should do so via `spawn_blocking`.
+pub type Blocked = Arc<dyn Fn(PeerId) -> BoxFuture<'static, bool> + Send + Sync>;
+
 pub struct BoundEndpoint<'a> {
     pub endpoint: Endpoint,
     pub incoming: IncomingConnections<'a>,
@@ -54,15 +63,21 @@ pub struct Endpoint {
     endpoint: quinn::Endpoint,
     advertised_addrs: Option<NonEmpty<SocketAddr>>,
     conntrack: Conntrack,
+    blocked: Blocked,
     refcount: Arc<()>,
 }
 
 impl Endpoint {
+    /// Bind to `listen_addr`.
+    ///
+    /// Incoming connections from peers for which `blocked` returns `true` are
+    /// closed right after the handshake, and yield [`Error::Blocked`].
     pub async fn bind<'a, S>(
         signer: S,
         listen_addr: SocketAddr,
         advertised_addrs: Option<NonEmpty<SocketAddr>>,
         network: Network,
+        blocked: Blocked,
     ) -> Result<BoundEndpoint<'a>>
     where
         S: Signer + Clone + Send + Sync + 'static,
@@ -76,12 +91,14 @@ impl Endpoint {
             endpoint,
             advertised_addrs,
             conntrack: conntrack.clone(),
+            blocked: blocked.clone(),
             refcount: Arc::new(()),
         };
         let incoming = incoming
             .map(Ok)
             .and_then(move |connecting| {
                 let conntrack = conntrack.clone();
+                let blocked = blocked.clone();
                 async move {
                     let conn = connecting.await?;
                     let remote_peer = remote_peer(&conn)?;
@@ -89,6 +106,12 @@ impl Endpoint {
                         remote_peer != peer_id,
                         "self-connections are prevented in the TLS handshake"
                     );
+                    if blocked(remote_peer).await {
+                        let reason = CloseReason::Blocked;
+                        conn.connection
+                            .close((reason as u32).into(), reason.reason_phrase());
+                        return Err(Error::Blocked(remote_peer));
+                    }
                     let (conn, streams) = Connection::new(remote_peer, conntrack.clone(), conn);
                     conntrack.connected(&conn);
 
@@ -143,6 +166,11 @@ impl Endpoint {
         Ok(addrs)
     }
 
+    /// Determine if `peer` is blocked, see [`Endpoint::bind`].
+    pub async fn is_blocked(&self, peer: PeerId) -> bool {
+        (self.blocked)(peer).await
+    }
+
     pub fn connections_total(&self) -> usize {
         self.conntrack.total()
     }
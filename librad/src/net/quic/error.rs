@@ -18,6 +18,9 @@ pub enum Error {
     #[error("connect to self")]
     SelfConnect,
 
+    #[error("connection from blocked peer {0}")]
+    Blocked(peer::PeerId),
+
     #[error("endpoint is shutting down")]
     Shutdown,
 
@@ -21,6 +21,7 @@ pub struct Paths {
     keys_dir: PathBuf,
     git_dir: PathBuf,
     git_includes_dir: PathBuf,
+    hooks_dir: PathBuf,
 }
 
 impl Paths {
@@ -37,6 +38,7 @@ impl Paths {
             keys_dir: config_dir.join("keys"),
             git_dir: data_dir.join("git"),
             git_includes_dir: config_dir.join("git-includes"),
+            hooks_dir: config_dir.join("hooks"),
         }
         .init()
     }
@@ -48,6 +50,7 @@ impl Paths {
             keys_dir: root.join("keys"),
             git_dir: root.join("git"),
             git_includes_dir: root.join("git-includes"),
+            hooks_dir: root.join("hooks"),
         }
         .init()
     }
@@ -64,6 +67,11 @@ impl Paths {
         &self.git_includes_dir
     }
 
+    /// The directory containing hook executables, see [`crate::git::hooks`].
+    pub fn hooks_dir(&self) -> &Path {
+        &self.hooks_dir
+    }
+
     fn all_dirs(&self) -> impl Iterator<Item = &Path> {
         // Nb. this pattern match is here to keep the map consistent with the
         // struct fields
@@ -71,12 +79,14 @@ impl Paths {
             keys_dir,
             git_dir,
             git_includes_dir,
+            hooks_dir,
         } = self;
 
         vec![
             keys_dir.as_path(),
             git_dir.as_path(),
             git_includes_dir.as_path(),
+            hooks_dir.as_path(),
         ]
         .into_iter()
     }
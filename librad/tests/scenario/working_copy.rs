@@ -88,7 +88,7 @@ async fn can_fetch() {
             let urn = proj.project.urn();
             peer2
                 .using_storage(move |store| {
-                    tracking::tracked(&store, &urn)
+                    tracking::tracked_peers(&store, &urn)
                         .unwrap()
                         .map(|peer| {
                             let self_ref = Reference::rad_self(Namespace::from(&urn), peer);
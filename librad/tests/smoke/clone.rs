@@ -57,7 +57,7 @@ async fn not_present() {
 
         contributor.clone_from(maintainer, true).await;
 
-        let cfg = contributor.0.protocol_config().replication;
+        let cfg = contributor.0.protocol_config().replication.clone();
         contributor
             .0
             .using_storage(move |storage| {
@@ -144,7 +144,7 @@ struct Leecher<'a>(&'a RunningTestPeer);
 
 impl Leecher<'_> {
     async fn clone_from(&self, host: Host<'_>, supply_addr_hints: bool) {
-        let cfg = self.0.protocol_config().replication;
+        let cfg = self.0.protocol_config().replication.clone();
         let urn = host.project.project.urn();
         let owner = host.project.owner;
         let host_peer = host.peer.peer_id();
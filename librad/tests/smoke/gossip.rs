@@ -12,13 +12,15 @@ use futures::StreamExt as _;
 use librad::{
     git::{
         local::url::LocalUrl,
+        tracking,
         types::{remote, Fetchspec, Force, Reference, Remote},
         Urn,
     },
     net::{
         peer::Peer,
         protocol::{
-            event::{self, upstream::predicate::gossip_from},
+            broadcast::PutResult,
+            event::{self, upstream::predicate::gossip_from, Upstream},
             gossip::{self, Rev},
         },
     },
@@ -178,6 +180,76 @@ async fn fetches_on_gossip_notify() {
     }
 }
 
+/// Given two connected peers.
+/// Then have peer2 follow peer1.
+/// Then create a project for peer1 and announce it.
+/// Assert that peer2 applies the announcement, and as a result tracks peer1 in
+/// the context of the project and has it in its monorepo.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn tracks_followed_peer_on_gossip() {
+    logging::init();
+
+    let net = testnet::run(config()).await.unwrap();
+    {
+        let peer1 = net.peers().index(0);
+        let peer2 = net.peers().index(1);
+        let peer1_id = peer1.peer_id();
+
+        peer2
+            .using_storage(move |storage| tracking::follow(&storage, peer1_id))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let proj = peer1
+            .using_storage(move |storage| TestProject::create(&storage))
+            .await
+            .unwrap()
+            .unwrap();
+        let urn = proj.project.urn();
+
+        let peer2_events = peer2.subscribe();
+        peer1
+            .announce(gossip::Payload {
+                origin: None,
+                urn: urn.clone().with_path(reflike!("refs/rad/id")),
+                rev: None,
+            })
+            .unwrap();
+
+        let put = event::upstream::expect(
+            peer2_events.boxed(),
+            gossip_from(peer1_id),
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+        match put {
+            Upstream::Gossip(gossip) => match *gossip {
+                event::upstream::Gossip::Put { result, .. } => assert!(
+                    matches!(result, PutResult::Applied(_)),
+                    "expected the announcement to be applied, got {:?}",
+                    result
+                ),
+            },
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        let (tracked, has_urn) = peer2
+            .using_storage(move |storage| -> anyhow::Result<(bool, bool)> {
+                Ok((
+                    tracking::is_tracked(&storage, &urn, peer1_id)?,
+                    storage.has_urn(&urn)?,
+                ))
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(tracked, "expected peer2 to track peer1");
+        assert!(has_urn, "expected peer2 to have the project");
+    }
+}
+
 /// Given that a) a peer 1 holds a given URN and b) that same peer is a seed of
 /// a peer 2, verify that requesting peer 2 for providers for said URN returns
 /// peer 1.
@@ -304,14 +304,14 @@ impl Node {
         let addr_hints = peer_info.seen_addrs.iter().copied().collect::<Vec<_>>();
 
         let result = {
-            let cfg = api.protocol_config().replication;
+            let cfg = api.protocol_config().replication.clone();
             let urn = urn.clone();
             api.using_storage(move |storage| {
                 let fetcher = fetcher::PeerToPeer::new(urn.clone(), peer_id, addr_hints)
                     .build(&storage)
                     .map_err(|e| Error::MkFetcher(e.into()))??;
                 replication::replicate(&storage, fetcher, cfg, None)?;
-                tracking::track(&storage, &urn, peer_id)?;
+                tracking::track_with(&storage, &urn, peer_id, tracking::Initiator::Seed)?;
 
                 Ok::<_, Error>(())
             })
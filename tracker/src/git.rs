@@ -0,0 +1,675 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Persistence of issues in the `librad` [`Storage`].
+//!
+//! Issues belong to a project, and are stored in its namespace, one branch per
+//! issue:
+//!
+//! ```text
+//! refs/namespaces/<project>/refs/rad/issues/<issue id>
+//! ```
+//!
+//! Every commit on such a branch records the [`Log`] of the issue, as seen by
+//! the peer owning the branch, in a versioned JSON blob named `issue`. The
+//! [`IssueId`] is the oid of the first commit.
+//!
+//! The branches are `rad` refs, so they are included in the signed
+//! [`Refs`] of the project, and replicated from tracked peers by
+//! [`librad::git::replication::replicate`]. The copies of remote peers are
+//! found under `refs/remotes/<peer>/rad/issues/<issue id>`, and [`issues`]
+//! merges them into a single [`Log`] per issue.
+//!
+//! Operations are made by the local [`PeerId`], on behalf of the `rad/self` of
+//! the project (see [`author`]).
+
+use std::{collections::BTreeMap, path::Path};
+
+use librad::{
+    git::{
+        identities::local,
+        refs::{self, Refs},
+        storage::{self, transaction, ReadOnly, Storage},
+        tracking,
+        types::{Namespace, Reference},
+        Urn,
+    },
+    git_ext as ext,
+    internal::canonical::{Cjson, CjsonError},
+    peer::PeerId,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    clock::RadClock,
+    ops::{Op, OpId, OpLog},
+    Label,
+    Reaction,
+    Title,
+};
+
+/// The name of the blob holding the log of the issue.
+const BLOB_PATH: &str = "issue";
+
+/// The version of the stored [`Document`] format.
+const VERSION: u8 = 1;
+
+/// The identifier of a stored issue, ie. the oid of the first commit on its
+/// branch.
+pub type IssueId = ext::Oid;
+
+/// The identifier of a comment, ie. the [`OpId`] of the operation which
+/// created it.
+pub type CommentId = OpId<PeerId>;
+
+/// The [`OpLog`] of an issue as stored in the [`Storage`].
+pub type Log = OpLog<IssueId, PeerId, Urn>;
+
+/// An [`crate::Issue`] as obtained from a [`Log`].
+pub type Issue = crate::Issue<IssueId, CommentId, Urn>;
+
+/// A [`crate::Comment`] as obtained from a [`Log`].
+pub type Comment = crate::Comment<CommentId, Urn>;
+
+/// Errors which can occur when storing or loading issues.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// The project has no `rad/self`, so we don't know who the author is.
+    #[error("no rad/self found for {0}")]
+    MissingSelf(Urn),
+    /// The stored log of an issue is not valid.
+    #[error("malformed issue {id}: {reason}")]
+    Malformed {
+        /// The issue.
+        id: IssueId,
+        /// What is wrong with it.
+        reason: &'static str,
+    },
+    /// The stored log of an issue is in a format we don't understand.
+    #[error("issue {id} is stored in unknown version {version}")]
+    UnknownVersion {
+        /// The issue.
+        id: IssueId,
+        /// The version of the stored log.
+        version: u8,
+    },
+    /// Failed to load the `rad/self` of the project.
+    #[error(transparent)]
+    Local(#[from] local::Error),
+    /// Failed to update the signed refs of the project.
+    #[error(transparent)]
+    Refs(#[from] refs::stored::Error),
+    /// Failed to determine the tracked peers of the project.
+    #[error(transparent)]
+    Tracking(#[from] tracking::Error),
+    /// Failed to access the storage.
+    #[error(transparent)]
+    Store(#[from] storage::Error),
+    /// Failed to update the branch of an issue.
+    #[error(transparent)]
+    Transaction(#[from] transaction::Error),
+    /// Failed to decode the log of an issue.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// Failed to encode the log of an issue.
+    #[error(transparent)]
+    Cjson(#[from] CjsonError),
+    /// Some other git error.
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+/// Create a new issue in `project`, authored by the `rad/self` of `project`.
+///
+/// The issue is committed to a new branch, and the signed [`Refs`] of
+/// `project` are updated to include it.
+pub fn create(
+    storage: &Storage,
+    project: &Urn,
+    title: Title,
+    content: String,
+) -> Result<Log, Error> {
+    let author = author(storage, project)?;
+    // The identifier is not part of the stored log, we only learn it once the
+    // log is committed.
+    let log = Log::new(
+        git2::Oid::zero().into(),
+        *storage.peer_id(),
+        author,
+        title,
+        content,
+    );
+    let doc = Document::from(&log);
+    let id = commit(storage, project, &doc, None)?;
+
+    let branch = Reference::rad_issue(Namespace::from(project), None, &id);
+    let mut tx = storage.transaction(format!("Create issue {}", id));
+    tx.create(&branch, *id);
+    tx.commit()?;
+    Refs::update(storage, project)?;
+
+    doc.into_log(id)
+}
+
+/// Record `log` on the local branch of the issue.
+///
+/// If the issue was created by another peer, the local branch is created. The
+/// signed [`Refs`] of `project` are updated accordingly.
+///
+/// `false` is returned if the log did not change since the last call.
+pub fn save(storage: &Storage, project: &Urn, log: &Log) -> Result<bool, Error> {
+    let repo = storage.as_raw();
+    let id = log.identifier();
+    let branch = Reference::rad_issue(Namespace::from(project), None, id);
+    let tip = storage.reference(&branch)?.and_then(|tip| tip.target());
+    let parent = repo.find_commit(tip.unwrap_or(**id))?;
+
+    let doc = Document::from(log);
+    let unchanged = match parent.tree()?.get_path(Path::new(BLOB_PATH)) {
+        Ok(entry) => {
+            entry.id() == git2::Oid::hash_object(git2::ObjectType::Blob, &doc.canonical_form()?)?
+        },
+        Err(_) => false,
+    };
+    let target = match (unchanged, tip) {
+        (true, Some(_)) => return Ok(false),
+        // Start the local branch from the log it was created with
+        (true, None) => parent.id(),
+        (false, _) => *commit(storage, project, &doc, Some(&parent))?,
+    };
+
+    let mut tx = storage.transaction(format!("Update issue {}", id));
+    tx.update(&branch, target, tip.into());
+    tx.commit()?;
+    Refs::update(storage, project)?;
+
+    Ok(true)
+}
+
+/// Load the log of issue `id` of `project` as seen by `peer`, or by the local
+/// peer if `None`.
+///
+/// If there is no such issue, `None` is returned.
+pub fn load(
+    storage: &ReadOnly,
+    project: &Urn,
+    id: &IssueId,
+    peer: Option<PeerId>,
+) -> Result<Option<Log>, Error> {
+    let branch = Reference::rad_issue(Namespace::from(project), peer, id);
+    match storage.reference(&branch)?.and_then(|tip| tip.target()) {
+        None => Ok(None),
+        Some(tip) => read(storage, *id, tip).map(Some),
+    }
+}
+
+/// Load all issues of `project`, merging the logs of the local peer and all
+/// tracked peers.
+///
+/// The local logs are loaded first. If the log of a tracked peer can't be
+/// merged with them, eg. because it has a different operation with the same
+/// [`OpId`], it is skipped with a warning.
+pub fn issues(storage: &ReadOnly, project: &Urn) -> Result<BTreeMap<IssueId, Log>, Error> {
+    let mut issues = BTreeMap::<IssueId, Log>::new();
+    let peers = Some(None)
+        .into_iter()
+        .chain(tracking::tracked_peers(storage, project)?.map(Some));
+    for peer in peers {
+        for branch in storage.references(&Reference::rad_issues(Namespace::from(project), peer))? {
+            let branch = branch?;
+            // Skip anything which isn't named after an issue
+            let id = match branch
+                .name()
+                .and_then(|name| name.rsplit('/').next())
+                .and_then(|id| git2::Oid::from_str(id).ok())
+            {
+                Some(id) => IssueId::from(id),
+                None => continue,
+            };
+            if let Some(tip) = branch.target() {
+                let log = read(storage, id, tip)?;
+                match issues.get_mut(&id) {
+                    None => {
+                        issues.insert(id, log);
+                    },
+                    Some(merged) => {
+                        // `merge` leaves `merged` untouched if it fails
+                        if let Err(err) = merged.merge(log) {
+                            tracing::warn!(
+                                issue = %id,
+                                // The local logs are loaded first, and are never merged
+                                peer = %peer.unwrap_or(*storage.peer_id()),
+                                "skipping conflicting issue log: {}",
+                                err
+                            );
+                        }
+                    },
+                }
+            }
+        }
+    }
+
+    Ok(issues)
+}
+
+/// The author of issues and comments created by the local peer, ie. the
+/// `rad/self` of `project`.
+pub fn author(storage: &Storage, project: &Urn) -> Result<Urn, Error> {
+    local::load(storage, project.clone())?
+        .map(|local| local.urn())
+        .ok_or_else(|| Error::MissingSelf(project.clone()))
+}
+
+fn commit(
+    storage: &Storage,
+    project: &Urn,
+    doc: &Document,
+    parent: Option<&git2::Commit>,
+) -> Result<IssueId, Error> {
+    let repo = storage.as_raw();
+    let blob = repo.blob(&doc.canonical_form()?)?;
+    let tree = {
+        let mut builder = repo.treebuilder(None)?;
+        builder.insert(BLOB_PATH, blob, 0o100_644)?;
+        repo.find_tree(builder.write()?)?
+    };
+    let author = repo.signature()?;
+    let msg = format!("Update issue \"{}\" of {}", doc.title, project);
+    let oid = repo.commit(
+        None,
+        &author,
+        &author,
+        &msg,
+        &tree,
+        &parent.into_iter().collect::<Vec<_>>(),
+    )?;
+
+    Ok(oid.into())
+}
+
+fn read(storage: &ReadOnly, id: IssueId, tip: git2::Oid) -> Result<Log, Error> {
+    let repo = storage.as_raw();
+    let entry = repo
+        .find_commit(tip)?
+        .tree()?
+        .get_path(Path::new(BLOB_PATH))
+        .map_err(|_| Error::Malformed {
+            id,
+            reason: "missing issue blob",
+        })?;
+    let blob = repo.find_blob(entry.id())?;
+    // Check the version first, later versions may not decode as a `Document`
+    let Versioned { version } = serde_json::from_slice(blob.content())?;
+    if version != VERSION {
+        return Err(Error::UnknownVersion { id, version });
+    }
+    let doc: Document = serde_json::from_slice(blob.content())?;
+    doc.into_log(id)
+}
+
+/// The version of a stored [`Document`].
+#[derive(Deserialize)]
+struct Versioned {
+    version: u8,
+}
+
+/// The stored log of an issue.
+#[derive(Serialize, Deserialize)]
+struct Document {
+    version: u8,
+    actor: PeerId,
+    author: Urn,
+    title: String,
+    content: String,
+    timestamp: RadClock,
+    ops: Vec<Entry>,
+}
+
+impl Document {
+    fn canonical_form(&self) -> Result<Vec<u8>, CjsonError> {
+        Cjson(self).canonical_form()
+    }
+
+    fn into_log(self, id: IssueId) -> Result<Log, Error> {
+        let mut log = Log::new_with_timestamp(
+            id,
+            self.actor,
+            self.author,
+            Title::new(self.title),
+            self.content,
+            self.timestamp,
+        );
+        for Entry { clock, actor, op } in self.ops {
+            if clock == 0 || log.insert(OpId { clock, actor }, op.into()) != Ok(true) {
+                return Err(Error::Malformed {
+                    id,
+                    reason: "invalid operation id",
+                });
+            }
+        }
+
+        Ok(log)
+    }
+}
+
+impl From<&Log> for Document {
+    fn from(log: &Log) -> Self {
+        Self {
+            version: VERSION,
+            actor: log.root().actor,
+            author: log.author().clone(),
+            title: log.title().to_string(),
+            content: log.content().clone(),
+            timestamp: *log.timestamp(),
+            ops: log
+                .ops()
+                .map(|(id, op)| Entry {
+                    clock: id.clock,
+                    actor: id.actor,
+                    op: op.into(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A stored [`Op`] along with its [`OpId`].
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    clock: u64,
+    actor: PeerId,
+    op: StoredOp,
+}
+
+/// A stored [`OpId`].
+#[derive(Serialize, Deserialize)]
+struct Id {
+    clock: u64,
+    actor: PeerId,
+}
+
+impl From<&CommentId> for Id {
+    fn from(id: &CommentId) -> Self {
+        Self {
+            clock: id.clock,
+            actor: id.actor,
+        }
+    }
+}
+
+impl From<Id> for CommentId {
+    fn from(Id { clock, actor }: Id) -> Self {
+        Self { clock, actor }
+    }
+}
+
+/// A stored [`Op`].
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum StoredOp {
+    AddLabel {
+        label: String,
+    },
+    RemoveLabel {
+        label: String,
+    },
+    AddAssignee {
+        assignee: Urn,
+    },
+    RemoveAssignee {
+        assignee: Urn,
+    },
+    React {
+        comment: Id,
+        user: Urn,
+        value: String,
+    },
+    Unreact {
+        comment: Id,
+        user: Urn,
+        value: String,
+    },
+    Reply {
+        parent: Option<Id>,
+        author: Urn,
+        content: String,
+        timestamp: RadClock,
+    },
+    Delete {
+        comment: Id,
+    },
+    Edit {
+        comment: Id,
+        content: String,
+    },
+}
+
+impl From<&Op<PeerId, Urn>> for StoredOp {
+    fn from(op: &Op<PeerId, Urn>) -> Self {
+        match op {
+            Op::AddLabel(label) => Self::AddLabel {
+                label: label.to_string(),
+            },
+            Op::RemoveLabel(label) => Self::RemoveLabel {
+                label: label.to_string(),
+            },
+            Op::AddAssignee(assignee) => Self::AddAssignee {
+                assignee: assignee.clone(),
+            },
+            Op::RemoveAssignee(assignee) => Self::RemoveAssignee {
+                assignee: assignee.clone(),
+            },
+            Op::React { comment, reaction } => Self::React {
+                comment: comment.into(),
+                user: reaction.user().clone(),
+                value: reaction.value().clone(),
+            },
+            Op::Unreact { comment, reaction } => Self::Unreact {
+                comment: comment.into(),
+                user: reaction.user().clone(),
+                value: reaction.value().clone(),
+            },
+            Op::Reply {
+                parent,
+                author,
+                content,
+                timestamp,
+            } => Self::Reply {
+                parent: parent.as_ref().map(Id::from),
+                author: author.clone(),
+                content: content.clone(),
+                timestamp: *timestamp,
+            },
+            Op::Delete { comment } => Self::Delete {
+                comment: comment.into(),
+            },
+            Op::Edit { comment, content } => Self::Edit {
+                comment: comment.into(),
+                content: content.clone(),
+            },
+        }
+    }
+}
+
+impl From<StoredOp> for Op<PeerId, Urn> {
+    fn from(op: StoredOp) -> Self {
+        match op {
+            StoredOp::AddLabel { label } => Self::AddLabel(Label::new(label)),
+            StoredOp::RemoveLabel { label } => Self::RemoveLabel(Label::new(label)),
+            StoredOp::AddAssignee { assignee } => Self::AddAssignee(assignee),
+            StoredOp::RemoveAssignee { assignee } => Self::RemoveAssignee(assignee),
+            StoredOp::React {
+                comment,
+                user,
+                value,
+            } => Self::React {
+                comment: comment.into(),
+                reaction: Reaction::new(user, value),
+            },
+            StoredOp::Unreact {
+                comment,
+                user,
+                value,
+            } => Self::Unreact {
+                comment: comment.into(),
+                reaction: Reaction::new(user, value),
+            },
+            StoredOp::Reply {
+                parent,
+                author,
+                content,
+                timestamp,
+            } => Self::Reply {
+                parent: parent.map(CommentId::from),
+                author,
+                content,
+                timestamp,
+            },
+            StoredOp::Delete { comment } => Self::Delete {
+                comment: comment.into(),
+            },
+            StoredOp::Edit { comment, content } => Self::Edit {
+                comment: comment.into(),
+                content,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use librad::{git::types::Force, keys::SecretKey, paths::Paths};
+    use librad_test::rad::identities::TestProject;
+
+    #[test]
+    fn create_save_load() {
+        let tmp = tempfile::tempdir().unwrap();
+        let paths = Paths::from_root(tmp.path()).unwrap();
+        let storage = Storage::open(&paths, SecretKey::new()).unwrap();
+        let proj = TestProject::create(&storage).unwrap();
+        let urn = proj.project.urn();
+        let me = *storage.peer_id();
+        let owner = author(&storage, &urn).unwrap();
+        assert_eq!(owner, proj.owner.urn());
+
+        let mut log = create(
+            &storage,
+            &urn,
+            Title::from("Buggy Boeuf"),
+            String::from("We have bugs in our boeuf"),
+        )
+        .unwrap();
+        assert_eq!(log.issue().author(), &owner);
+
+        let reply = log.reply(
+            me,
+            None,
+            owner.clone(),
+            String::from("How do we find the bugs in our boeuf"),
+        );
+        log.react(
+            me,
+            reply.clone(),
+            Reaction::new(owner.clone(), String::from("surprise")),
+        );
+        let nested = log.reply(me, Some(reply), owner.clone(), String::from("Nevermind"));
+        log.delete(me, nested);
+        log.add_label(me, Label::new(String::from("bug")));
+
+        assert!(save(&storage, &urn, &log).unwrap());
+        assert!(!save(&storage, &urn, &log).unwrap());
+
+        let loaded = load(&storage, &urn, log.identifier(), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(loaded, log);
+
+        let refs = Refs::load(&storage, &urn, None).unwrap().unwrap();
+        assert!(refs
+            .rad
+            .keys()
+            .any(|name| name.as_str() == format!("issues/{}", log.identifier())));
+
+        // Pretend a tracked peer replicated the issue, and made an edit of its own
+        let peer = PeerId::from(SecretKey::new());
+        tracking::track(&storage, &urn, peer).unwrap();
+        let mut theirs = log.clone();
+        theirs.add_assignee(peer, owner);
+        let tip = Reference::rad_issue(Namespace::from(&urn), None, log.identifier())
+            .oid(storage.as_raw())
+            .unwrap();
+        let parent = storage.as_raw().find_commit(tip).unwrap();
+        let tip = commit(&storage, &urn, &Document::from(&theirs), Some(&parent)).unwrap();
+        Reference::rad_issue(Namespace::from(&urn), peer, log.identifier())
+            .create(storage.as_raw(), *tip, Force::False, "replicated")
+            .unwrap();
+        log.remove_label(me, &Label::new(String::from("bug")));
+        save(&storage, &urn, &log).unwrap();
+
+        let all = issues(&storage, &urn).unwrap();
+        assert_eq!(all.len(), 1);
+        let merged = all[log.identifier()].issue();
+        assert!(merged.meta().labels().is_empty());
+        assert!(merged.meta().assignees().contains(&proj.owner.urn()));
+    }
+
+    #[test]
+    fn conflicting_peer_log() {
+        let tmp = tempfile::tempdir().unwrap();
+        let paths = Paths::from_root(tmp.path()).unwrap();
+        let storage = Storage::open(&paths, SecretKey::new()).unwrap();
+        let proj = TestProject::create(&storage).unwrap();
+        let urn = proj.project.urn();
+        let me = *storage.peer_id();
+
+        let mut log = create(&storage, &urn, Title::from("Buggy Boeuf"), String::new()).unwrap();
+        let mut theirs = log.clone();
+        let label = log.add_label(me, Label::new(String::from("bug")));
+        save(&storage, &urn, &log).unwrap();
+
+        // A tracked peer claims we made a different edit
+        let peer = PeerId::from(SecretKey::new());
+        tracking::track(&storage, &urn, peer).unwrap();
+        theirs
+            .insert(label, Op::AddLabel(Label::new(String::from("feature"))))
+            .unwrap();
+        let tip = commit(&storage, &urn, &Document::from(&theirs), None).unwrap();
+        Reference::rad_issue(Namespace::from(&urn), peer, log.identifier())
+            .create(storage.as_raw(), *tip, Force::False, "replicated")
+            .unwrap();
+
+        // Their log is skipped, ours is still loaded
+        let loaded = issues(&storage, &urn).unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded.get(log.identifier()), Some(&log));
+    }
+
+    #[test]
+    fn reject_unknown_version() {
+        let tmp = tempfile::tempdir().unwrap();
+        let paths = Paths::from_root(tmp.path()).unwrap();
+        let storage = Storage::open(&paths, SecretKey::new()).unwrap();
+        let proj = TestProject::create(&storage).unwrap();
+        let urn = proj.project.urn();
+
+        let log = create(&storage, &urn, Title::from("Buggy Boeuf"), String::new()).unwrap();
+        let mut doc = Document::from(&log);
+        doc.version = VERSION + 1;
+        let peer = PeerId::from(SecretKey::new());
+        let tip = commit(&storage, &urn, &doc, None).unwrap();
+        Reference::rad_issue(Namespace::from(&urn), peer, log.identifier())
+            .create(storage.as_raw(), *tip, Force::False, "replicated")
+            .unwrap();
+
+        assert!(matches!(
+            load(&storage, &urn, log.identifier(), Some(peer)),
+            Err(Error::UnknownVersion { version, .. }) if version == VERSION + 1
+        ));
+    }
+}
@@ -15,6 +15,12 @@
 //! allowing us to label for organisation, react for emotions, and assign to
 //! users to help responsibility.
 //!
+//! Concurrent edits of an issue by several peers can be merged using the
+//! operation log in [`ops`].
+//!
+//! Issues can be stored in, and replicated via, the `librad` storage, see
+//! [`git`].
+//!
 //! ```
 //! # use std::error::Error;
 //! #
@@ -84,14 +90,24 @@
 #![deny(missing_docs, unused_import_braces, unused_qualifications, warnings)]
 use std::hash::Hash;
 
+use serde::{de, ser::SerializeStruct as _, Deserialize, Deserializer, Serialize, Serializer};
+
 mod thread;
 pub use thread::{DataState, Error as ThreadError, Finger, Replies, ReplyTo, Thread};
 
 mod metadata;
 pub use metadata::*;
 
+pub mod git;
+
+pub mod ops;
+
 use clock::{Clock, RadClock};
 
+/// The version of the encoding of an [`Issue`], see [`Issue`]'s
+/// [`Serialize`] and [`minicbor::Encode`] instances.
+pub const ENCODING_VERSION: u8 = 0;
+
 /// An [`Issue`] that has been closed. The underlying issue cannot be mutated,
 /// and can we can only access the reference of this issue..
 #[derive(Debug, Clone)]
@@ -118,7 +134,18 @@ impl<Id, Cid, User: Eq + Hash> ClosedIssue<Id, Cid, User> {
 ///
 /// It also contains [`Metadata`] for which we would like to keep track of and
 /// enhance the experience of the conversation.
-#[derive(Debug, Clone)]
+///
+/// # Encoding
+///
+/// In JSON, an `Issue` is an object with the fields `version`, `identifier`,
+/// `author`, `title`, `thread`, `meta` and `timestamp`. In CBOR, it is an array
+/// of these, in that order. The `version` is always [`ENCODING_VERSION`], and
+/// other versions are rejected when decoding. Sets, like the labels or the
+/// reactions to a comment, are encoded in ascending order, so the encoding of
+/// an `Issue` is deterministic, and can be made canonical using
+/// `librad::internal::canonical::Cjson`. Deleted comments are retained as
+/// [`DataState::Dead`].
+#[derive(Debug, Clone, PartialEq)]
 pub struct Issue<Id, Cid, User: Eq + Hash> {
     identifier: Id,
     author: User,
@@ -168,6 +195,16 @@ impl<Id, Cid, User: Eq + Hash> Issue<Id, Cid, User> {
         ClosedIssue(self)
     }
 
+    /// Get a reference to the identifier of this issue.
+    pub fn identifier(&self) -> &Id {
+        &self.identifier
+    }
+
+    /// Get a reference to the time this issue was created at.
+    pub fn timestamp(&self) -> &RadClock {
+        &self.timestamp
+    }
+
     /// Get a reference to the author (`User`) of this issue.
     pub fn author(&self) -> &User {
         &self.author
@@ -198,3 +235,258 @@ impl<Id, Cid, User: Eq + Hash> Issue<Id, Cid, User> {
         &mut self.meta
     }
 }
+
+impl<Id, Cid, User> Serialize for Issue<Id, Cid, User>
+where
+    Id: Serialize,
+    Cid: Serialize,
+    User: Eq + Hash + Ord + Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut issue = serializer.serialize_struct("Issue", 7)?;
+        issue.serialize_field("version", &ENCODING_VERSION)?;
+        issue.serialize_field("identifier", &self.identifier)?;
+        issue.serialize_field("author", &self.author)?;
+        issue.serialize_field("title", &self.title)?;
+        issue.serialize_field("thread", &self.thread)?;
+        issue.serialize_field("meta", &self.meta)?;
+        issue.serialize_field("timestamp", &self.timestamp)?;
+        issue.end()
+    }
+}
+
+impl<'de, Id, Cid, User> Deserialize<'de> for Issue<Id, Cid, User>
+where
+    Id: Deserialize<'de>,
+    Cid: Deserialize<'de>,
+    User: Eq + Hash + Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(bound(
+            deserialize = "Id: Deserialize<'de>, Cid: Deserialize<'de>, User: Deserialize<'de>"
+        ))]
+        struct Versioned<Id, Cid, User: Eq + Hash> {
+            version: u8,
+            identifier: Id,
+            author: User,
+            title: Title,
+            thread: Thread<Comment<Cid, User>>,
+            meta: Metadata<User>,
+            timestamp: RadClock,
+        }
+
+        let issue = Versioned::deserialize(deserializer)?;
+        if issue.version != ENCODING_VERSION {
+            return Err(de::Error::invalid_value(
+                de::Unexpected::Unsigned(issue.version.into()),
+                &"a known version",
+            ));
+        }
+
+        Ok(Issue {
+            identifier: issue.identifier,
+            author: issue.author,
+            title: issue.title,
+            thread: issue.thread,
+            meta: issue.meta,
+            timestamp: issue.timestamp,
+        })
+    }
+}
+
+impl<Id, Cid, User> minicbor::Encode for Issue<Id, Cid, User>
+where
+    Id: minicbor::Encode,
+    Cid: minicbor::Encode,
+    User: Eq + Hash + Ord + minicbor::Encode,
+{
+    fn encode<W: minicbor::encode::Write>(
+        &self,
+        e: &mut minicbor::Encoder<W>,
+    ) -> Result<(), minicbor::encode::Error<W::Error>> {
+        e.array(7)?
+            .u8(ENCODING_VERSION)?
+            .encode(&self.identifier)?
+            .encode(&self.author)?
+            .encode(&self.title)?
+            .encode(&self.thread)?
+            .encode(&self.meta)?
+            .encode(&self.timestamp)?;
+        Ok(())
+    }
+}
+
+impl<'b, Id, Cid, User> minicbor::Decode<'b> for Issue<Id, Cid, User>
+where
+    Id: minicbor::Decode<'b>,
+    Cid: minicbor::Decode<'b>,
+    User: Eq + Hash + minicbor::Decode<'b>,
+{
+    fn decode(d: &mut minicbor::Decoder<'b>) -> Result<Self, minicbor::decode::Error> {
+        decode_array(d, 7, "invalid Issue")?;
+        if d.u8()? != ENCODING_VERSION {
+            return Err(minicbor::decode::Error::Message("unknown Issue version"));
+        }
+
+        Ok(Issue {
+            identifier: d.decode()?,
+            author: d.decode()?,
+            title: d.decode()?,
+            thread: d.decode()?,
+            meta: d.decode()?,
+            timestamp: d.decode()?,
+        })
+    }
+}
+
+/// Expect a definite CBOR array of `len` elements, failing with `msg`
+/// otherwise.
+fn decode_array(
+    d: &mut minicbor::Decoder<'_>,
+    len: u64,
+    msg: &'static str,
+) -> Result<(), minicbor::decode::Error> {
+    match d.array()? {
+        Some(n) if n == len => Ok(()),
+        _ => Err(minicbor::decode::Error::Message(msg)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use librad::internal::canonical::Cjson;
+    use librad_test::roundtrip::{cbor_roundtrip, cjson_roundtrip, json_roundtrip};
+
+    type TestIssue = Issue<u64, u64, String>;
+
+    fn epoch_plus(secs: u64) -> RadClock {
+        RadClock::from(UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    /// An issue with a reaction on the root, a deleted reply, a label and an
+    /// assignee.
+    fn issue() -> TestIssue {
+        let timestamp = epoch_plus(1);
+        let mut issue = Issue::new_with_timestamp(
+            1,
+            0,
+            String::from("kim"),
+            Title::from("Buggy Boeuf"),
+            String::from("bugs"),
+            timestamp,
+        );
+        let thread = issue.thread_mut();
+        thread
+            .view_mut()
+            .unwrap()
+            .get_mut()
+            .react(Reaction::new(String::from("finto"), String::from("eyes")));
+        thread.reply(
+            Comment::new_with_timestamp(1, String::from("finto"), String::from("gone"), timestamp),
+            ReplyTo::Main,
+        );
+        thread.delete().unwrap();
+        thread.navigate_to_root();
+        issue.meta_mut().add_label(Label::new(String::from("bug")));
+        issue.meta_mut().add_assignee(String::from("finto"));
+
+        issue
+    }
+
+    fn roundtrip<A>(a: A)
+    where
+        for<'de> A: Clone
+            + std::fmt::Debug
+            + PartialEq
+            + Serialize
+            + Deserialize<'de>
+            + minicbor::Encode
+            + minicbor::Decode<'de>,
+    {
+        json_roundtrip(a.clone());
+        cjson_roundtrip(a.clone());
+        cbor_roundtrip(a);
+    }
+
+    #[test]
+    fn roundtrip_clock() {
+        roundtrip(epoch_plus(1));
+        roundtrip(RadClock::current_time());
+    }
+
+    #[test]
+    fn roundtrip_data_state() {
+        roundtrip(DataState::Live(String::from("rose")));
+        roundtrip(DataState::Dead(String::from("rose")));
+    }
+
+    #[test]
+    fn roundtrip_components() {
+        let issue = issue();
+        let (root, main_thread) = issue.thread().parts();
+        roundtrip(root.clone());
+        roundtrip(main_thread[0].clone());
+        assert!(matches!(
+            main_thread[0].iter().next(),
+            Some(DataState::Dead(_))
+        ));
+        roundtrip(issue.thread().clone());
+        roundtrip(root.get().clone());
+        roundtrip(issue.meta().clone());
+    }
+
+    #[test]
+    fn roundtrip_issue() {
+        roundtrip(issue());
+
+        // `Thread`'s `PartialEq` ignores the root
+        let issue = issue();
+        let json: TestIssue =
+            serde_json::from_slice(&Cjson(&issue).canonical_form().unwrap()).unwrap();
+        assert_eq!(json.thread().parts(), issue.thread().parts());
+        let cbor: TestIssue = minicbor::decode(&minicbor::to_vec(&issue).unwrap()).unwrap();
+        assert_eq!(cbor.thread().parts(), issue.thread().parts());
+    }
+
+    #[test]
+    fn canonical_json() {
+        let json = Cjson(&issue()).canonical_form().unwrap();
+        assert_eq!(
+            std::str::from_utf8(&json).unwrap(),
+            concat!(
+                r#"{"author":"kim","identifier":1,"#,
+                r#""meta":{"assignees":["finto"],"labels":["bug"]},"#,
+                r#""thread":{"mainThread":[[{"dead":{"author":"finto","content":"gone","#,
+                r#""identifier":1,"reactions":[],"timestamp":{"nanos":0,"secs":1}}}]],"#,
+                r#""root":{"live":{"author":"kim","content":"bugs","identifier":0,"#,
+                r#""reactions":[{"user":"finto","value":"eyes"}],"#,
+                r#""timestamp":{"nanos":0,"secs":1}}}},"#,
+                r#""timestamp":{"nanos":0,"secs":1},"title":"Buggy Boeuf","version":0}"#
+            )
+        );
+    }
+
+    #[test]
+    fn unknown_version() {
+        let mut json = serde_json::to_value(&issue()).unwrap();
+        json["version"] = 1.into();
+        assert!(serde_json::from_value::<TestIssue>(json).is_err());
+
+        let mut cbor = minicbor::to_vec(&issue()).unwrap();
+        // The array header is followed by the version
+        assert_eq!(cbor[1], ENCODING_VERSION);
+        cbor[1] = 1;
+        assert!(minicbor::decode::<TestIssue>(&cbor).is_err());
+    }
+}
@@ -10,12 +10,19 @@ use std::{
     str::FromStr,
 };
 
+use serde::{Deserialize, Serialize, Serializer};
+
 pub mod clock;
 use clock::{Clock, RadClock};
 
 /// The metadata that is related to an issue.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "User: Serialize + Ord",
+    deserialize = "User: Deserialize<'de>"
+))]
 pub struct Metadata<User: Eq + Hash> {
+    #[serde(serialize_with = "sorted")]
     labels: HashSet<Label>,
     assignees: Assignees<User>,
 }
@@ -62,7 +69,7 @@ impl<User: Eq + Hash> Metadata<User> {
 }
 
 /// The title of an issue.
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Title(String);
 
 impl Title {
@@ -98,11 +105,16 @@ impl FromStr for Title {
 /// [`Comment::content`] of the comment, and its [`Comment::reactions`].
 ///
 /// It has a unique identifier (of type `Id`) chosen by the implementor.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "Id: Serialize, User: Serialize + Ord",
+    deserialize = "Id: Deserialize<'de>, User: Deserialize<'de>"
+))]
 pub struct Comment<Id, User: Eq + Hash> {
     identifier: Id,
     author: User,
     content: String,
+    #[serde(serialize_with = "sorted")]
     reactions: HashSet<Reaction<User>>,
     timestamp: RadClock,
 }
@@ -130,16 +142,31 @@ impl<Cid, User: Eq + Hash> Comment<Cid, User> {
         }
     }
 
+    /// Get a reference to the identifier of this comment.
+    pub fn identifier(&self) -> &Cid {
+        &self.identifier
+    }
+
     /// Get a reference to to the author of this comment.
     pub fn author(&self) -> &User {
         &self.author
     }
 
+    /// Get a reference to the time this comment was created at.
+    pub fn timestamp(&self) -> &RadClock {
+        &self.timestamp
+    }
+
     /// Get a reference to to the content of this comment.
     pub fn content(&self) -> &String {
         &self.content
     }
 
+    /// Replace the content of this comment.
+    pub fn edit(&mut self, content: String) {
+        self.content = content
+    }
+
     /// Add a new reaction to the set of reactions on the comment.
     /// Returns `true` if the reaction was new.
     /// Returns `false` if the reaction already existed.
@@ -171,7 +198,7 @@ impl<Cid, User: Eq + Hash> Comment<Cid, User> {
 }
 
 /// A custom label that can be added to an issue.
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Label(String);
 
 impl Label {
@@ -198,8 +225,12 @@ impl FromStr for Label {
 }
 
 /// A collection of users that represent the assigned users of the issue.
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Assignees<User: Eq + Hash>(HashSet<User>);
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "User: Serialize + Ord",
+    deserialize = "User: Deserialize<'de>"
+))]
+pub struct Assignees<User: Eq + Hash>(#[serde(serialize_with = "sorted")] HashSet<User>);
 
 impl<User: Eq + Hash> Assignees<User> {
     /// Create an empty set of assignees.
@@ -237,7 +268,7 @@ impl<User: Eq + Hash> std::ops::Deref for Assignees<User> {
 }
 
 /// A reaction is the pair of a user and a free-form reaction.
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Reaction<User> {
     user: User,
     value: String,
@@ -259,3 +290,180 @@ impl<User> Reaction<User> {
         &self.value
     }
 }
+
+// Encoding
+//
+// Sets are encoded as sequences, sorted so that the encoding is deterministic.
+// In CBOR, structs are encoded as arrays of their fields, in the order of
+// declaration.
+
+fn sorted<T, S>(set: &HashSet<T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Ord + Serialize,
+    S: Serializer,
+{
+    let mut items = set.iter().collect::<Vec<_>>();
+    items.sort();
+    items.serialize(serializer)
+}
+
+fn encode_sorted<T, W>(
+    set: &HashSet<T>,
+    e: &mut minicbor::Encoder<W>,
+) -> Result<(), minicbor::encode::Error<W::Error>>
+where
+    T: Ord + minicbor::Encode,
+    W: minicbor::encode::Write,
+{
+    let mut items = set.iter().collect::<Vec<_>>();
+    items.sort();
+    e.encode(items)?;
+    Ok(())
+}
+
+fn decode_set<'b, T>(d: &mut minicbor::Decoder<'b>) -> Result<HashSet<T>, minicbor::decode::Error>
+where
+    T: Eq + Hash + minicbor::Decode<'b>,
+{
+    Ok(d.decode::<Vec<T>>()?.into_iter().collect())
+}
+
+impl minicbor::Encode for Title {
+    fn encode<W: minicbor::encode::Write>(
+        &self,
+        e: &mut minicbor::Encoder<W>,
+    ) -> Result<(), minicbor::encode::Error<W::Error>> {
+        e.str(&self.0)?;
+        Ok(())
+    }
+}
+
+impl<'b> minicbor::Decode<'b> for Title {
+    fn decode(d: &mut minicbor::Decoder<'b>) -> Result<Self, minicbor::decode::Error> {
+        Ok(Title(d.str()?.to_owned()))
+    }
+}
+
+impl minicbor::Encode for Label {
+    fn encode<W: minicbor::encode::Write>(
+        &self,
+        e: &mut minicbor::Encoder<W>,
+    ) -> Result<(), minicbor::encode::Error<W::Error>> {
+        e.str(&self.0)?;
+        Ok(())
+    }
+}
+
+impl<'b> minicbor::Decode<'b> for Label {
+    fn decode(d: &mut minicbor::Decoder<'b>) -> Result<Self, minicbor::decode::Error> {
+        Ok(Label(d.str()?.to_owned()))
+    }
+}
+
+impl<User: minicbor::Encode> minicbor::Encode for Reaction<User> {
+    fn encode<W: minicbor::encode::Write>(
+        &self,
+        e: &mut minicbor::Encoder<W>,
+    ) -> Result<(), minicbor::encode::Error<W::Error>> {
+        e.array(2)?.encode(&self.user)?.str(&self.value)?;
+        Ok(())
+    }
+}
+
+impl<'b, User: minicbor::Decode<'b>> minicbor::Decode<'b> for Reaction<User> {
+    fn decode(d: &mut minicbor::Decoder<'b>) -> Result<Self, minicbor::decode::Error> {
+        crate::decode_array(d, 2, "invalid Reaction")?;
+        let user = d.decode()?;
+        let value = d.str()?.to_owned();
+        Ok(Reaction { user, value })
+    }
+}
+
+impl<User> minicbor::Encode for Assignees<User>
+where
+    User: Eq + Hash + Ord + minicbor::Encode,
+{
+    fn encode<W: minicbor::encode::Write>(
+        &self,
+        e: &mut minicbor::Encoder<W>,
+    ) -> Result<(), minicbor::encode::Error<W::Error>> {
+        encode_sorted(&self.0, e)
+    }
+}
+
+impl<'b, User> minicbor::Decode<'b> for Assignees<User>
+where
+    User: Eq + Hash + minicbor::Decode<'b>,
+{
+    fn decode(d: &mut minicbor::Decoder<'b>) -> Result<Self, minicbor::decode::Error> {
+        decode_set(d).map(Assignees)
+    }
+}
+
+impl<User> minicbor::Encode for Metadata<User>
+where
+    User: Eq + Hash + Ord + minicbor::Encode,
+{
+    fn encode<W: minicbor::encode::Write>(
+        &self,
+        e: &mut minicbor::Encoder<W>,
+    ) -> Result<(), minicbor::encode::Error<W::Error>> {
+        e.array(2)?;
+        encode_sorted(&self.labels, e)?;
+        e.encode(&self.assignees)?;
+        Ok(())
+    }
+}
+
+impl<'b, User> minicbor::Decode<'b> for Metadata<User>
+where
+    User: Eq + Hash + minicbor::Decode<'b>,
+{
+    fn decode(d: &mut minicbor::Decoder<'b>) -> Result<Self, minicbor::decode::Error> {
+        crate::decode_array(d, 2, "invalid Metadata")?;
+        let labels = decode_set(d)?;
+        let assignees = d.decode()?;
+        Ok(Metadata { labels, assignees })
+    }
+}
+
+impl<Cid, User> minicbor::Encode for Comment<Cid, User>
+where
+    Cid: minicbor::Encode,
+    User: Eq + Hash + Ord + minicbor::Encode,
+{
+    fn encode<W: minicbor::encode::Write>(
+        &self,
+        e: &mut minicbor::Encoder<W>,
+    ) -> Result<(), minicbor::encode::Error<W::Error>> {
+        e.array(5)?
+            .encode(&self.identifier)?
+            .encode(&self.author)?
+            .str(&self.content)?;
+        encode_sorted(&self.reactions, e)?;
+        e.encode(&self.timestamp)?;
+        Ok(())
+    }
+}
+
+impl<'b, Cid, User> minicbor::Decode<'b> for Comment<Cid, User>
+where
+    Cid: minicbor::Decode<'b>,
+    User: Eq + Hash + minicbor::Decode<'b>,
+{
+    fn decode(d: &mut minicbor::Decoder<'b>) -> Result<Self, minicbor::decode::Error> {
+        crate::decode_array(d, 5, "invalid Comment")?;
+        let identifier = d.decode()?;
+        let author = d.decode()?;
+        let content = d.str()?.to_owned();
+        let reactions = decode_set(d)?;
+        let timestamp = d.decode()?;
+        Ok(Comment {
+            identifier,
+            author,
+            content,
+            reactions,
+            timestamp,
+        })
+    }
+}
@@ -31,13 +31,15 @@
 //! # }
 //! ```
 use std::{
+    convert::TryFrom,
     fmt,
     ops::{Div, Mul, Neg, Sub},
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use num_bigint::BigInt;
 pub use num_bigint::Sign;
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
 
 // Rough calculations for the number of seconds in some larger unit
 const SECONDS_IN_MINUTE: u64 = 60;
@@ -217,6 +219,96 @@ impl Clock for RadClock {
     }
 }
 
+impl From<SystemTime> for RadClock {
+    fn from(time: SystemTime) -> Self {
+        RadClock(time)
+    }
+}
+
+impl From<RadClock> for SystemTime {
+    fn from(clock: RadClock) -> Self {
+        clock.0
+    }
+}
+
+/// A [`RadClock`] is encoded as the time since the UNIX epoch, ie.
+/// `{"secs": <u64>, "nanos": <u32>}` in JSON, and `[secs, nanos]` in CBOR.
+/// Points in time before the epoch can't be encoded.
+#[derive(Serialize, Deserialize)]
+struct SinceEpoch {
+    secs: u64,
+    nanos: u32,
+}
+
+impl TryFrom<&RadClock> for SinceEpoch {
+    type Error = &'static str;
+
+    fn try_from(clock: &RadClock) -> Result<Self, Self::Error> {
+        let since = clock
+            .0
+            .duration_since(UNIX_EPOCH)
+            .or(Err("time before the UNIX epoch"))?;
+        Ok(Self {
+            secs: since.as_secs(),
+            nanos: since.subsec_nanos(),
+        })
+    }
+}
+
+impl TryFrom<SinceEpoch> for RadClock {
+    type Error = &'static str;
+
+    fn try_from(SinceEpoch { secs, nanos }: SinceEpoch) -> Result<Self, Self::Error> {
+        if nanos >= 1_000_000_000 {
+            return Err("nanos out of range");
+        }
+        UNIX_EPOCH
+            .checked_add(Duration::new(secs, nanos))
+            .map(RadClock)
+            .ok_or("time out of range")
+    }
+}
+
+impl Serialize for RadClock {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        SinceEpoch::try_from(self)
+            .map_err(<S::Error as ser::Error>::custom)?
+            .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RadClock {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Self::try_from(SinceEpoch::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+impl minicbor::Encode for RadClock {
+    fn encode<W: minicbor::encode::Write>(
+        &self,
+        e: &mut minicbor::Encoder<W>,
+    ) -> Result<(), minicbor::encode::Error<W::Error>> {
+        let since = SinceEpoch::try_from(self).map_err(minicbor::encode::Error::Message)?;
+        e.array(2)?.u64(since.secs)?.u32(since.nanos)?;
+        Ok(())
+    }
+}
+
+impl<'b> minicbor::Decode<'b> for RadClock {
+    fn decode(d: &mut minicbor::Decoder<'b>) -> Result<Self, minicbor::decode::Error> {
+        crate::decode_array(d, 2, "invalid RadClock")?;
+        let secs = d.u64()?;
+        let nanos = d.u32()?;
+        Self::try_from(SinceEpoch { secs, nanos }).map_err(minicbor::decode::Error::Message)
+    }
+}
+
 impl RadClock {
     /// Calculate the [`Elapsed`] time for two `RadClock`s.
     pub fn elapsed(&self, other: &Self) -> Elapsed {
@@ -0,0 +1,813 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Conflict-free merging of concurrent edits to an [`Issue`].
+//!
+//! Instead of mutating an [`Issue`] directly, every peer records its edits as
+//! [`Op`]s in an [`OpLog`]. Each operation is identified by an [`OpId`]: a
+//! Lamport timestamp paired with the actor who made the edit, which gives a
+//! total order of all operations. [`OpLog::merge`] takes the union of the
+//! operations of two logs, and [`OpLog::issue`] replays them in [`OpId`] order.
+//! Since the resulting [`Issue`] only depends on the set of operations, merging
+//! is commutative, associative and idempotent.
+//!
+//! Conflicting operations are resolved as follows:
+//!
+//! * Labels, assignees and reactions: the last add or remove wins.
+//! * Replies are ordered by their [`OpId`], which is also the identifier of the
+//!   [`Comment`] they create.
+//! * Deletions can't be undone, the last edit determines the content.
+//! * Operations referring to a comment which is not known (yet) have no effect.
+//!
+//! ```
+//! use radicle_tracker::{ops::OpLog, Label, Title};
+//!
+//! let mut ours = OpLog::new(
+//!     0,
+//!     "laptop",
+//!     String::from("kim"),
+//!     Title::from("Buggy Boeuf"),
+//!     String::from("We have bugs in our boeuf"),
+//! );
+//! let mut theirs = ours.clone();
+//!
+//! ours.add_label("laptop", Label::new(String::from("bug")));
+//! theirs.remove_label("desktop", &Label::new(String::from("bug")));
+//! theirs.add_assignee("desktop", String::from("finto"));
+//!
+//! let mut left = ours.clone();
+//! left.merge(theirs.clone()).unwrap();
+//! let mut right = theirs;
+//! right.merge(ours).unwrap();
+//!
+//! assert_eq!(left.issue(), right.issue());
+//! ```
+
+use std::{
+    collections::{btree_map, BTreeMap},
+    hash::Hash,
+};
+
+use thiserror::Error;
+
+use crate::{
+    clock::{Clock, RadClock},
+    Comment,
+    Finger,
+    Issue,
+    Label,
+    Reaction,
+    ReplyTo,
+    Thread,
+    Title,
+};
+
+/// Errors which can occur when merging [`OpLog`]s.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// An attempt was made to merge the logs of two different issues.
+    #[error("the logs are of different issues")]
+    Mismatch,
+    /// The logs contain different operations with the same [`OpId`].
+    #[error("the logs contain different operations with the same id")]
+    Conflict,
+    /// The logs are of the same issue, but disagree on its title, content,
+    /// author or creation time.
+    #[error("the logs disagree on the initial state of the issue")]
+    Malformed,
+}
+
+/// The identifier of an [`Op`].
+///
+/// `OpId`s are ordered by their [`OpId::clock`] first, and by their
+/// [`OpId::actor`] second.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OpId<A> {
+    /// The Lamport timestamp of the operation, ie. one more than the greatest
+    /// clock known to the actor when it made the edit.
+    pub clock: u64,
+    /// The actor who made the edit.
+    pub actor: A,
+}
+
+/// An edit of an [`Issue`] recorded in an [`OpLog`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op<A, User> {
+    /// See [`crate::Metadata::add_label`].
+    AddLabel(Label),
+    /// See [`crate::Metadata::remove_label`].
+    RemoveLabel(Label),
+    /// See [`crate::Metadata::add_assignee`].
+    AddAssignee(User),
+    /// See [`crate::Metadata::remove_assignee`].
+    RemoveAssignee(User),
+    /// See [`Comment::react`].
+    React {
+        /// The comment to react to.
+        comment: OpId<A>,
+        /// The reaction.
+        reaction: Reaction<User>,
+    },
+    /// See [`Comment::unreact`].
+    Unreact {
+        /// The comment to remove the reaction from.
+        comment: OpId<A>,
+        /// The reaction.
+        reaction: Reaction<User>,
+    },
+    /// See [`Thread::reply`].
+    Reply {
+        /// The comment to reply to. Replies to the root of the thread, or to
+        /// `None`, are added to the main thread, replies to any other comment
+        /// are added to the reply thread that comment is part of.
+        parent: Option<OpId<A>>,
+        /// The author of the new comment.
+        author: User,
+        /// The content of the new comment.
+        content: String,
+        /// The time the new comment was created at.
+        timestamp: RadClock,
+    },
+    /// See [`Thread::delete`].
+    Delete {
+        /// The comment to delete.
+        comment: OpId<A>,
+    },
+    /// See [`Thread::edit`].
+    Edit {
+        /// The comment to edit.
+        comment: OpId<A>,
+        /// The new content of the comment.
+        content: String,
+    },
+}
+
+/// The log of all [`Op`]s made to an [`Issue`], by all actors.
+///
+/// The log starts out with the initial state of the issue, the root of its
+/// [`Thread`] is identified by the [`OpId`] with clock `0` and the actor who
+/// created the issue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpLog<Id, A, User> {
+    identifier: Id,
+    root: OpId<A>,
+    author: User,
+    title: Title,
+    content: String,
+    timestamp: RadClock,
+    ops: BTreeMap<OpId<A>, Op<A, User>>,
+}
+
+impl<Id, A, User> OpLog<Id, A, User>
+where
+    A: Clone + Ord,
+{
+    /// Start the log of a new issue, created by `actor`.
+    pub fn new(identifier: Id, actor: A, author: User, title: Title, content: String) -> Self {
+        let timestamp = RadClock::current_time();
+        Self::new_with_timestamp(identifier, actor, author, title, content, timestamp)
+    }
+
+    /// Start the log of a new issue, created by `actor`, with a supplied
+    /// `timestamp`.
+    pub fn new_with_timestamp(
+        identifier: Id,
+        actor: A,
+        author: User,
+        title: Title,
+        content: String,
+        timestamp: RadClock,
+    ) -> Self {
+        Self {
+            identifier,
+            root: OpId { clock: 0, actor },
+            author,
+            title,
+            content,
+            timestamp,
+            ops: BTreeMap::new(),
+        }
+    }
+
+    /// Get a reference to the identifier of the issue.
+    pub fn identifier(&self) -> &Id {
+        &self.identifier
+    }
+
+    /// Get a reference to the identifier of the root comment of the issue.
+    pub fn root(&self) -> &OpId<A> {
+        &self.root
+    }
+
+    /// Get a reference to the author of the issue.
+    pub fn author(&self) -> &User {
+        &self.author
+    }
+
+    /// Get a reference to the [`Title`] of the issue.
+    pub fn title(&self) -> &Title {
+        &self.title
+    }
+
+    /// Get a reference to the content of the root comment of the issue.
+    pub fn content(&self) -> &String {
+        &self.content
+    }
+
+    /// Get a reference to the time the issue was created at.
+    pub fn timestamp(&self) -> &RadClock {
+        &self.timestamp
+    }
+
+    /// The greatest clock of any operation in the log.
+    pub fn clock(&self) -> u64 {
+        // `OpId`s are ordered by clock first
+        self.ops
+            .keys()
+            .next_back()
+            .map_or(self.root.clock, |id| id.clock)
+    }
+
+    /// Iterate over the operations in the log, in [`OpId`] order.
+    pub fn ops(&self) -> impl Iterator<Item = (&OpId<A>, &Op<A, User>)> {
+        self.ops.iter()
+    }
+
+    /// Insert an operation made elsewhere, eg. when loading a stored log.
+    ///
+    /// If the same operation is already in the log, `false` is returned.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`Error::Conflict`] if a different operation with the same
+    /// `id` is already in the log.
+    pub fn insert(&mut self, id: OpId<A>, op: Op<A, User>) -> Result<bool, Error>
+    where
+        User: PartialEq,
+    {
+        match self.ops.entry(id) {
+            btree_map::Entry::Occupied(entry) if entry.get() == &op => Ok(false),
+            btree_map::Entry::Occupied(_) => Err(Error::Conflict),
+            btree_map::Entry::Vacant(entry) => {
+                entry.insert(op);
+                Ok(true)
+            },
+        }
+    }
+
+    /// Merge the operations of `other` into `self`.
+    ///
+    /// `self` is left unchanged if merging fails.
+    ///
+    /// # Errors
+    ///
+    /// * [`Error::Mismatch`] if `other` is the log of a different issue.
+    /// * [`Error::Malformed`] if `other` has a different title, content, author
+    ///   or creation time.
+    /// * [`Error::Conflict`] if `other` has a different operation with the same
+    ///   [`OpId`] as `self`.
+    pub fn merge(&mut self, other: Self) -> Result<(), Error>
+    where
+        Id: PartialEq,
+        User: PartialEq,
+    {
+        if self.identifier != other.identifier || self.root != other.root {
+            return Err(Error::Mismatch);
+        }
+        if self.author != other.author
+            || self.title != other.title
+            || self.content != other.content
+            || self.timestamp != other.timestamp
+        {
+            return Err(Error::Malformed);
+        }
+        if other
+            .ops
+            .iter()
+            .any(|(id, op)| self.ops.get(id).map_or(false, |ours| ours != op))
+        {
+            return Err(Error::Conflict);
+        }
+        self.ops.extend(other.ops);
+        Ok(())
+    }
+
+    /// Record `op`, made by `actor`. The [`OpId`] of the operation is
+    /// returned.
+    pub fn record(&mut self, actor: A, op: Op<A, User>) -> OpId<A> {
+        let id = OpId {
+            clock: self.clock() + 1,
+            actor,
+        };
+        self.ops.insert(id.clone(), op);
+        id
+    }
+
+    /// Record [`Op::AddLabel`].
+    pub fn add_label(&mut self, actor: A, label: Label) -> OpId<A> {
+        self.record(actor, Op::AddLabel(label))
+    }
+
+    /// Record [`Op::RemoveLabel`].
+    pub fn remove_label(&mut self, actor: A, label: &Label) -> OpId<A> {
+        self.record(actor, Op::RemoveLabel(label.clone()))
+    }
+
+    /// Record [`Op::AddAssignee`].
+    pub fn add_assignee(&mut self, actor: A, assignee: User) -> OpId<A> {
+        self.record(actor, Op::AddAssignee(assignee))
+    }
+
+    /// Record [`Op::RemoveAssignee`].
+    pub fn remove_assignee(&mut self, actor: A, assignee: User) -> OpId<A> {
+        self.record(actor, Op::RemoveAssignee(assignee))
+    }
+
+    /// Record [`Op::React`].
+    pub fn react(&mut self, actor: A, comment: OpId<A>, reaction: Reaction<User>) -> OpId<A> {
+        self.record(actor, Op::React { comment, reaction })
+    }
+
+    /// Record [`Op::Unreact`].
+    pub fn unreact(&mut self, actor: A, comment: OpId<A>, reaction: Reaction<User>) -> OpId<A> {
+        self.record(actor, Op::Unreact { comment, reaction })
+    }
+
+    /// Record [`Op::Reply`]. The returned [`OpId`] identifies the new comment.
+    pub fn reply(
+        &mut self,
+        actor: A,
+        parent: Option<OpId<A>>,
+        author: User,
+        content: String,
+    ) -> OpId<A> {
+        self.record(
+            actor,
+            Op::Reply {
+                parent,
+                author,
+                content,
+                timestamp: RadClock::current_time(),
+            },
+        )
+    }
+
+    /// Record [`Op::Delete`].
+    pub fn delete(&mut self, actor: A, comment: OpId<A>) -> OpId<A> {
+        self.record(actor, Op::Delete { comment })
+    }
+
+    /// Record [`Op::Edit`].
+    pub fn edit(&mut self, actor: A, comment: OpId<A>, content: String) -> OpId<A> {
+        self.record(actor, Op::Edit { comment, content })
+    }
+
+    /// Replay all operations in the log, and get the resulting [`Issue`].
+    pub fn issue(&self) -> Issue<Id, OpId<A>, User>
+    where
+        Id: Clone,
+        User: Clone + Eq + Hash,
+    {
+        let mut issue = Issue::new_with_timestamp(
+            self.identifier.clone(),
+            self.root.clone(),
+            self.author.clone(),
+            self.title.clone(),
+            self.content.clone(),
+            self.timestamp,
+        );
+        for (id, op) in &self.ops {
+            apply(&mut issue, id, op);
+        }
+        issue.thread_mut().navigate_to_root();
+
+        issue
+    }
+}
+
+fn apply<Id, A, User>(issue: &mut Issue<Id, OpId<A>, User>, id: &OpId<A>, op: &Op<A, User>)
+where
+    A: Clone + Ord,
+    User: Clone + Eq + Hash,
+{
+    match op {
+        Op::AddLabel(label) => {
+            issue.meta_mut().add_label(label.clone());
+        },
+        Op::RemoveLabel(label) => {
+            issue.meta_mut().remove_label(label);
+        },
+        Op::AddAssignee(assignee) => {
+            issue.meta_mut().add_assignee(assignee.clone());
+        },
+        Op::RemoveAssignee(assignee) => {
+            issue.meta_mut().remove_assignee(assignee);
+        },
+        Op::React { comment, reaction } => {
+            let thread = issue.thread_mut();
+            if navigate(thread, comment) {
+                if let Ok(node) = thread.view_mut() {
+                    node.get_mut().react(reaction.clone());
+                }
+            }
+        },
+        Op::Unreact { comment, reaction } => {
+            let thread = issue.thread_mut();
+            if navigate(thread, comment) {
+                if let Ok(node) = thread.view_mut() {
+                    node.get_mut().unreact(reaction);
+                }
+            }
+        },
+        Op::Reply {
+            parent,
+            author,
+            content,
+            timestamp,
+        } => {
+            let comment = Comment::new_with_timestamp(
+                id.clone(),
+                author.clone(),
+                content.clone(),
+                *timestamp,
+            );
+            let thread = issue.thread_mut();
+            let parent = match parent {
+                None => Some(Finger::Root),
+                Some(parent) => locate(thread, parent),
+            };
+            match parent {
+                Some(Finger::Root) => {
+                    thread.navigate_to_root();
+                    thread.reply(comment, ReplyTo::Main);
+                },
+                Some(Finger::Main(main)) | Some(Finger::Thread { main, .. }) => {
+                    if thread.navigate_to(Finger::Main(main)).is_ok() {
+                        thread.reply(comment, ReplyTo::Thread)
+                    }
+                },
+                // Unknown parent
+                None => {},
+            }
+        },
+        Op::Delete { comment } => {
+            let thread = issue.thread_mut();
+            // Deleting the root is not possible, so the result is ignored
+            if navigate(thread, comment) {
+                thread.delete().ok();
+            }
+        },
+        Op::Edit { comment, content } => {
+            let thread = issue.thread_mut();
+            if navigate(thread, comment) {
+                thread.edit(|node| node.edit(content.clone()));
+            }
+        },
+    }
+}
+
+/// Point the finger of `thread` to the comment `id`. Returns `false` if there
+/// is no such comment.
+fn navigate<A, User>(thread: &mut Thread<Comment<OpId<A>, User>>, id: &OpId<A>) -> bool
+where
+    A: Eq,
+    User: Eq + Hash,
+{
+    match locate(thread, id) {
+        Some(finger) => thread.navigate_to(finger).is_ok(),
+        None => false,
+    }
+}
+
+/// Find the comment `id` in `thread`.
+fn locate<A, User>(thread: &Thread<Comment<OpId<A>, User>>, id: &OpId<A>) -> Option<Finger>
+where
+    A: Eq,
+    User: Eq + Hash,
+{
+    let (root, main_thread) = thread.parts();
+    if root.get().identifier() == id {
+        return Some(Finger::Root);
+    }
+    main_thread.iter().enumerate().find_map(|(main, replies)| {
+        replies
+            .iter()
+            .position(|node| node.get().identifier() == id)
+            .map(|reply| match reply {
+                0 => Finger::Main(main),
+                reply => Finger::Thread { main, reply },
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::{Duration, SystemTime};
+
+    use proptest::prelude::*;
+
+    use crate::DataState;
+
+    type Log = OpLog<u8, u8, String>;
+
+    const ACTORS: usize = 3;
+
+    fn genesis() -> Log {
+        OpLog::new(
+            0,
+            0,
+            String::from("kim"),
+            Title::from("Buggy Boeuf"),
+            String::from("We have bugs in our boeuf"),
+        )
+    }
+
+    fn label(n: u8) -> Label {
+        Label::new(format!("label-{}", n))
+    }
+
+    fn user(n: u8) -> String {
+        format!("user-{}", n)
+    }
+
+    /// An edit made by one of the actors, with comments referred to by their
+    /// index in the comments known to the actor.
+    #[derive(Clone, Debug)]
+    enum Action {
+        AddLabel(u8),
+        RemoveLabel(u8),
+        AddAssignee(u8),
+        RemoveAssignee(u8),
+        React(usize, u8),
+        Unreact(usize, u8),
+        Reply(Option<usize>),
+        Delete(usize),
+        Edit(usize),
+        /// Merge the log of another actor.
+        Sync(usize),
+    }
+
+    fn action() -> impl Strategy<Value = Action> {
+        prop_oneof![
+            (0..3u8).prop_map(Action::AddLabel),
+            (0..3u8).prop_map(Action::RemoveLabel),
+            (0..3u8).prop_map(Action::AddAssignee),
+            (0..3u8).prop_map(Action::RemoveAssignee),
+            (any::<usize>(), 0..3u8).prop_map(|(c, r)| Action::React(c, r)),
+            (any::<usize>(), 0..3u8).prop_map(|(c, r)| Action::Unreact(c, r)),
+            proptest::option::of(any::<usize>()).prop_map(Action::Reply),
+            any::<usize>().prop_map(Action::Delete),
+            any::<usize>().prop_map(Action::Edit),
+            (0..ACTORS).prop_map(Action::Sync),
+        ]
+    }
+
+    fn script() -> impl Strategy<Value = Vec<(usize, Action)>> {
+        proptest::collection::vec((0..ACTORS, action()), 0..64)
+    }
+
+    /// Run `script`, yielding the log of each actor.
+    fn run(script: Vec<(usize, Action)>) -> Vec<Log> {
+        let mut replicas = vec![genesis(); ACTORS];
+        for (step, (actor, action)) in script.into_iter().enumerate() {
+            let comments = Some(replicas[actor].root().clone())
+                .into_iter()
+                .chain(
+                    replicas[actor]
+                        .ops()
+                        .filter(|(_, op)| matches!(op, Op::Reply { .. }))
+                        .map(|(id, _)| id.clone()),
+                )
+                .collect::<Vec<_>>();
+            let comment = |i: usize| comments[i % comments.len()].clone();
+            let reaction = |r: u8| Reaction::new(user(actor as u8), format!("reaction-{}", r));
+
+            let a = actor as u8;
+            match action {
+                Action::AddLabel(l) => {
+                    replicas[actor].add_label(a, label(l));
+                },
+                Action::RemoveLabel(l) => {
+                    replicas[actor].remove_label(a, &label(l));
+                },
+                Action::AddAssignee(u) => {
+                    replicas[actor].add_assignee(a, user(u));
+                },
+                Action::RemoveAssignee(u) => {
+                    replicas[actor].remove_assignee(a, user(u));
+                },
+                Action::React(c, r) => {
+                    replicas[actor].react(a, comment(c), reaction(r));
+                },
+                Action::Unreact(c, r) => {
+                    replicas[actor].unreact(a, comment(c), reaction(r));
+                },
+                Action::Reply(parent) => {
+                    replicas[actor].reply(
+                        a,
+                        parent.map(comment),
+                        user(a),
+                        format!("reply {}", step),
+                    );
+                },
+                Action::Delete(c) => {
+                    replicas[actor].delete(a, comment(c));
+                },
+                Action::Edit(c) => {
+                    replicas[actor].edit(a, comment(c), format!("edit {}", step));
+                },
+                Action::Sync(other) => {
+                    let other = replicas[other].clone();
+                    replicas[actor].merge(other).unwrap();
+                },
+            }
+        }
+
+        replicas
+    }
+
+    fn merged(logs: impl IntoIterator<Item = Log>) -> Log {
+        let mut logs = logs.into_iter();
+        let mut acc = logs.next().unwrap();
+        for log in logs {
+            acc.merge(log).unwrap();
+        }
+        acc
+    }
+
+    /// An operation which differs from `op`.
+    fn other_than(op: &Op<u8, String>) -> Op<u8, String> {
+        match op {
+            Op::AddLabel(l) => Op::RemoveLabel(l.clone()),
+            _ => Op::AddLabel(label(0)),
+        }
+    }
+
+    /// [`Issue`]s compare only the main thread, so look at the root comment as
+    /// well.
+    fn assert_same(a: &Log, b: &Log) -> Result<(), TestCaseError> {
+        let (a, b) = (a.issue(), b.issue());
+        prop_assert_eq!(a.thread().parts(), b.thread().parts());
+        prop_assert_eq!(a, b);
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn prop_merge_commutes(script in script()) {
+            let replicas = run(script);
+            for a in &replicas {
+                for b in &replicas {
+                    let mut ab = a.clone();
+                    ab.merge(b.clone()).unwrap();
+                    let mut ba = b.clone();
+                    ba.merge(a.clone()).unwrap();
+                    prop_assert_eq!(&ab, &ba);
+                    assert_same(&ab, &ba)?;
+                }
+            }
+        }
+
+        #[test]
+        fn prop_merge_is_idempotent(script in script()) {
+            for replica in run(script) {
+                let mut twice = replica.clone();
+                twice.merge(replica.clone()).unwrap();
+                assert_same(&twice, &replica)?;
+            }
+        }
+
+        #[test]
+        fn prop_replicas_converge(script in script()) {
+            let replicas = run(script);
+            let forward = merged(replicas.clone());
+            let backward = merged(replicas.clone().into_iter().rev());
+            assert_same(&forward, &backward)?;
+
+            // Merging pairwise first doesn't make a difference either
+            let mut first = replicas[0].clone();
+            first.merge(replicas[1].clone()).unwrap();
+            let mut second = replicas[2].clone();
+            second.merge(first).unwrap();
+            assert_same(&second, &forward)?;
+        }
+
+        #[test]
+        fn prop_colliding_ops_conflict(script in script(), n in any::<usize>()) {
+            let ours = merged(run(script));
+            let ops = ours.ops().collect::<Vec<_>>();
+            prop_assume!(!ops.is_empty());
+            let (id, op) = ops[n % ops.len()];
+
+            let mut theirs = OpLog {
+                ops: BTreeMap::new(),
+                ..ours.clone()
+            };
+            theirs.insert(id.clone(), other_than(op)).unwrap();
+            prop_assert_eq!(theirs.insert(id.clone(), op.clone()), Err(Error::Conflict));
+
+            let mut left = ours.clone();
+            prop_assert_eq!(left.merge(theirs.clone()), Err(Error::Conflict));
+            prop_assert_eq!(&left, &ours);
+            let mut right = theirs.clone();
+            prop_assert_eq!(right.merge(ours), Err(Error::Conflict));
+            prop_assert_eq!(&right, &theirs);
+        }
+
+        #[test]
+        fn prop_differing_headers_are_malformed(script in script(), field in 0..4u8) {
+            let ours = merged(run(script));
+            let mut theirs = ours.clone();
+            match field {
+                0 => theirs.author = user(9),
+                1 => theirs.title = Title::from("Other"),
+                2 => theirs.content = String::new(),
+                _ => {
+                    theirs.timestamp = RadClock::from(
+                        SystemTime::from(theirs.timestamp) + Duration::from_secs(1),
+                    )
+                },
+            }
+
+            let mut left = ours.clone();
+            prop_assert_eq!(left.merge(theirs.clone()), Err(Error::Malformed));
+            prop_assert_eq!(&left, &ours);
+            let mut right = theirs;
+            prop_assert_eq!(right.merge(ours), Err(Error::Malformed));
+        }
+    }
+
+    #[test]
+    fn last_label_edit_wins() {
+        let mut ours = genesis();
+        let mut theirs = ours.clone();
+        ours.add_label(1, label(0));
+        theirs.add_label(2, label(0));
+        theirs.remove_label(2, &label(0));
+
+        ours.merge(theirs).unwrap();
+        assert!(ours.issue().meta().labels().is_empty());
+    }
+
+    #[test]
+    fn deletions_are_final() {
+        let mut ours = genesis();
+        let reply = ours.reply(1, None, user(1), String::from("How do we find the bugs?"));
+        let mut theirs = ours.clone();
+        theirs.edit(2, reply.clone(), String::from("How do we find them?"));
+        theirs.edit(2, reply.clone(), String::from("How do we find the bugs?!"));
+        ours.delete(1, reply);
+
+        ours.merge(theirs).unwrap();
+        let mut thread = ours.issue().thread().clone();
+        thread.next_reply(ReplyTo::Main).unwrap();
+        assert!(matches!(thread.view(), Ok(DataState::Dead(_))));
+    }
+
+    #[test]
+    fn replies_wait_for_their_parent() {
+        let mut ours = genesis();
+        let mut theirs = ours.clone();
+        let parent = ours.reply(1, None, user(1), String::from("finto"));
+        let child = OpId {
+            clock: parent.clock + 1,
+            actor: 2,
+        };
+        theirs
+            .insert(
+                child,
+                Op::Reply {
+                    parent: Some(parent),
+                    author: user(2),
+                    content: String::from("kim"),
+                    timestamp: RadClock::current_time(),
+                },
+            )
+            .unwrap();
+        assert!(theirs.issue().thread().parts().1.is_empty());
+
+        theirs.merge(ours).unwrap();
+        let mut thread = theirs.issue().thread().clone();
+        thread.next_reply(ReplyTo::Main).unwrap();
+        thread.next_reply(ReplyTo::Thread).unwrap();
+        assert_eq!(thread.view().unwrap().get().author(), &user(2));
+    }
+
+    #[test]
+    fn merging_different_issues_fails() {
+        let mut ours = genesis();
+        let theirs = OpLog::new(
+            1,
+            0,
+            String::from("kim"),
+            Title::from("Other"),
+            String::new(),
+        );
+        assert_eq!(ours.merge(theirs), Err(Error::Mismatch));
+    }
+}
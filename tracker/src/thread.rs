@@ -4,12 +4,14 @@
 // Linking Exception. For full terms see the included LICENSE file.
 
 use nonempty::NonEmpty;
+use serde::{de, ser::SerializeStruct as _, Deserialize, Deserializer, Serialize, Serializer};
 use thiserror::Error;
 
 /// The "liveness" status of some data.
 ///
 /// TODO: we may want to consider `Modified`.
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub enum DataState<A> {
     /// The data has been created.
     Live(A),
@@ -219,6 +221,12 @@ impl<A> Thread<A> {
         }
     }
 
+    /// The root item, and the replies on the main thread along with their
+    /// reply threads.
+    pub(crate) fn parts(&self) -> (&DataState<A>, &[Replies<A>]) {
+        (&self.root, &self.main_thread)
+    }
+
     /// Look at the previous reply of the thread. If it's the case that we are
     /// looking at the first reply to an item on the main thread, then we
     /// will point to the main thread item.
@@ -762,6 +770,136 @@ impl<A> Thread<A> {
     }
 }
 
+// Encoding
+//
+// A `DataState` is encoded as `{"live": a}` or `{"dead": a}` in JSON, and as
+// `[0, a]` or `[1, a]` in CBOR. `Replies` are encoded as a non-empty sequence,
+// and a `Thread` as `{"root": .., "mainThread": [..]}` in JSON and
+// `[root, [..]]` in CBOR. The finger of a `Thread` is not encoded, a decoded
+// `Thread` points to its root.
+
+impl<A: minicbor::Encode> minicbor::Encode for DataState<A> {
+    fn encode<W: minicbor::encode::Write>(
+        &self,
+        e: &mut minicbor::Encoder<W>,
+    ) -> Result<(), minicbor::encode::Error<W::Error>> {
+        let tag = match self {
+            Self::Live(_) => 0,
+            Self::Dead(_) => 1,
+        };
+        e.array(2)?.u8(tag)?.encode(self.get())?;
+        Ok(())
+    }
+}
+
+impl<'b, A: minicbor::Decode<'b>> minicbor::Decode<'b> for DataState<A> {
+    fn decode(d: &mut minicbor::Decoder<'b>) -> Result<Self, minicbor::decode::Error> {
+        crate::decode_array(d, 2, "invalid DataState")?;
+        match d.u8()? {
+            0 => Ok(Self::Live(d.decode()?)),
+            1 => Ok(Self::Dead(d.decode()?)),
+            _ => Err(minicbor::decode::Error::Message("unknown DataState")),
+        }
+    }
+}
+
+impl<A: Serialize> Serialize for Replies<A> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de, A: Deserialize<'de>> Deserialize<'de> for Replies<A> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        NonEmpty::from_vec(Vec::deserialize(deserializer)?)
+            .map(Replies)
+            .ok_or_else(|| de::Error::invalid_length(0, &"at least one reply"))
+    }
+}
+
+impl<A: minicbor::Encode> minicbor::Encode for Replies<A> {
+    fn encode<W: minicbor::encode::Write>(
+        &self,
+        e: &mut minicbor::Encoder<W>,
+    ) -> Result<(), minicbor::encode::Error<W::Error>> {
+        e.array(self.len() as u64)?;
+        for reply in self.iter() {
+            e.encode(reply)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'b, A: minicbor::Decode<'b>> minicbor::Decode<'b> for Replies<A> {
+    fn decode(d: &mut minicbor::Decoder<'b>) -> Result<Self, minicbor::decode::Error> {
+        NonEmpty::from_vec(d.decode()?)
+            .map(Replies)
+            .ok_or(minicbor::decode::Error::Message("empty Replies"))
+    }
+}
+
+impl<A: Serialize> Serialize for Thread<A> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut thread = serializer.serialize_struct("Thread", 2)?;
+        thread.serialize_field("root", &self.root)?;
+        thread.serialize_field("mainThread", &self.main_thread)?;
+        thread.end()
+    }
+}
+
+impl<'de, A: Deserialize<'de>> Deserialize<'de> for Thread<A> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Parts<A> {
+            root: DataState<A>,
+            main_thread: Vec<Replies<A>>,
+        }
+
+        let Parts { root, main_thread } = Parts::deserialize(deserializer)?;
+        Ok(Thread {
+            finger: ROOT_FINGER,
+            root,
+            main_thread,
+        })
+    }
+}
+
+impl<A: minicbor::Encode> minicbor::Encode for Thread<A> {
+    fn encode<W: minicbor::encode::Write>(
+        &self,
+        e: &mut minicbor::Encoder<W>,
+    ) -> Result<(), minicbor::encode::Error<W::Error>> {
+        e.array(2)?.encode(&self.root)?.encode(&self.main_thread)?;
+        Ok(())
+    }
+}
+
+impl<'b, A: minicbor::Decode<'b>> minicbor::Decode<'b> for Thread<A> {
+    fn decode(d: &mut minicbor::Decoder<'b>) -> Result<Self, minicbor::decode::Error> {
+        crate::decode_array(d, 2, "invalid Thread")?;
+        let root = d.decode()?;
+        let main_thread = d.decode()?;
+        Ok(Thread {
+            finger: ROOT_FINGER,
+            root,
+            main_thread,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;